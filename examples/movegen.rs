@@ -0,0 +1,42 @@
+// Jack Alpert 2020
+
+// Times legal move generation over a few positions.
+// Run with `cargo run --release --example movegen`
+
+use chess::*;
+use std::time::Instant;
+
+const POSITIONS: [&str; 4] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+];
+
+fn main() {
+    let boards: Vec<Board> = POSITIONS
+        .iter()
+        .map(|fen| Board::from_fen(fen).unwrap())
+        .collect();
+    // Generate the moves of every position two moves deep, several times over
+    let start = Instant::now();
+    let mut num_calls = 0;
+    let mut num_moves = 0;
+    for _ in 0..5 {
+        for board in &boards {
+            for mv in board.legal_moves() {
+                let moves = board.play(mv).legal_moves();
+                num_calls += 1;
+                num_moves += moves.len();
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "Generated {} moves in {} calls to legal_moves: {:.2} ms, {:.1} us per call",
+        num_moves,
+        num_calls,
+        elapsed.as_secs_f64() * 1000.0,
+        elapsed.as_secs_f64() * 1e6 / num_calls as f64
+    );
+}
@@ -0,0 +1,252 @@
+// Jack Alpert 2020
+
+use crate::board::path::*;
+use crate::board::*;
+
+/**
+A set of squares packed into the bits of a u64. The square in row R and column C
+is bit R * 8 + C, so a1 is the lowest bit and h8 the highest.
+*/
+pub type Bitboard = u64;
+
+// Index of LOC's bit in a Bitboard
+pub fn square_index(loc: Square) -> usize {
+    debug_assert!(in_bounds(loc));
+    (loc.0 * 8 + loc.1) as usize
+}
+
+// The square whose bit is INDEX
+pub fn index_square(index: usize) -> Square {
+    ((index / 8) as isize, (index % 8) as isize)
+}
+
+// A Bitboard holding only LOC
+pub fn square_bit(loc: Square) -> Bitboard {
+    1 << square_index(loc)
+}
+
+/** Iterates over the squares in a Bitboard, from a1 to h8 */
+pub struct Squares(pub Bitboard);
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1; // Clear the lowest bit
+        Some(index_square(index))
+    }
+}
+
+// The bits reached from each square by each of OFFSETS, staying on the board
+const fn offset_table(offsets: &[Square]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        let (row, col) = ((index / 8) as isize, (index % 8) as isize);
+        let mut i = 0;
+        while i < offsets.len() {
+            let (to_row, to_col) = (row + offsets[i].0, col + offsets[i].1);
+            if to_row >= 0 && to_row < 8 && to_col >= 0 && to_col < 8 {
+                table[index] |= 1 << (to_row * 8 + to_col);
+            }
+            i += 1;
+        }
+        index += 1;
+    }
+    table
+}
+
+/** Squares a Knight attacks from each square */
+pub const KNIGHT_ATTACKS: [Bitboard; 64] = offset_table(&KNIGHT_JUMPS);
+/** Squares a King attacks from each square */
+pub const KING_ATTACKS: [Bitboard; 64] = offset_table(&KING_STEPS);
+/** Squares a Pawn attacks from each square, indexed by Color */
+pub const PAWN_ATTACKS: [[Bitboard; 64]; 2] =
+    [offset_table(&[(1, -1), (1, 1)]), offset_table(&[(-1, -1), (-1, 1)])];
+
+// Row and column steps of the 8 directions a Queen slides in. The first 4 increase the
+// square index, and each direction is 4 places away from its opposite.
+const STEPS: [Square; 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (1, -1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (-1, 1),
+];
+
+// Every square from each square to the edge of the board in each direction, excluding
+// the starting square. Indexed by direction, then square.
+const RAYS: [[Bitboard; 64]; 8] = {
+    let mut rays = [[0; 64]; 8];
+    let mut dir = 0;
+    while dir < 8 {
+        let mut index = 0;
+        while index < 64 {
+            let (mut row, mut col) = ((index / 8) as isize, (index % 8) as isize);
+            loop {
+                row += STEPS[dir].0;
+                col += STEPS[dir].1;
+                if row < 0 || row >= 8 || col < 0 || col >= 8 {
+                    break;
+                }
+                rays[dir][index] |= 1 << (row * 8 + col);
+            }
+            index += 1;
+        }
+        dir += 1;
+    }
+    rays
+};
+
+// Directions a Rook or Bishop slides in, as indices into STEPS
+const ORTHOGONAL_DIRS: [usize; 4] = [0, 2, 4, 6];
+const DIAGONAL_DIRS: [usize; 4] = [1, 3, 5, 7];
+
+// Squares reached sliding from INDEX in direction DIR, up to and including the first piece in OCCUPIED
+fn ray_attacks(dir: usize, index: usize, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[dir][index];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+    let first = if dir < 4 {
+        blockers.trailing_zeros() as usize
+    } else {
+        63 - blockers.leading_zeros() as usize
+    };
+    ray ^ RAYS[dir][first]
+}
+
+/** Squares a Rook on square INDEX attacks, with pieces on OCCUPIED blocking its way */
+pub fn rook_attacks(index: usize, occupied: Bitboard) -> Bitboard {
+    ORTHOGONAL_DIRS
+        .iter()
+        .fold(0, |attacks, &dir| attacks | ray_attacks(dir, index, occupied))
+}
+
+/** Squares a Bishop on square INDEX attacks, with pieces on OCCUPIED blocking its way */
+pub fn bishop_attacks(index: usize, occupied: Bitboard) -> Bitboard {
+    DIAGONAL_DIRS
+        .iter()
+        .fold(0, |attacks, &dir| attacks | ray_attacks(dir, index, occupied))
+}
+
+/** Squares a Queen on square INDEX attacks, with pieces on OCCUPIED blocking its way */
+pub fn queen_attacks(index: usize, occupied: Bitboard) -> Bitboard {
+    rook_attacks(index, occupied) | bishop_attacks(index, occupied)
+}
+
+/**
+Squares strictly between FROM and TO along a row, column or diagonal.
+Returns 0 if they do not share one.
+*/
+pub fn between(from: Square, to: Square) -> Bitboard {
+    let (from, to) = (square_index(from), square_index(to));
+    (0..8)
+        .find(|&dir| RAYS[dir][from] & (1 << to) != 0)
+        .map_or(0, |dir| RAYS[dir][from] & RAYS[(dir + 4) % 8][to])
+}
+
+impl Board {
+    /** Squares holding a piece of either color */
+    pub fn occupied(&self) -> Bitboard {
+        self.colors[White as usize] | self.colors[Black as usize]
+    }
+    /** Squares holding PLAYER's pieces */
+    pub fn color_bitboard(&self, player: Color) -> Bitboard {
+        self.colors[player as usize]
+    }
+    /** Squares holding PIECE */
+    pub fn piece_bitboard(&self, piece: Piece) -> Bitboard {
+        self.pieces[piece.piece_type() as usize] & self.colors[piece.color() as usize]
+    }
+
+    /** Squares holding a piece of PLAYER that attacks square INDEX, given the pieces on OCCUPIED */
+    pub fn attackers(&self, index: usize, player: Color, occupied: Bitboard) -> Bitboard {
+        let pieces = |piece_type| self.piece_bitboard(Piece(piece_type, player));
+        let diagonal = pieces(Bishop) | pieces(Queen);
+        let orthogonal = pieces(Rook) | pieces(Queen);
+        // A Pawn attacks INDEX from the squares a Pawn of the other color would attack
+        (PAWN_ATTACKS[player.other() as usize][index] & pieces(Pawn))
+            | (KNIGHT_ATTACKS[index] & pieces(Knight))
+            | (KING_ATTACKS[index] & pieces(King))
+            | (bishop_attacks(index, occupied) & diagonal)
+            | (rook_attacks(index, occupied) & orthogonal)
+    }
+
+    /** Returns true if any piece of PLAYER attacks LOC */
+    pub fn is_attacked(&self, loc: Square, player: Color) -> bool {
+        self.attackers(square_index(loc), player, self.occupied()) != 0
+    }
+
+    /**
+    Squares the piece at FROM attacks or may move to, ignoring check and the rules
+    that depend on the rest of the position: Pawn advances, en passant and castling.
+    */
+    pub fn attacks_from(&self, from: Square) -> Bitboard {
+        let index = square_index(from);
+        let occupied = self.occupied();
+        match self.get(from) {
+            None => 0,
+            Some(Piece(Pawn, color)) => PAWN_ATTACKS[color as usize][index],
+            Some(Piece(Knight, _)) => KNIGHT_ATTACKS[index],
+            Some(Piece(King, _)) => KING_ATTACKS[index],
+            Some(Piece(Bishop, _)) => bishop_attacks(index, occupied),
+            Some(Piece(Rook, _)) => rook_attacks(index, occupied),
+            Some(Piece(Queen, _)) => queen_attacks(index, occupied),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_attack_tables() {
+        // Knight in the corner and in the middle
+        assert_eq!(KNIGHT_ATTACKS[0].count_ones(), 2);
+        assert_eq!(KNIGHT_ATTACKS[square_index((3, 3))].count_ones(), 8);
+        assert_eq!(KING_ATTACKS[square_index((0, 4))].count_ones(), 5);
+        // Pawns on the edge attack a single square
+        let a2 = square_index((1, 0));
+        assert_eq!(PAWN_ATTACKS[White as usize][a2], square_bit((2, 1)));
+        assert_eq!(PAWN_ATTACKS[Black as usize][a2], square_bit((0, 1)));
+    }
+    #[test]
+    fn test_sliding_attacks() {
+        let d4 = square_index((3, 3));
+        assert_eq!(rook_attacks(d4, 0).count_ones(), 14);
+        assert_eq!(bishop_attacks(d4, 0).count_ones(), 13);
+        // Blockers are attacked, but nothing behind them
+        let blockers = square_bit((3, 5)) | square_bit((5, 5)) | square_bit((1, 3));
+        let rook = rook_attacks(d4, blockers);
+        assert!(rook & square_bit((3, 5)) != 0);
+        assert!(rook & square_bit((3, 6)) == 0);
+        assert!(rook & square_bit((0, 3)) == 0);
+        assert_eq!(rook.count_ones(), 11);
+        let bishop = bishop_attacks(d4, blockers);
+        assert!(bishop & square_bit((5, 5)) != 0);
+        assert!(bishop & square_bit((6, 6)) == 0);
+    }
+    #[test]
+    fn test_between_and_squares() {
+        assert_eq!(
+            Squares(between((0, 0), (3, 3))).collect::<Vec<Square>>(),
+            vec![(1, 1), (2, 2)]
+        );
+        assert_eq!(between((0, 4), (0, 7)).count_ones(), 2);
+        assert_eq!(between((0, 0), (1, 2)), 0);
+        assert_eq!(between((4, 4), (4, 5)), 0);
+        let board = Board::new();
+        assert_eq!(Squares(board.color_bitboard(Black)).count(), 16);
+        assert!(board.is_attacked((2, 0), White));
+        assert!(!board.is_attacked((4, 0), White));
+    }
+}
@@ -0,0 +1,303 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+use alloc::collections::BTreeMap;
+use core::cmp::Reverse;
+
+/** One move of an opening book, as stored in a Polyglot .bin file */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BookEntry {
+    pub key: u64, // Of the position the move is played in
+    pub raw_move: u16, // The move packed as Polyglot does, see encode_move
+    pub weight: u16, // How often to play the move, relative to the others
+    pub learn: u32, // Unused here, but kept when the book is written back
+}
+
+/** How to pick between the moves a book gives for a position */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BookChoice {
+    Best, // Always the move with the greatest weight
+    Weighted, // At random, in proportion to the weights
+}
+
+/**
+An opening book in the Polyglot format: 16-byte entries of key, move, weight and
+learning data, big-endian and sorted by key. Positions are looked up with KEYS,
+which must be the ones the book was written with: the default keys for books made by
+other tools.
+*/
+#[derive(Debug, Clone)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+    keys: ZobristKeys,
+}
+
+// Polyglot's numbering of the pieces a Pawn may become
+const PROMOTIONS: [PieceType; 4] = [Knight, Bishop, Rook, Queen];
+
+impl Book {
+    /** The size of each entry in a Polyglot file */
+    pub const ENTRY_SIZE: usize = 16;
+
+    /** Read a book from the contents of a Polyglot .bin file */
+    pub fn from_bytes(bytes: &[u8], keys: ZobristKeys) -> Result<Book, ParseError> {
+        if !bytes.len().is_multiple_of(Self::ENTRY_SIZE) {
+            return Err(ParseError::InvalidBook(format!(
+                "A book is made of {}-byte entries, but this one has {} bytes.",
+                Self::ENTRY_SIZE,
+                bytes.len()
+            )));
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks(Self::ENTRY_SIZE)
+            .map(|chunk| {
+                let number = |start: usize, len: usize| {
+                    chunk[start..start + len]
+                        .iter()
+                        .fold(0, |number, &byte| number << 8 | byte as u64)
+                };
+                BookEntry {
+                    key: number(0, 8),
+                    raw_move: number(8, 2) as u16,
+                    weight: number(10, 2) as u16,
+                    learn: number(12, 4) as u32,
+                }
+            })
+            .collect();
+        // Lookups rely on the order, so don't trust the file to be sorted
+        entries.sort_by_key(|entry| (entry.key, Reverse(entry.weight)));
+        Ok(Book { entries, keys })
+    }
+
+    /** Write the book in the Polyglot format */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * Self::ENTRY_SIZE);
+        for entry in self.entries.iter() {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.raw_move.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    /**
+    Build a book from the first MAX_PLY moves of each of GAMES. As in Polyglot, a move
+    scores 2 each time its player went on to win and 1 each time the game was drawn.
+    Unfinished games count as draws. Moves that only ever lost are left out.
+    */
+    pub fn from_games(games: &[Game], keys: ZobristKeys, max_ply: usize) -> Book {
+        let mut scores: BTreeMap<(u64, u16), u64> = BTreeMap::new();
+        for game in games.iter() {
+            let winner = match game.result() {
+                "1-0" => Some(White),
+                "0-1" => Some(Black),
+                _ => None,
+            };
+            for (board, &mv) in game.boards().iter().zip(game.moves()).take(max_ply) {
+                let score = match winner {
+                    Some(color) if color == board.player() => 2,
+                    Some(_) => 0,
+                    None => 1,
+                };
+                *scores
+                    .entry((board.zobrist_key(&keys), encode_move(board, mv)))
+                    .or_insert(0) += score;
+            }
+        }
+        // Scale the scores down if any is too big to store
+        let most = scores.values().copied().max().unwrap_or(0);
+        let scale = |score: u64| {
+            if most > u16::MAX as u64 {
+                (score * u16::MAX as u64 / most).max(1)
+            } else {
+                score
+            }
+        };
+        let mut entries: Vec<BookEntry> = scores
+            .into_iter()
+            .filter(|&(_, score)| score > 0)
+            .map(|((key, raw_move), score)| BookEntry {
+                key,
+                raw_move,
+                weight: scale(score) as u16,
+                learn: 0,
+            })
+            .collect();
+        entries.sort_by_key(|entry| (entry.key, Reverse(entry.weight)));
+        Book { entries, keys }
+    }
+
+    /** The number of entries in the book */
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /**
+    Returns the legal moves the book gives for BOARD and their weights, greatest first.
+    Entries that don't decode to a legal move, e.g. from another position with the same
+    key, are skipped.
+    */
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        let key = board.zobrist_key(&self.keys);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((decode_move(board, entry.raw_move)?, entry.weight)))
+            .collect()
+    }
+
+    /**
+    Pick one of the book's moves for BOARD as CHOICE asks, or None if the book has none.
+    RANDOM is any random number, used by a Weighted choice.
+    */
+    pub fn choose(&self, board: &Board, choice: BookChoice, random: u64) -> Option<Move> {
+        let moves = self.moves(board);
+        let total: u64 = moves.iter().map(|&(_, weight)| weight as u64).sum();
+        match choice {
+            // Moves are sorted by weight, and one of weight 0 is only played if nothing else is
+            BookChoice::Best => moves.first().map(|&(mv, _)| mv),
+            BookChoice::Weighted if total == 0 => moves.first().map(|&(mv, _)| mv),
+            BookChoice::Weighted => {
+                let mut target = random % total;
+                moves.iter().find_map(|&(mv, weight)| {
+                    if target < weight as u64 {
+                        Some(mv)
+                    } else {
+                        target -= weight as u64;
+                        None
+                    }
+                })
+            }
+        }
+    }
+}
+
+/**
+Pack MV, a move on BOARD, as Polyglot does: the column and row of the destination in
+bits 0-5, those of the origin in bits 6-11 and the promotion in bits 12-14.
+Castling is written as the King capturing its own Rook.
+*/
+pub fn encode_move(board: &Board, mv: Move) -> u16 {
+    let to = match board.castle_side(mv.from, mv.to) {
+        Some(side) => board
+            .castling_rook(board.player(), side)
+            .unwrap_or((mv.from.0, side.rook_from())),
+        None => mv.to,
+    };
+    let promotion = mv.promotion.map_or(0, |piece_type| {
+        PROMOTIONS.iter().position(|&p| p == piece_type).unwrap() + 1
+    });
+    (to.1 | to.0 << 3 | mv.from.1 << 6 | mv.from.0 << 9) as u16 | (promotion as u16) << 12
+}
+
+/** Unpack a move packed by encode_move, returning None unless it is legal on BOARD */
+pub fn decode_move(board: &Board, raw_move: u16) -> Option<Move> {
+    let square = |bits: u16| (((bits >> 3) & 7) as isize, (bits & 7) as isize);
+    let (from, mut to) = (square(raw_move >> 6), square(raw_move));
+    let promotion = match (raw_move >> 12) & 7 {
+        0 => None,
+        n => Some(*PROMOTIONS.get(n as usize - 1)?),
+    };
+    // The King taking the Rook it may castle with is castling
+    let player = board.player();
+    if board.get(from) == Some(Piece(King, player)) {
+        for &side in &[KingSide, QueenSide] {
+            if board.castling_rook(player, side) == Some(to) {
+                to = board.castle_target(player, side).unwrap();
+            }
+        }
+    }
+    board
+        .legal_moves()
+        .into_iter()
+        .find(|mv| mv.from == from && mv.to == to && mv.promotion == promotion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n\
+                       [Result \"1/2-1/2\"]\n\n1. e4 c5 2. Nf3 1/2-1/2\n\n\
+                       [Result \"0-1\"]\n\n1. d4 d5 0-1\n";
+
+    #[test]
+    fn test_encode_move() {
+        let board = Board::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let cases = [("e1g1", 0x0107), ("e1c1", 0x0100), ("b7a8q", 0x4c78), ("b7b8n", 0x1c79)];
+        for &(text, raw_move) in cases.iter() {
+            let mv = board.parse_uci(text).unwrap();
+            assert_eq!(encode_move(&board, mv), raw_move, "{}", text);
+            assert_eq!(decode_move(&board, raw_move), Some(mv), "{}", text);
+        }
+        // e1e8 is no move at all, and the Rook on a8 is not White's
+        assert_eq!(decode_move(&board, 0x013c), None);
+        assert_eq!(decode_move(&Board::new(), 0x031c), Some(Board::new().parse_uci("e2e4").unwrap()));
+    }
+    #[test]
+    fn test_polyglot_book() {
+        // Entries as another tool would write them, keyed by the published Polyglot keys
+        let entry = |key: u64, raw_move: u16, weight: u16| {
+            let mut bytes = key.to_be_bytes().to_vec();
+            bytes.extend_from_slice(&raw_move.to_be_bytes());
+            bytes.extend_from_slice(&weight.to_be_bytes());
+            bytes.extend_from_slice(&[0; 4]);
+            bytes
+        };
+        let bytes = [
+            entry(0x463B_9618_1691_FC9C, 0x031C, 10), // 1. e4
+            entry(0x463B_9618_1691_FC9C, 0x02DB, 5),  // 1. d4
+            entry(0x823C_9B50_FD11_4196, 0x0D24, 7),  // 1. e4 e5
+        ]
+        .concat();
+        let book = Book::from_bytes(&bytes, POLYGLOT.clone()).unwrap();
+        let start = Board::new();
+        let (e4, d4) = (start.parse_uci("e2e4").unwrap(), start.parse_uci("d2d4").unwrap());
+        assert_eq!(book.moves(&start), vec![(e4, 10), (d4, 5)]);
+        let after_e4 = start.play(e4);
+        assert_eq!(book.moves(&after_e4), vec![(after_e4.parse_uci("e7e5").unwrap(), 7)]);
+        assert_eq!(book.moves(&start.play(d4)), vec![]);
+    }
+    #[test]
+    fn test_build_book() {
+        let games = Game::parse_pgn_games(PGN).unwrap();
+        let book = Book::from_games(&games, POLYGLOT.clone(), 3);
+        let start = Board::new();
+        let e4 = start.parse_uci("e2e4").unwrap();
+        // e4 won once and drew once, d4 lost
+        assert_eq!(book.moves(&start), vec![(e4, 3)]);
+        let after_e4 = start.play(e4);
+        let (e5, c5) = (after_e4.parse_uci("e7e5").unwrap(), after_e4.parse_uci("c7c5").unwrap());
+        assert_eq!(book.moves(&after_e4), vec![(c5, 1)]);
+        assert!(!book.moves(&after_e4).iter().any(|&(mv, _)| mv == e5));
+        // Only the first 3 moves of each game were added
+        let game = &games[0];
+        assert_eq!(book.moves(&game.boards()[3]), vec![]);
+        assert_eq!(book.len(), 5);
+        // Written out and read back, the book is the same
+        let read = Book::from_bytes(&book.to_bytes(), POLYGLOT.clone()).unwrap();
+        assert_eq!(read.entries, book.entries);
+        assert!(Book::from_bytes(&[0; 17], POLYGLOT.clone()).is_err());
+    }
+    #[test]
+    fn test_choose() {
+        let pgn = "[Result \"1-0\"]\n\n1. e4 1-0\n\n[Result \"1-0\"]\n\n1. d4 1-0\n\n\
+                   [Result \"1-0\"]\n\n1. d4 1-0\n";
+        let book = Book::from_games(&Game::parse_pgn_games(pgn).unwrap(), POLYGLOT.clone(), 1);
+        let start = Board::new();
+        let d4 = start.parse_uci("d2d4").unwrap();
+        assert_eq!(book.choose(&start, BookChoice::Best, 0), Some(d4));
+        // d4 has weight 4 and e4 weight 2
+        let picks: Vec<String> = (0..6)
+            .map(|random| book.choose(&start, BookChoice::Weighted, random).unwrap().to_string())
+            .collect();
+        assert_eq!(picks, vec!["d2d4", "d2d4", "d2d4", "d2d4", "e2e4", "e2e4"]);
+        assert_eq!(book.choose(&start.play(d4), BookChoice::Best, 0), None);
+    }
+}
@@ -0,0 +1,307 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CastleSide {
+    KingSide,
+    QueenSide,
+}
+pub use CastleSide::*;
+
+impl CastleSide {
+    // Column the King lands on after castling
+    pub fn king_to(&self) -> isize {
+        match self {
+            KingSide => 6,
+            QueenSide => 2,
+        }
+    }
+    // Column the Rook starts on before castling
+    pub fn rook_from(&self) -> isize {
+        match self {
+            KingSide => 7,
+            QueenSide => 0,
+        }
+    }
+    // Column the Rook lands on after castling
+    pub fn rook_to(&self) -> isize {
+        match self {
+            KingSide => 5,
+            QueenSide => 3,
+        }
+    }
+}
+
+impl Color {
+    // The row on which this player's King and Rooks begin the game
+    pub fn home_row(&self) -> isize {
+        match self {
+            White => 0,
+            Black => 7,
+        }
+    }
+}
+
+// Column on which both Kings begin the game
+pub const KING_HOME_COL: isize = 4;
+
+impl Board {
+    /** Returns true if PLAYER still holds the right to castle on SIDE */
+    pub fn can_castle(&self, player: Color, side: CastleSide) -> bool {
+        self.castling[player as usize][side as usize].is_some()
+    }
+
+    /** The square of the Rook PLAYER may castle with on SIDE, if they still hold the right */
+    pub fn castling_rook(&self, player: Color, side: CastleSide) -> Option<Square> {
+        self.castling[player as usize][side as usize].map(|col| (player.home_row(), col))
+    }
+
+    /**
+    Returns true if this is a Chess960 game, where the King and Rooks may start on any
+    column and castling is written as the King taking its own Rook, e.g. "e1h1"
+    */
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /** Write castling as the King taking its own Rook, as Chess960 does, or not */
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+    /**
+    The square the King moves to when PLAYER castles on SIDE: the one it lands on, or
+    in Chess960 the Rook's. None if the right has been lost.
+    */
+    pub fn castle_target(&self, player: Color, side: CastleSide) -> Option<Square> {
+        let rook = self.castling_rook(player, side)?;
+        if self.chess960 {
+            Some(rook)
+        } else {
+            Some((rook.0, side.king_to()))
+        }
+    }
+
+    /**
+    Returns the side being castled on if moving FROM -> TO is a King stepping
+    two squares sideways from its home square, or in Chess960 a King on its home
+    row taking its own Rook. Otherwise, returns None.
+    Does not check that the move is legal.
+    */
+    pub fn castle_side(&self, from: Square, to: Square) -> Option<CastleSide> {
+        let player = self.get(from)?.color();
+        if self.get(from) != Some(Piece(King, player))
+            || from.0 != player.home_row()
+            || to.0 != from.0
+        {
+            None
+        } else if self.chess960 {
+            if self.get(to) != Some(Piece(Rook, player)) {
+                None
+            } else if to.1 > from.1 {
+                Some(KingSide)
+            } else {
+                Some(QueenSide)
+            }
+        } else if from.1 != KING_HOME_COL {
+            None
+        } else if to.1 == KingSide.king_to() {
+            Some(KingSide)
+        } else if to.1 == QueenSide.king_to() {
+            Some(QueenSide)
+        } else {
+            None
+        }
+    }
+
+    /**
+    Ensure that PLAYER may castle on SIDE as far as the pieces are concerned:
+    the right has not been lost, the Rook is in place and every square the King
+    and Rook cross or land on is empty but for the two of them.
+    Checking rules are applied in validate_move.
+    */
+    pub fn validate_castle(&self, player: Color, side: CastleSide) -> Result<(), MoveError> {
+        let row = player.home_row();
+        let rook = self
+            .castling_rook(player, side)
+            .ok_or(MoveError::CastlingRightLost(side))?;
+        let king = self.find_king(Some(player)).expect("This player has no King!");
+        if self.get(rook) != Some(Piece(Rook, player)) {
+            return Err(MoveError::CastlingRookMissing(side));
+        }
+        let cols = [king.1, rook.1, side.king_to(), side.rook_to()];
+        let (first, last) = (*cols.iter().min().unwrap(), *cols.iter().max().unwrap());
+        if (first..=last)
+            .map(|col| (row, col))
+            .any(|loc| loc != king && loc != rook && self.get(loc).is_some())
+        {
+            Err(MoveError::CastlingBlocked(side))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Remove any castling rights affected by PIECE moving FROM -> TO.
+    // Moving the King or a Rook, or capturing a Rook on its home square, loses the right.
+    pub(crate) fn update_castling_rights(&mut self, from: Square, to: Square, piece: Piece) {
+        for &player in &[White, Black] {
+            for &side in &[KingSide, QueenSide] {
+                let rook_home = self.castling_rook(player, side);
+                if rook_home.is_some_and(|rook_home| [from, to].contains(&rook_home))
+                    || piece == Piece(King, player)
+                {
+                    self.castling[player as usize][side as usize] = None;
+                }
+            }
+        }
+    }
+
+    // Grant each castling right whose King and Rook are still on their home squares.
+    // Used when loading positions that do not record castling rights.
+    pub(crate) fn infer_castling_rights(&mut self) {
+        for &player in &[White, Black] {
+            let row = player.home_row();
+            for &side in &[KingSide, QueenSide] {
+                let in_place = self.get((row, KING_HOME_COL)) == Some(Piece(King, player))
+                    && self.get((row, side.rook_from())) == Some(Piece(Rook, player));
+                self.castling[player as usize][side as usize] =
+                    Some(side.rook_from()).filter(|_| in_place);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // White King and Rooks on their home squares with full castling rights
+    fn castling_board() -> Board {
+        let mut board = Board::empty(White, 1);
+        board
+            .set((0, 4), Some(Piece(King, White)))
+            .set((0, 0), Some(Piece(Rook, White)))
+            .set((0, 7), Some(Piece(Rook, White)))
+            .set((7, 4), Some(Piece(King, Black)));
+        board.infer_castling_rights();
+        board
+    }
+    #[test]
+    fn test_castle_both_sides() {
+        let board = castling_board();
+        board.validate_move((0, 4), (0, 6), None).unwrap();
+        board.validate_move((0, 4), (0, 2), None).unwrap();
+
+        let king_side = board.execute_move((0, 4), (0, 6), None);
+        assert!(king_side.get((0, 6)) == Some(Piece(King, White)));
+        assert!(king_side.get((0, 5)) == Some(Piece(Rook, White)));
+        assert!(king_side.get((0, 7)).is_none());
+        assert!(!king_side.can_castle(White, KingSide));
+        assert!(!king_side.can_castle(White, QueenSide));
+
+        let queen_side = board.execute_move((0, 4), (0, 2), None);
+        assert!(queen_side.get((0, 2)) == Some(Piece(King, White)));
+        assert!(queen_side.get((0, 3)) == Some(Piece(Rook, White)));
+        assert!(queen_side.get((0, 0)).is_none());
+    }
+    #[test]
+    fn test_castle_rights_lost() {
+        let board = castling_board()
+            .execute_move((0, 7), (1, 7), None) // Move the King-side Rook
+            .execute_move((7, 4), (7, 3), None)
+            .execute_move((1, 7), (0, 7), None); // And move it back
+        assert!(!board.can_castle(White, KingSide));
+        assert!(board.can_castle(White, QueenSide));
+        assert!(board.validate_move((0, 4), (0, 6), Some(White)).is_err());
+
+        // Capturing the Rook on its home square also loses the right
+        let mut board = castling_board();
+        board.set((5, 0), Some(Piece(Rook, Black)));
+        board.player = Black;
+        let board = board.execute_move((5, 0), (0, 0), None);
+        assert!(!board.can_castle(White, QueenSide));
+    }
+    #[test]
+    fn test_castle_blocked() {
+        let mut board = castling_board();
+        board.set((0, 1), Some(Piece(Knight, White)));
+        assert!(board.validate_move((0, 4), (0, 2), None).is_err());
+        assert!(board.validate_move((0, 4), (0, 6), None).is_ok());
+    }
+    #[test]
+    fn test_castle_check_rules() {
+        // Out of check
+        let mut board = castling_board();
+        board.set((5, 4), Some(Piece(Rook, Black)));
+        assert!(board.validate_move((0, 4), (0, 6), None).is_err());
+        assert!(board.validate_move((0, 4), (0, 2), None).is_err());
+        // Through check
+        let mut board = castling_board();
+        board.set((5, 5), Some(Piece(Rook, Black)));
+        assert!(board.validate_move((0, 4), (0, 6), None).is_err());
+        assert!(board.validate_move((0, 4), (0, 2), None).is_ok());
+        // Into check
+        let mut board = castling_board();
+        board.set((5, 2), Some(Piece(Rook, Black)));
+        assert!(board.validate_move((0, 4), (0, 2), None).is_err());
+        assert!(board.validate_move((0, 4), (0, 6), None).is_ok());
+        // The Queen-side Rook may pass over an attacked square
+        let mut board = castling_board();
+        board.set((5, 1), Some(Piece(Rook, Black)));
+        assert!(board.validate_move((0, 4), (0, 2), None).is_ok());
+    }
+    #[test]
+    fn test_chess960_positions() {
+        let fen = |index| Board::new_chess960(index).unwrap().to_fen();
+        assert_eq!(fen(518), Board::new().to_fen());
+        assert_eq!(fen(0), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(fen(959), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1");
+        assert!(Board::new_chess960(960).is_none());
+        // Every arrangement is different, with the King between the Rooks
+        let mut fens: Vec<String> = (0..960).map(fen).collect();
+        fens.sort();
+        fens.dedup();
+        assert_eq!(fens.len(), 960);
+        for index in 0..960 {
+            let board = Board::new_chess960(index).unwrap();
+            let king = board.find_king(Some(White)).unwrap();
+            assert!(board.is_chess960());
+            assert!(board.castling_rook(White, QueenSide).unwrap().1 < king.1);
+            assert!(board.castling_rook(Black, KingSide).unwrap().1 > king.1);
+        }
+    }
+    #[test]
+    fn test_chess960_castling() {
+        // The King already on its landing square only moves the Rook
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/6KR w H - 0 1").unwrap();
+        let castle = board.parse_uci("g1h1").unwrap();
+        assert!(castle.castle && !castle.capture);
+        assert!(board.legal_moves().contains(&castle));
+        assert_eq!(board.san(castle), "O-O");
+        assert_eq!(board.play(castle).to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        // The King on b1 castles to c1 and the Rook to d1
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/RK6 w A - 0 1").unwrap();
+        let castle = board.parse_san("O-O-O").unwrap();
+        assert_eq!(castle.to_string(), "b1a1");
+        assert_eq!(board.play(castle).to_fen(), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+        // A piece on the Rook's landing square is in the way, even though it isn't between them
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R1NK4 w A - 0 1").unwrap();
+        assert_eq!(
+            board.validate_move((0, 3), (0, 0), None),
+            Err(MoveError::CastlingBlocked(QueenSide))
+        );
+        // The Rook shields the King's landing square from the Queen until it moves
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/qR1K4 w B - 0 1").unwrap();
+        assert_eq!(
+            board.validate_move((0, 3), (0, 1), None),
+            Err(MoveError::CastleIntoCheck((0, 2)))
+        );
+        // Moving the King loses both rights, and the other Rook's moves don't matter
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/1RK2R1R w FB - 0 1").unwrap();
+        let moved = board.execute_move((0, 7), (1, 7), None);
+        assert!(moved.can_castle(White, KingSide) && moved.can_castle(White, QueenSide));
+        let moved = board.execute_move((0, 2), (1, 2), None);
+        assert!(!moved.can_castle(White, KingSide) && !moved.can_castle(White, QueenSide));
+    }
+}
@@ -6,17 +6,17 @@ use crate::board::*;
 impl Board {
     /** Returns true if PLAYER has no valid moves. False otherwise */
     pub fn has_no_moves(&self, player: Option<Color>) -> bool {
-        for from in self.find_pieces(player) {
-            for to in iproduct!(0..8, 0..8) {
-                if self.validate_move(from, to, player).is_ok() {
-                    return false;
-                }
-            }
-        }
-        true
+        self.find_pieces(player)
+            .into_iter()
+            .all(|from| self.legal_moves_from(from).is_empty())
     }
-    
-    
+
+    /** Returns true if the current player's King is attacked */
+    pub fn in_check(&self) -> bool {
+        self.find_king(None)
+            .is_some_and(|king| self.is_attacked(king, self.player.other()))
+    }
+
     /**
     Returns the number of squares that would be attacking a King at square
     KING_POSITION of color KING_COLOR. Returns 0 if not in check.
@@ -31,13 +31,8 @@ impl Board {
     */
     pub fn squares_checking(&self, king_position: Square, king_color: Option<Color>) -> Vec<Square> {
         let king_color = king_color.unwrap_or(self.player);
-        [
-            self.ranged_checking(king_position, king_color),
-            self.pawns_checking(king_position, king_color),
-            self.knights_checking(king_position, king_color),
-            self.kings_checking(king_position, king_color),
-        ]
-        .concat()
+        let index = square_index(king_position);
+        Squares(self.attackers(index, king_color.other(), self.occupied())).collect()
     }
 
     /**
@@ -45,11 +40,10 @@ impl Board {
     returns the direction of the pin, i.e. SHIELDED_SQUARE -> BLOCKING_SQUARE.
     Otherwise, returns None
     Algorithm:
-        1) Ensure that the there are no pieces between the piece that is shielded and the
-            piece that is blocking.
-        2) Beginning at BLOCKING_SQUARE, progress along the path towards SHIELDED_SQUARE until a piece
-        is found or the end of the board is reached. If the piece belongs to the opposing player
-        and can attack along that direction, return the direction. Otherwise, return None.
+        1) Take the piece at BLOCKING_SQUARE off the board.
+        2) Find the opposing Rooks and Queens, or Bishops and Queens for a diagonal, that
+        would then attack SHIELDED_SQUARE. If one of them attacks through BLOCKING_SQUARE,
+        return the direction. Otherwise, return None.
     */
     pub fn is_pinned(
         &self,
@@ -61,135 +55,16 @@ impl Board {
         // Return None if the direction doesn't exist
         let direction = Self::get_direction(shielded_square, blocking_square)?;
         //
-        if self.clear_path(blocking_square, shielded_square) {
-            let path = Self::get_directed_path(blocking_square, direction);
-            let closure = Self::get_directed_closure(direction, player.other());
-            if self.check_squares(path, &closure).is_some() {
-                return Some(direction);
-            }
-        }
-        None
-    }
-    
-    /**
-    Returns the position of the square in SQUARES containing a piece that satisfies f.
-    If no piece satisfies, returns None.
-    1) Filter squares to only check squares that are in bounds.
-    2) Check each square in order:
-        If a piece PIECE exists in that square, end the search.
-        If f(PIECE) == TRUE, return the square. Otherwise, return None
-    */
-    fn check_squares<F>(
-        &self,
-        squares: Vec<Square>,
-        // TODO: directions: Box<dyn Iterator<Item = Vec<Square>>>,
-        f: &F, // some closure that returns true if the piece matches, false otherwise
-    ) -> Option<Square>
-    where
-        F: Fn(Piece) -> bool,
-    {
-        // Filter out squares that are out of bounds
-        let squares: Vec<Square> = squares.into_iter().filter(|x| in_bounds(*x)).collect();
-        for loc in squares {
-            match self.get(loc) {
-                None => continue,
-                Some(piece) => {
-                    if f(piece) {
-                        return Some(loc);
-                    } else {
-                        return None;
-                    }
-                }
-            }
-        }
-        None
-    }
-    // Check horizontally, vertically and diagonally for pieces checking the king
-    fn ranged_checking(&self, king_position: Square, king_color: Color) -> Vec<Square> {
-        let directions = [
-            (Increasing, Zero),
-            (Decreasing, Zero),
-            (Zero, Increasing),
-            (Zero, Decreasing),
-            (Increasing, Increasing),
-            (Decreasing, Decreasing),
-            (Decreasing, Increasing),
-            (Increasing, Decreasing),
-        ];
-        directions
-            .iter()
-            .filter_map(|direction| {
-                let path = Self::get_directed_path(king_position, *direction);
-                let closure = Self::get_directed_closure(*direction, king_color.other());
-                self.check_squares(path, &closure)
-            })
-            .collect()
-    }
-    // Check each square—defined as an offset to the king's position—for pieces that match closure
-    fn relative_checking<F>(
-        &self,
-        king_position: Square,
-        squares: Vec<Square>,
-        closure: &F,
-    ) -> Vec<Square>
-    where
-        F: Fn(Piece) -> bool,
-    {
-        squares
-            .into_iter()
-            .map(|x| vec![(king_position.0 + x.0, king_position.1 + x.1)])
-            .filter_map(|squares| self.check_squares(squares, &closure))
-            .collect()
-    }
-    // Check for 2 directions, diagonal-left and diagonal-right
-    fn pawns_checking(&self, king_position: Square, king_color: Color) -> Vec<Square> {
-        let closure = |piece: Piece| piece == Piece(Pawn, king_color.other());
-        let squares = match king_color {
-            White => vec![(1, 1), (1, -1)],
-            Black => vec![(-1, 1), (-1, -1)],
+        let occupied = self.occupied() & !square_bit(blocking_square);
+        let index = square_index(shielded_square);
+        let opposing = |piece_type| self.piece_bitboard(Piece(piece_type, player.other()));
+        let attackers = match direction {
+            (Zero, _) | (_, Zero) => rook_attacks(index, occupied) & (opposing(Rook) | opposing(Queen)),
+            _ => bishop_attacks(index, occupied) & (opposing(Bishop) | opposing(Queen)),
         };
-        self.relative_checking(king_position, squares, &closure)
-    }
-    // Check 8 directions, each containing one square
-    fn knights_checking(&self, king_position: Square, king_color: Color) -> Vec<Square> {
-        let closure = |piece: Piece| piece == Piece(Knight, king_color.other());
-        let squares = vec![
-            (1, 2),
-            (1, -2),
-            (-1, 2),
-            (-1, -2),
-            (2, 1),
-            (2, -1),
-            (-2, 1),
-            (-2, -1),
-        ];
-        self.relative_checking(king_position, squares, &closure)
-    }
-    fn kings_checking(&self, king_position: Square, king_color: Color) -> Vec<Square> {
-        let closure = |piece: Piece| piece == Piece(King, king_color.other());
-        let squares = vec![
-            (1, -1),
-            (1, 0),
-            (1, 1),
-            (0, -1),
-            (0, 1),
-            (-1, -1),
-            (-1, 0),
-            (-1, 1),
-        ];
-        self.relative_checking(king_position, squares, &closure)
-    }
-    
-    fn get_directed_closure(dir: Direction, color: Color) -> Box<dyn Fn(Piece) -> bool> {
-        match dir {
-            (Zero, Zero) => panic!("This is not a direction!"),
-            (Zero, _) | (_, Zero) => Box::new(move |piece: Piece| {
-                piece == Piece(Rook, color) || piece == Piece(Queen, color)
-            }),
-            _ => Box::new(move |piece: Piece| {
-                piece == Piece(Bishop, color) || piece == Piece(Queen, color)
-            }),
-        }
+        Squares(attackers)
+            .any(|attacker| between(shielded_square, attacker) & square_bit(blocking_square) != 0)
+            .then_some(direction)
     }
 }
 
@@ -198,7 +73,7 @@ mod tests {
     use super::*;
     #[test]
     fn test_num_checking() {
-        let mut board = Board::empty(White, 0);
+        let mut board = Board::empty(White, 1);
 
         let king_position = (0, 4);
         board.set(king_position, Some(Piece(King, White)));
@@ -215,7 +90,7 @@ mod tests {
     }
     #[test]
     fn test_horizonal_check() {
-        let mut board = Board::empty(Black, 0);
+        let mut board = Board::empty(Black, 1);
         let king_position = (4, 6);
         board.set(king_position, Some(Piece(King, Black)));
         assert_eq!(board.num_checking(king_position, None), 0);
@@ -234,7 +109,7 @@ mod tests {
     }
     #[test]
     fn test_vertical_check() {
-        let mut board = Board::empty(Black, 0);
+        let mut board = Board::empty(Black, 1);
         let king_position = (6, 4);
         board.set(king_position, Some(Piece(King, Black)));
         assert_eq!(board.num_checking(king_position, None), 0);
@@ -253,7 +128,7 @@ mod tests {
     }
     #[test]
     fn test_diagonal_check() {
-        let mut board = Board::empty(White, 0);
+        let mut board = Board::empty(White, 1);
         let king_position = (4, 4);
         board.set(king_position, Some(Piece(King, White)));
         assert_eq!(board.num_checking(king_position, None), 0);
@@ -275,7 +150,7 @@ mod tests {
     }
     #[test]
     fn test_pawns_check_white() {
-        let mut board = Board::empty(White, 0);
+        let mut board = Board::empty(White, 1);
         let king_position = (4, 4);
         board.set(king_position, Some(Piece(King, White)));
         assert_eq!(board.num_checking(king_position, None), 0);
@@ -293,7 +168,7 @@ mod tests {
     }
     #[test]
     fn test_pawns_check_black() {
-        let mut board = Board::empty(Black, 0);
+        let mut board = Board::empty(Black, 1);
         let king_position = (4, 4);
         board.set(king_position, Some(Piece(King, Black)));
         assert_eq!(board.num_checking(king_position, None), 0);
@@ -311,7 +186,7 @@ mod tests {
     }
     #[test]
     fn test_knights_check() {
-        let mut board = Board::empty(Black, 0);
+        let mut board = Board::empty(Black, 1);
         let king_position = (4, 4);
         board.set(king_position, Some(Piece(King, Black)));
         assert_eq!(board.num_checking(king_position, None), 0);
@@ -324,7 +199,7 @@ mod tests {
     }
     #[test]
     fn test_kings_check() {
-        let mut board = Board::empty(White, 0);
+        let mut board = Board::empty(White, 1);
         let king_position = (4, 4);
         board.set(king_position, Some(Piece(King, White)));
         assert_eq!(board.num_checking(king_position, None), 0);
@@ -340,7 +215,7 @@ mod tests {
     }
     #[test]
     fn test_pinning_single() {
-        let mut board = Board::empty(White, 0);
+        let mut board = Board::empty(White, 1);
         let (king, attacker, blocker) = ((4, 2), (0, 6), (3, 3));
         board.set(king, Some(Piece(King, White)));
         board.set(attacker, Some(Piece(Queen, Black)));
@@ -350,7 +225,7 @@ mod tests {
     }
     #[test]
     fn test_pinning_double() {
-        let mut board = Board::empty(White, 0);
+        let mut board = Board::empty(White, 1);
         let (king, attacker, blocker_1, blocker_2) = ((4, 2), (0, 6), (3, 3), (2, 4));
         board.set(king, Some(Piece(King, White)));
         board.set(attacker, Some(Piece(Queen, Black)));
@@ -1,10 +1,12 @@
 // Jack Alpert 2020
 
+use crate::board::path::*;
 use crate::board::*;
+#[cfg(feature = "std")]
 use ansi_term::{Colour, Style};
-use std::fmt;
-use std::fmt::{Display, Formatter};
-use std::str::FromStr;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
 
 impl Display for Color {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -33,6 +35,23 @@ impl Display for Sign {
     }
 }
 
+impl Display for Draw {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match &self {
+                Stalemate => "stalemate",
+                InsufficientMaterial => "insufficient material",
+                FiftyMoves => "the fifty-move rule",
+                SeventyFiveMoves => "the seventy-five-move rule",
+                ThreefoldRepetition => "threefold repetition",
+                FivefoldRepetition => "fivefold repetition",
+            }
+        )
+    }
+}
+
 impl Display for Piece {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(
@@ -57,9 +76,9 @@ impl Display for Piece {
 }
 
 impl FromStr for Piece {
-    type Err = String;
+    type Err = ParseError;
 
-    fn from_str(s: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+    fn from_str(s: &str) -> core::result::Result<Self, <Self as FromStr>::Err> {
         match s.trim() {
             "♙" => Ok(Piece(Pawn, White)),
             "♟" => Ok(Piece(Pawn, Black)),
@@ -73,15 +92,65 @@ impl FromStr for Piece {
             "♛" => Ok(Piece(Queen, Black)),
             "♔" => Ok(Piece(King, White)),
             "♚" => Ok(Piece(King, Black)),
-            _ => Err(String::from("Not a recognized piece")),
+            _ => Err(ParseError::InvalidPiece(String::from(s))),
         }
     }
 }
 
+impl Display for PieceType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match &self {
+                Pawn => "Pawn",
+                Rook => "Rook",
+                Knight => "Knight",
+                Bishop => "Bishop",
+                Queen => "Queen",
+                King => "King",
+            }
+        )
+    }
+}
+
+// Accepts either the full name or the letter used in algebraic notation, ignoring case
+impl FromStr for PieceType {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> core::result::Result<Self, <Self as FromStr>::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "p" | "pawn" => Ok(Pawn),
+            "r" | "rook" => Ok(Rook),
+            "n" | "knight" => Ok(Knight),
+            "b" | "bishop" => Ok(Bishop),
+            "q" | "queen" => Ok(Queen),
+            "k" | "king" => Ok(King),
+            _ => Err(ParseError::InvalidPiece(String::from(s))),
+        }
+    }
+}
+
+// Long algebraic notation, as used by UCI, e.g. "e2e4" or "e7e8q"
+impl Display for Move {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(piece_type) = self.drop {
+            return write!(f, "{}@{}", piece_type.letter(), square_name(self.to));
+        }
+        write!(f, "{}{}", square_name(self.from), square_name(self.to))?;
+        match self.promotion {
+            Some(piece_type) => write!(f, "{}", piece_type.letter().to_ascii_lowercase()),
+            None => Ok(()),
+        }
+    }
+}
+
+// Draws the board with colored squares for a terminal
+#[cfg(feature = "std")]
 impl Display for Board {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         // Label each column with its appropriate index
-        write!(f, "     1  2  3  4  5  6  7  8\n")?;
+        writeln!(f, "     1  2  3  4  5  6  7  8")?;
         for row in 0..8 {
             // Mark each row with its index
             write!(f, " {}  ", row + 1)?;
@@ -98,12 +167,25 @@ impl Display for Board {
                         .on(tile_color)
                         .paint(match self.get((row, col)) {
                             Some(p) => format!(" {} ", p),
-                            None => format!("   "),
+                            None => "   ".to_string(),
                         }),
                 )?;
             }
             // Add a new line for each row
-            write!(f, "\n")?;
+            writeln!(f)?;
+        }
+        // List the pieces each player may drop, in Crazyhouse
+        for &player in &[White, Black] {
+            let pocket: String = POCKET_TYPES
+                .iter()
+                .map(|&piece_type| {
+                    let count = self.pocket(player, piece_type) as usize;
+                    Piece(piece_type, player).to_string().repeat(count)
+                })
+                .collect();
+            if !pocket.is_empty() {
+                writeln!(f, "{}'s pocket: {}", player, pocket)?;
+            }
         }
 
         // Return a Result
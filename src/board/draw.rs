@@ -0,0 +1,251 @@
+// Jack Alpert 2020
+
+use crate::board::path::*;
+use crate::board::*;
+
+// Number of moves by either player without a capture or Pawn move for each rule
+pub const FIFTY_MOVES: u32 = 100;
+pub const SEVENTY_FIVE_MOVES: u32 = 150;
+
+/** Why a game ended without a winner */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Draw {
+    Stalemate,
+    InsufficientMaterial,
+    FiftyMoves,          // May be claimed by either player
+    SeventyFiveMoves,    // Ends the game without a claim
+    ThreefoldRepetition, // May be claimed by either player
+    FivefoldRepetition,  // Ends the game without a claim
+}
+pub use Draw::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Outcome {
+    Checkmate(Color),  // The winner
+    VariantWin(Color), // The winner, by a rule of the variant being played
+    Drawn(Draw),
+}
+
+impl Outcome {
+    pub fn winner(&self) -> Option<Color> {
+        match self {
+            Outcome::Checkmate(winner) | Outcome::VariantWin(winner) => Some(*winner),
+            Outcome::Drawn(_) => None,
+        }
+    }
+}
+
+impl Board {
+    /**
+    Returns true if neither player has enough pieces left to checkmate: only Kings,
+    a King and a single Bishop or Knight against a lone King, or Kings and Bishops
+    that all stand on squares of the same color.
+    */
+    pub fn is_insufficient_material(&self) -> bool {
+        // Any piece in a pocket can be dropped to give mate
+        if self.pockets != [[0; 5]; 2] {
+            return false;
+        }
+        let pieces: Vec<(Square, Piece)> = Squares(self.occupied() & !self.pieces[King as usize])
+            .map(|loc| (loc, self.get(loc).unwrap()))
+            .collect();
+        match pieces.as_slice() {
+            [] => true,
+            [(_, Piece(Knight, _))] => true,
+            _ => {
+                let square_color = |loc: Square| (loc.0 + loc.1) % 2;
+                pieces.iter().all(|&(loc, piece)| {
+                    piece.piece_type() == Bishop && square_color(loc) == square_color(pieces[0].0)
+                })
+            }
+        }
+    }
+
+    /**
+    Returns true if OTHER is the same position for the purpose of repetition: the same
+    pieces on the same squares, the same player to move and the same moves available,
+    including castling and capturing en passant, and the same number of checks given
+    and pieces in each pocket.
+    */
+    pub fn is_same_position(&self, other: &Board) -> bool {
+        self.pieces == other.pieces
+            && self.colors == other.colors
+            && self.player == other.player
+            && self.castling == other.castling
+            && self.checks == other.checks
+            && self.pockets == other.pockets
+            && self.promoted == other.promoted
+            && self.capturable_en_passant() == other.capturable_en_passant()
+    }
+
+    // The en passant square, if a Pawn can actually capture there
+    fn capturable_en_passant(&self) -> Option<Square> {
+        let target = self.en_passant?;
+        let pawn = Piece(Pawn, self.player);
+        // Only a Pawn beside the one that just advanced two squares can capture it
+        let row = match self.player {
+            White => target.0 - 1,
+            Black => target.0 + 1,
+        };
+        [(row, target.1 - 1), (row, target.1 + 1)]
+            .iter()
+            .any(|&from| {
+                in_bounds(from)
+                    && self.get(from) == Some(pawn)
+                    && self.keeps_king_safe(from, target, pawn)
+            })
+            .then_some(target)
+    }
+}
+
+impl Game {
+    /** Returns how many times the current position has occurred, including now */
+    pub fn repetitions(&self) -> usize {
+        let board = self.board();
+        // No earlier position can repeat after a capture or Pawn move
+        self.boards()
+            .iter()
+            .rev()
+            .take(board.halfmove_clock() as usize + 1)
+            .filter(|other| board.is_same_position(other))
+            .count()
+    }
+
+    /**
+    Returns the draw the current player may claim, by the fifty-move rule or threefold
+    repetition, if any.
+    */
+    pub fn claimable_draw(&self) -> Option<Draw> {
+        if self.repetitions() >= 3 {
+            Some(ThreefoldRepetition)
+        } else if self.board().halfmove_clock() >= FIFTY_MOVES {
+            Some(FiftyMoves)
+        } else {
+            None
+        }
+    }
+
+    /**
+    Returns how the game has ended, or None if it continues, by the rules of the
+    game's variant. See standard_outcome for standard chess.
+    */
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.variant().outcome(self)
+    }
+
+    /**
+    Returns how the game has ended by the rules of standard chess, or None if it continues.
+    Checkmate, stalemate, insufficient material, the seventy-five-move rule and fivefold
+    repetition end the game without either player claiming a draw.
+    */
+    pub fn standard_outcome(&self) -> Option<Outcome> {
+        if let Some(outcome) = self.mate_outcome() {
+            return Some(outcome);
+        }
+        if self.board().is_insufficient_material() {
+            return Some(Outcome::Drawn(InsufficientMaterial));
+        }
+        self.automatic_draw().map(Outcome::Drawn)
+    }
+
+    /** Returns checkmate or stalemate if the current player has no valid moves */
+    pub fn mate_outcome(&self) -> Option<Outcome> {
+        let board = self.board();
+        if !board.has_no_moves(None) {
+            None
+        } else if board.in_check() {
+            Some(Outcome::Checkmate(board.player().other()))
+        } else {
+            Some(Outcome::Drawn(Stalemate))
+        }
+    }
+
+    /**
+    Returns the draw by fivefold repetition or the seventy-five-move rule, if either
+    has ended the game. These apply whatever the variant.
+    */
+    pub fn automatic_draw(&self) -> Option<Draw> {
+        if self.repetitions() >= 5 {
+            Some(FivefoldRepetition)
+        } else if self.board().halfmove_clock() >= SEVENTY_FIVE_MOVES {
+            Some(SeventyFiveMoves)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_insufficient_material() {
+        let insufficient = [
+            "8/8/8/4k3/8/8/8/4K3 w - - 0 1",
+            "8/8/8/4k3/8/8/8/2B1K3 w - - 0 1",
+            "8/8/8/4k3/8/8/8/1N2K3 b - - 0 1",
+            "8/8/3b4/4k3/8/8/8/2B1K3 w - - 0 1", // Both Bishops on dark squares
+        ];
+        for fen in insufficient.iter() {
+            assert!(Board::from_fen(fen).unwrap().is_insufficient_material(), "{}", fen);
+        }
+        let sufficient = [
+            "8/8/8/4k3/8/8/8/1NB1K3 w - - 0 1",
+            "8/8/2b5/4k3/8/8/8/2B1K3 w - - 0 1", // Bishops on opposite colors
+            "8/8/8/4k3/8/8/8/1NN1K3 w - - 0 1",
+            "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1",
+            "8/8/8/4k3/8/8/8/R3K3 w - - 0 1",
+        ];
+        for fen in sufficient.iter() {
+            assert!(!Board::from_fen(fen).unwrap().is_insufficient_material(), "{}", fen);
+        }
+        let game = Game::from_board(Board::from_fen(insufficient[1]).unwrap());
+        assert_eq!(game.outcome(), Some(Outcome::Drawn(InsufficientMaterial)));
+    }
+    #[test]
+    fn test_repetition() {
+        let mut game = Game::new();
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+        for san in shuffle.iter().cycle().take(8) {
+            assert_eq!(game.outcome(), None);
+            game.play(game.board().parse_san(san).unwrap());
+        }
+        assert_eq!(game.repetitions(), 3);
+        assert_eq!(game.claimable_draw(), Some(ThreefoldRepetition));
+        assert_eq!(game.outcome(), None);
+        for san in shuffle.iter().cycle().take(8) {
+            game.play(game.board().parse_san(san).unwrap());
+        }
+        assert_eq!(game.repetitions(), 5);
+        assert_eq!(game.outcome(), Some(Outcome::Drawn(FivefoldRepetition)));
+    }
+    #[test]
+    fn test_en_passant_repetition() {
+        // After 1. e4 the en passant square cannot be used, so it does not change the position
+        let after_e4 = Board::new().play(Board::new().parse_san("e4").unwrap());
+        let same = Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        assert!(after_e4.is_same_position(&same.unwrap()));
+        let board = Board::from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        let without = Board::from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1").unwrap();
+        assert!(!board.is_same_position(&without));
+        // Taking en passant would leave the King in check from the Rook
+        let board = Board::from_fen("8/8/8/8/k2pP2R/8/8/4K3 b - e3 0 1").unwrap();
+        let without = Board::from_fen("8/8/8/8/k2pP2R/8/8/4K3 b - - 0 1").unwrap();
+        assert!(board.is_same_position(&without));
+    }
+    #[test]
+    fn test_move_rules() {
+        let game = Game::from_board(Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap());
+        assert_eq!(game.claimable_draw(), None);
+        let game = Game::from_board(Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 100 80").unwrap());
+        assert_eq!(game.claimable_draw(), Some(FiftyMoves));
+        assert_eq!(game.outcome(), None);
+        let game = Game::from_board(Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 150 100").unwrap());
+        assert_eq!(game.outcome(), Some(Outcome::Drawn(SeventyFiveMoves)));
+        // Checkmate on the last move still wins
+        let mut game =
+            Game::from_board(Board::from_fen("4k3/R7/8/8/8/8/8/1R2K3 w - - 149 100").unwrap());
+        game.play(game.board().parse_san("Rb8#").unwrap());
+        assert_eq!(game.outcome(), Some(Outcome::Checkmate(White)));
+    }
+}
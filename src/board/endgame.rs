@@ -0,0 +1,743 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+use alloc::collections::BTreeMap;
+
+/** How far a position is from mate with perfect play, in plies */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dtm {
+    Mate(u32), // The player to move gives mate in this many plies
+    Mated(u32), // The player to move is mated in this many plies, 0 if they already are
+    Draw,
+}
+
+impl Dtm {
+    // Tables store the plies plus one, or 0 for a draw. Odd plies are wins.
+    fn from_byte(byte: u8) -> Dtm {
+        match byte {
+            0 => Dtm::Draw,
+            n if n % 2 == 0 => Dtm::Mate(n as u32 - 1),
+            n => Dtm::Mated(n as u32 - 1),
+        }
+    }
+
+    /** The score a search gives the position, as for a mate found PLY plies from the root */
+    pub fn score(&self, ply: usize) -> i32 {
+        match *self {
+            Dtm::Mate(plies) => MATE - ply as i32 - plies as i32,
+            Dtm::Mated(plies) => -MATE + ply as i32 + plies as i32,
+            Dtm::Draw => 0,
+        }
+    }
+}
+
+// Tables of more pieces would be too big to keep in memory
+const MAX_PIECES: usize = 4;
+// The longest mate a table can hold
+const MAX_PLIES: usize = 254;
+
+// The letters of the pieces in the order of table names
+const LETTERS: [(char, PieceType); 6] =
+    [('K', King), ('Q', Queen), ('R', Rook), ('B', Bishop), ('N', Knight), ('P', Pawn)];
+
+// The squares of the triangle a1-d1-d4, numbered 0 to 9
+const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+// Which pieces a table holds and how its positions are numbered. The board is turned so
+// that the White King is in the a1-d1-d4 triangle, or only mirrored so that it is on
+// files a to d when there are Pawns, which only go one way.
+#[derive(Debug, Clone, PartialEq)]
+struct Layout {
+    pieces: Vec<Piece>, // White's then Black's, each starting with the King
+    pawns: bool,
+}
+
+impl Layout {
+    // The layout for MATERIAL, a name like "KRvK", if it has a King each and few enough pieces
+    fn new(material: &str) -> Option<Layout> {
+        let mut sides = material.split('v');
+        let mut pieces = Vec::new();
+        for &color in [White, Black].iter() {
+            let mut side: Vec<PieceType> = sides
+                .next()?
+                .chars()
+                .map(|letter| LETTERS.iter().find(|&&(l, _)| l == letter).map(|&(_, p)| p))
+                .collect::<Option<_>>()?;
+            if side.iter().filter(|&&piece_type| piece_type == King).count() != 1 {
+                return None;
+            }
+            side.sort_by_key(|&piece_type| LETTERS.iter().position(|&(_, p)| p == piece_type));
+            pieces.extend(side.into_iter().map(|piece_type| Piece(piece_type, color)));
+        }
+        if sides.next().is_some() || pieces.len() > MAX_PIECES {
+            return None;
+        }
+        let pawns = pieces.iter().any(|piece| piece.piece_type() == Pawn);
+        Some(Layout { pieces, pawns })
+    }
+
+    fn name(&self) -> String {
+        let side = |color: Color| -> String {
+            self.pieces
+                .iter()
+                .filter(|piece| piece.color() == color)
+                .map(|piece| LETTERS.iter().find(|&&(_, p)| p == piece.piece_type()).unwrap().0)
+                .collect()
+        };
+        format!("{}v{}", side(White), side(Black))
+    }
+
+    // Squares the White King can be numbered on
+    fn king_squares(&self) -> usize {
+        if self.pawns {
+            32
+        } else {
+            TRIANGLE.len()
+        }
+    }
+
+    // The number of positions, including those never used
+    fn len(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    // The number of BOARD, the same for every turn or reflection of the position,
+    // or None if BOARD doesn't have exactly these pieces
+    fn index(&self, board: &Board) -> Option<usize> {
+        let n = self.pieces.len();
+        if board.occupied().count_ones() as usize != n {
+            return None;
+        }
+        let mut squares = [0; MAX_PIECES];
+        let mut start = 0;
+        while start < n {
+            let end = self.run_end(start);
+            let bitboard = board.piece_bitboard(self.pieces[start]);
+            if bitboard.count_ones() as usize != end - start {
+                return None;
+            }
+            for (square, loc) in squares[start..end].iter_mut().zip(Squares(bitboard)) {
+                *square = square_index(loc);
+            }
+            start = end;
+        }
+        let transforms = if self.pawns { 2 } else { 8 };
+        (0..transforms)
+            .filter_map(|t| {
+                let mut turned = [0; MAX_PIECES];
+                for (turned, &square) in turned.iter_mut().zip(squares[..n].iter()) {
+                    *turned = transform(square, t);
+                }
+                let king = self.king_code(turned[0])?;
+                // Identical pieces could be numbered in any order, so sort them
+                let mut start = 1;
+                while start < n {
+                    let end = self.run_end(start);
+                    turned[start..end].sort_unstable();
+                    start = end;
+                }
+                let position = turned[1..n].iter().fold(0, |index, &square| index * 64 + square);
+                let prefix = board.player() as usize * self.king_squares() + king;
+                Some(prefix * 64usize.pow(n as u32 - 1) + position)
+            })
+            .min()
+    }
+
+    // The end of the run of identical pieces from START
+    fn run_end(&self, start: usize) -> usize {
+        let n = self.pieces.len();
+        (start..n).find(|&i| self.pieces[i] != self.pieces[start]).unwrap_or(n)
+    }
+
+    // The number of the White King's square, if it is where the layout puts it
+    fn king_code(&self, square: usize) -> Option<usize> {
+        if self.pawns {
+            if square & 7 < 4 {
+                Some((square >> 3) * 4 + (square & 7))
+            } else {
+                None
+            }
+        } else {
+            TRIANGLE.iter().position(|&s| s == square)
+        }
+    }
+
+    // The position numbered INDEX, or None if it is not a legal position
+    fn board(&self, index: usize) -> Option<Board> {
+        let n = self.pieces.len();
+        let mut rest = index;
+        let mut squares = [0; MAX_PIECES];
+        for square in squares[1..n].iter_mut().rev() {
+            *square = rest % 64;
+            rest /= 64;
+        }
+        let king = rest % self.king_squares();
+        squares[0] = if self.pawns {
+            (king / 4) * 8 + king % 4
+        } else {
+            TRIANGLE[king]
+        };
+        let player = if rest / self.king_squares() == 0 { White } else { Black };
+        let mut board = Board::empty(player, 1);
+        for (&piece, &square) in self.pieces.iter().zip(squares.iter()) {
+            let loc = index_square(square);
+            if board.get(loc).is_some() || (piece.piece_type() == Pawn && (loc.0 == 0 || loc.0 == 7)) {
+                return None;
+            }
+            board.set(loc, Some(piece));
+        }
+        // The player who just moved can't be left in check
+        let other = board.player().other();
+        let king = board.find_king(Some(other))?;
+        if board.is_attacked(king, board.player()) {
+            return None;
+        }
+        Some(board)
+    }
+
+    // The tables needed first: those left after a capture or a promotion
+    fn dependencies(&self) -> Vec<Layout> {
+        let mut layouts = Vec::new();
+        for (i, &piece) in self.pieces.iter().enumerate() {
+            if piece.piece_type() == King {
+                continue;
+            }
+            let mut captured = self.clone();
+            captured.pieces.remove(i);
+            layouts.push(captured);
+            if piece.piece_type() == Pawn {
+                for &piece_type in [Queen, Rook, Bishop, Knight].iter() {
+                    let mut promoted = self.clone();
+                    promoted.pieces[i] = Piece(piece_type, piece.color());
+                    layouts.push(promoted);
+                }
+            }
+        }
+        layouts
+            .into_iter()
+            .filter_map(|layout| Layout::new(&layout.name()))
+            .collect()
+    }
+}
+
+// One of the 8 ways to turn and reflect the board, applied to the square with INDEX
+fn transform(index: usize, t: usize) -> usize {
+    let mut index = index;
+    if t & 4 != 0 {
+        index = ((index >> 3) | (index << 3)) & 63;
+    }
+    if t & 2 != 0 {
+        index ^= 56;
+    }
+    if t & 1 != 0 {
+        index ^= 7;
+    }
+    index
+}
+
+/**
+The distance to mate of every position of an ending with 3 or 4 pieces, such as KRvK,
+worked out by retrograde analysis. Positions with castling rights are not covered,
+and en passant is ignored.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndgameTable {
+    layout: Layout,
+    values: Vec<u8>, // By position number, see Dtm::from_byte
+}
+
+impl EndgameTable {
+    /** The pieces of the table, e.g. "KRvK" */
+    pub fn name(&self) -> String {
+        self.layout.name()
+    }
+
+    /** The distance to mate of BOARD, or None if it doesn't have the table's pieces */
+    pub fn probe(&self, board: &Board) -> Option<Dtm> {
+        let index = self.layout.index(board)?;
+        Some(Dtm::from_byte(self.values[index]))
+    }
+
+    /*
+    Start from the mates, and the captures and promotions into the tables in TABLES,
+    then work backwards a ply at a time. A position is won in N plies if a move leads to
+    a position lost in N - 1, and lost in N if every move leads to a position won in at
+    most N - 1. Positions never reached this way are drawn.
+    */
+    fn generate(layout: Layout, tables: &EndgameTables) -> Result<EndgameTable, ParseError> {
+        let len = layout.len();
+        let mut values = vec![0u8; len];
+        let mut remaining = vec![0u8; len]; // Moves within the table whose result is unknown
+        let mut escapes = vec![false; len]; // A move out of the table draws or wins
+        let mut slowest = vec![0u8; len]; // Plies to the slowest mate by moving out of the table
+        let mut by_ply: Vec<Vec<u32>> = vec![Vec::new(); MAX_PLIES + 1]; // Settled positions
+        let mut exits: Vec<Vec<u32>> = vec![Vec::new(); MAX_PLIES + 1]; // Won by leaving the table
+
+        for index in 0..len {
+            let board = match layout.board(index) {
+                Some(board) if layout.index(&board) == Some(index) => board,
+                _ => continue,
+            };
+            let moves = board.legal_moves();
+            if moves.is_empty() {
+                if board.in_check() {
+                    values[index] = 1;
+                    by_ply[0].push(index as u32);
+                }
+                continue;
+            }
+            let mut fastest = None;
+            let mut children = Vec::new();
+            for mv in moves {
+                let child = board.play(mv);
+                if !mv.capture && mv.promotion.is_none() {
+                    children.push(layout.index(&child).unwrap());
+                    continue;
+                }
+                let dtm = tables.probe(&child).ok_or_else(|| {
+                    ParseError::InvalidEndgame(format!(
+                        "The table for {} is needed first.",
+                        material(&child)
+                    ))
+                })?;
+                match dtm {
+                    Dtm::Mated(plies) => {
+                        fastest = Some(fastest.unwrap_or(u32::MAX).min(plies + 1));
+                        escapes[index] = true;
+                    }
+                    Dtm::Draw => escapes[index] = true,
+                    Dtm::Mate(plies) => slowest[index] = slowest[index].max(plies as u8 + 1),
+                }
+            }
+            // Moves to positions that are the same after turning the board count once
+            children.sort_unstable();
+            children.dedup();
+            remaining[index] = children.len() as u8;
+            if let Some(plies) = fastest.filter(|&plies| plies as usize <= MAX_PLIES) {
+                // Unless a quicker win is found within the table
+                exits[plies as usize].push(index as u32);
+            } else if remaining[index] == 0 && !escapes[index] {
+                let plies = slowest[index] as usize;
+                if plies <= MAX_PLIES {
+                    values[index] = plies as u8 + 1;
+                    by_ply[plies].push(index as u32);
+                }
+            }
+        }
+
+        for ply in 0..=MAX_PLIES {
+            for index in core::mem::take(&mut exits[ply]) {
+                if values[index as usize] == 0 {
+                    values[index as usize] = ply as u8 + 1;
+                    by_ply[ply].push(index);
+                }
+            }
+            for index in core::mem::take(&mut by_ply[ply]) {
+                let index = index as usize;
+                let board = layout.board(index).unwrap();
+                let mut parents: Vec<usize> = unmoves(&board)
+                    .iter()
+                    .filter_map(|parent| layout.index(parent))
+                    .collect();
+                parents.sort_unstable();
+                parents.dedup();
+                for parent in parents {
+                    if values[parent] != 0 {
+                        continue;
+                    }
+                    if ply == MAX_PLIES {
+                        break; // Too long to store
+                    } else if ply % 2 == 0 {
+                        // The position is lost, so the player who moved into it wins
+                        values[parent] = ply as u8 + 2;
+                        by_ply[ply + 1].push(parent as u32);
+                    } else {
+                        remaining[parent] -= 1;
+                        if remaining[parent] == 0 && !escapes[parent] {
+                            let plies = (slowest[parent] as usize).max(ply + 1);
+                            if plies <= MAX_PLIES {
+                                values[parent] = plies as u8 + 1;
+                                by_ply[plies].push(parent as u32);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(EndgameTable { layout, values })
+    }
+}
+
+// The material of BOARD as a table name, with White first
+fn material(board: &Board) -> String {
+    let side = |color: Color| -> String {
+        LETTERS
+            .iter()
+            .map(|&(letter, piece_type)| {
+                let count = board.piece_bitboard(Piece(piece_type, color)).count_ones();
+                letter.to_string().repeat(count as usize)
+            })
+            .collect()
+    };
+    format!("{}v{}", side(White), side(Black))
+}
+
+// The positions from which the player who just moved on BOARD could have reached it
+// without a capture or promotion
+fn unmoves(board: &Board) -> Vec<Board> {
+    let mover = board.player().other();
+    let occupied = board.occupied();
+    let mut parents = Vec::new();
+    for from in Squares(board.color_bitboard(mover)) {
+        let piece = board.get(from).unwrap();
+        let targets = if piece.piece_type() == Pawn {
+            // Back towards the Pawn's own side, but never onto the first row
+            let back = if mover == White { -1 } else { 1 };
+            let one = (from.0 + back, from.1);
+            let two = (from.0 + 2 * back, from.1);
+            let mut targets = 0;
+            if one.0 != mover.home_row() && square_bit(one) & occupied == 0 {
+                targets |= square_bit(one);
+                // Two squares from the row the Pawn started on
+                if two.0 == mover.home_row() - back && square_bit(two) & occupied == 0 {
+                    targets |= square_bit(two);
+                }
+            }
+            targets
+        } else {
+            board.attacks_from(from) & !occupied
+        };
+        for to in Squares(targets) {
+            let mut parent = *board;
+            parent.set(from, None);
+            parent.set(to, Some(piece));
+            parent.player = mover;
+            // The player to move in BOARD can't have been in check when the other moved
+            let king = parent.find_king(Some(board.player()));
+            if king.is_some_and(|king| !parent.is_attacked(king, mover)) {
+                parents.push(parent);
+            }
+        }
+    }
+    parents
+}
+
+/**
+A set of endgame tables, looked up by the pieces on the board whichever side has them.
+Written to a file as a magic number, then each table's name, its number of positions
+and its values in runs of equal values.
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EndgameTables {
+    tables: BTreeMap<String, EndgameTable>,
+}
+
+const MAGIC: &[u8; 4] = b"CETB";
+
+impl EndgameTables {
+    pub fn new() -> EndgameTables {
+        EndgameTables::default()
+    }
+
+    /**
+    Work out the table for MATERIAL, e.g. "KPvK", and those it needs for the positions
+    left after captures and promotions. Tables already in the set are kept.
+    Fails unless MATERIAL has a King each and 3 or 4 pieces.
+    */
+    pub fn generate(&mut self, material: &str) -> Result<(), ParseError> {
+        let layout = Layout::new(material).ok_or_else(|| {
+            ParseError::InvalidEndgame(format!(
+                "{} is not an ending of at most {} pieces, such as KRvK.",
+                material, MAX_PIECES
+            ))
+        })?;
+        if self.find(&layout.name()).is_some() {
+            return Ok(());
+        }
+        for dependency in layout.dependencies() {
+            if !is_drawn(&dependency) {
+                self.generate(&stronger_first(&dependency))?;
+            }
+        }
+        let table = EndgameTable::generate(layout, self)?;
+        self.tables.insert(table.name(), table);
+        Ok(())
+    }
+
+    /** The names of the tables in the set */
+    pub fn names(&self) -> Vec<String> {
+        self.tables.keys().cloned().collect()
+    }
+
+    /**
+    The distance to mate of BOARD, or None if no table has its pieces or it has castling
+    rights. Positions without enough pieces to mate are drawn.
+    */
+    pub fn probe(&self, board: &Board) -> Option<Dtm> {
+        let castling = [White, Black]
+            .iter()
+            .any(|&color| board.can_castle(color, KingSide) || board.can_castle(color, QueenSide));
+        if castling || board.occupied().count_ones() as usize > MAX_PIECES {
+            return None;
+        }
+        if board.is_insufficient_material() {
+            return Some(Dtm::Draw);
+        }
+        if let Some(table) = self.tables.get(&material(board)) {
+            return table.probe(board);
+        }
+        let swapped = swap_colors(board);
+        self.tables.get(&material(&swapped))?.probe(&swapped)
+    }
+
+    /**
+    The move on BOARD that mates soonest, or failing that keeps the draw, or failing that
+    delays mate longest, with the distance to mate of BOARD. None if BOARD is not covered
+    or the game is over.
+    */
+    pub fn best_move(&self, board: &Board) -> Option<(Move, Dtm)> {
+        let dtm = self.probe(board)?;
+        let mut best: Option<(Move, (i32, i32))> = None;
+        for mv in board.legal_moves() {
+            // Rank the moves by what is left to the opponent
+            let rank = match self.probe(&board.play(mv))? {
+                Dtm::Mated(plies) => (2, -(plies as i32)),
+                Dtm::Draw => (1, 0),
+                Dtm::Mate(plies) => (0, plies as i32),
+            };
+            if best.is_none_or(|(_, best)| rank > best) {
+                best = Some((mv, rank));
+            }
+        }
+        best.map(|(mv, _)| (mv, dtm))
+    }
+
+    /** The moves of perfect play from BOARD until mate, or just the best move if it is drawn */
+    pub fn line(&self, board: &Board) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut board = *board;
+        while let Some((mv, dtm)) = self.best_move(&board) {
+            line.push(mv);
+            board = board.play(mv);
+            if dtm == Dtm::Draw || line.len() > MAX_PLIES {
+                break;
+            }
+        }
+        line
+    }
+
+    /** Write the tables in the file format */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for (name, table) in self.tables.iter() {
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&(table.values.len() as u32).to_le_bytes());
+            let mut runs = Vec::new();
+            let mut values = table.values.iter().peekable();
+            while let Some(&value) = values.next() {
+                let mut run = 1u8;
+                while run < u8::MAX && values.peek() == Some(&&value) {
+                    values.next();
+                    run += 1;
+                }
+                runs.push(run);
+                runs.push(value);
+            }
+            bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&runs);
+        }
+        bytes
+    }
+
+    /** Read tables written by to_bytes */
+    pub fn from_bytes(bytes: &[u8]) -> Result<EndgameTables, ParseError> {
+        let invalid = |msg: &str| ParseError::InvalidEndgame(format!("Not an endgame table file: {}", msg));
+        if bytes.get(..4) != Some(&MAGIC[..]) {
+            return Err(invalid("it doesn't start with the right magic number."));
+        }
+        let mut tables = EndgameTables::new();
+        let mut pos = 4;
+        let mut take = |len: usize| -> Result<&[u8], ParseError> {
+            let taken = bytes.get(pos..pos + len).ok_or_else(|| invalid("it ends too soon."))?;
+            pos += len;
+            Ok(taken)
+        };
+        let number = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        while let Ok(len) = take(1) {
+            let name = core::str::from_utf8(take(len[0] as usize)?).map_err(|_| invalid("a name isn't text."))?;
+            let layout = Layout::new(name).ok_or_else(|| invalid("a name isn't an ending."))?;
+            let len = number(take(4)?);
+            let runs_len = number(take(4)?);
+            let runs = take(runs_len)?;
+            if len != layout.len() || runs.len() % 2 != 0 {
+                return Err(invalid(&format!("the table for {} is the wrong size.", name)));
+            }
+            let mut values = Vec::with_capacity(len);
+            for run in runs.chunks(2) {
+                values.extend(core::iter::repeat_n(run[1], run[0] as usize));
+            }
+            if values.len() != len {
+                return Err(invalid(&format!("the table for {} is the wrong size.", name)));
+            }
+            tables.tables.insert(layout.name(), EndgameTable { layout, values });
+        }
+        Ok(tables)
+    }
+
+    // The table named NAME, or the same with the colors swapped
+    fn find(&self, name: &str) -> Option<&EndgameTable> {
+        let swapped: Vec<&str> = name.split('v').rev().collect();
+        self.tables.get(name).or_else(|| self.tables.get(&swapped.join("v")))
+    }
+}
+
+// True if LAYOUT can't be won by either side: bare Kings, or a single Knight or Bishop
+fn is_drawn(layout: &Layout) -> bool {
+    let others: Vec<PieceType> = layout
+        .pieces
+        .iter()
+        .map(|piece| piece.piece_type())
+        .filter(|&piece_type| piece_type != King)
+        .collect();
+    matches!(others.as_slice(), [] | [Knight] | [Bishop])
+}
+
+// LAYOUT with the side with more material as White, as tables are usually named
+fn stronger_first(layout: &Layout) -> String {
+    let value = |color: Color| -> i32 {
+        layout
+            .pieces
+            .iter()
+            .filter(|piece| piece.color() == color)
+            .map(|piece| match piece.piece_type() {
+                Queen => 9,
+                Rook => 5,
+                Bishop | Knight => 3,
+                Pawn => 1,
+                King => 0,
+            })
+            .sum()
+    };
+    let name = layout.name();
+    if value(Black) > value(White) {
+        let sides: Vec<&str> = name.split('v').rev().collect();
+        sides.join("v")
+    } else {
+        name
+    }
+}
+
+// BOARD with the colors of the pieces and the player to move swapped and the rows
+// reversed, which is the same position for the other player
+fn swap_colors(board: &Board) -> Board {
+    let mut swapped = Board::empty(board.player().other(), board.turn());
+    for loc in Squares(board.occupied()) {
+        let Piece(piece_type, color) = board.get(loc).unwrap();
+        swapped.set((7 - loc.0, loc.1), Some(Piece(piece_type, color.other())));
+    }
+    swapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(tables: &EndgameTables, fen: &str) -> Option<Dtm> {
+        tables.probe(&Board::from_fen(fen).unwrap())
+    }
+
+    // The longest mate in the table named NAME
+    fn longest_mate(tables: &EndgameTables, name: &str) -> u32 {
+        let values = tables.tables[name].values.iter();
+        values.filter_map(|&value| match Dtm::from_byte(value) {
+            Dtm::Mate(plies) => Some(plies),
+            _ => None,
+        }).max().unwrap()
+    }
+
+    #[test]
+    fn test_queen_and_rook() {
+        let mut tables = EndgameTables::new();
+        tables.generate("KQvK").unwrap();
+        tables.generate("KRvK").unwrap();
+        assert_eq!(tables.names(), vec!["KQvK", "KRvK"]);
+        assert_eq!(probe(&tables, "k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"), Some(Dtm::Mated(0)));
+        assert_eq!(probe(&tables, "k7/8/1K6/8/8/8/7Q/8 w - - 0 1"), Some(Dtm::Mate(1)));
+        assert_eq!(probe(&tables, "k7/8/1K6/8/8/8/8/7R b - - 0 1"), Some(Dtm::Mated(2)));
+        // The Queen can be taken, and the same after turning the board
+        assert_eq!(probe(&tables, "8/8/8/8/8/8/1k6/Q6K b - - 0 1"), Some(Dtm::Draw));
+        assert_eq!(probe(&tables, "K6Q/6k1/8/8/8/8/8/8 b - - 0 1"), Some(Dtm::Draw));
+        // Stalemate
+        assert_eq!(probe(&tables, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(Dtm::Draw));
+        // The longest mates are known to take 10 and 16 moves
+        assert_eq!(longest_mate(&tables, "KQvK"), 19);
+        assert_eq!(longest_mate(&tables, "KRvK"), 31);
+        // With the colors swapped, and not at all with castling rights or other pieces
+        assert_eq!(probe(&tables, "8/8/8/8/8/1k6/7q/K7 b - - 0 1"), Some(Dtm::Mate(1)));
+        assert_eq!(probe(&tables, "8/8/8/8/8/8/8/R3K1k1 w Q - 0 1"), None);
+        assert_eq!(probe(&tables, "8/8/8/8/8/8/8/RN2K1k1 w - - 0 1"), None);
+        assert_eq!(probe(&tables, "8/8/8/8/8/8/8/N3K1k1 w - - 0 1"), Some(Dtm::Draw));
+
+        let board = Board::from_fen("k7/8/1K6/8/8/8/7Q/8 w - - 0 1").unwrap();
+        let mate = board.parse_uci("h2h8").unwrap();
+        assert_eq!(tables.best_move(&board), Some((mate, Dtm::Mate(1))));
+        assert_eq!(tables.line(&board), vec![mate]);
+        let board = Board::from_fen("8/8/8/3k4/8/8/8/R3K3 w - - 0 1").unwrap();
+        let line = tables.line(&board);
+        let Some(Dtm::Mate(plies)) = tables.probe(&board) else { panic!() };
+        assert_eq!(line.len(), plies as usize);
+        let end = line.iter().fold(board, |board, &mv| board.play(mv));
+        assert!(end.in_check() && end.legal_moves().is_empty());
+    }
+    #[test]
+    #[ignore] // Slow without optimizations. Run with `cargo test --release -- --ignored`
+    fn test_pawn() {
+        let mut tables = EndgameTables::new();
+        tables.generate("KPvK").unwrap();
+        assert_eq!(tables.names(), vec!["KPvK", "KQvK", "KRvK"]);
+        // With the opposition the defending King draws, without it it loses
+        assert_eq!(probe(&tables, "8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), Some(Dtm::Draw));
+        assert!(matches!(probe(&tables, "8/4k3/8/4K3/4P3/8/8/8 b - - 0 1"), Some(Dtm::Mated(_))));
+        assert_eq!(probe(&tables, "8/8/8/4p3/4k3/8/4K3/8 b - - 0 1"), Some(Dtm::Draw));
+        assert!(matches!(probe(&tables, "8/8/8/4p3/4k3/8/4K3/8 w - - 0 1"), Some(Dtm::Mated(_))));
+        // The King in front of its Pawn on the sixth row wins either way
+        assert!(matches!(probe(&tables, "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(Dtm::Mate(_))));
+        assert_eq!(probe(&tables, "4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(Dtm::Draw));
+        // A Rook's Pawn is drawn if the other King reaches the corner
+        assert_eq!(probe(&tables, "7k/8/8/8/8/8/7P/7K w - - 0 1"), Some(Dtm::Draw));
+        // Promoting to a Queen stalemates, so the best move is something else
+        let board = Board::from_fen("8/k1P5/8/1K6/8/8/8/8 w - - 0 1").unwrap();
+        let queen = board.parse_uci("c7c8q").unwrap();
+        assert_eq!(tables.probe(&board.play(queen)), Some(Dtm::Draw));
+        let Some((best, Dtm::Mate(plies))) = tables.best_move(&board) else { panic!() };
+        assert_ne!(best, queen);
+        assert_eq!(tables.probe(&board.play(best)), Some(Dtm::Mated(plies - 1)));
+    }
+    #[test]
+    #[ignore] // Slow without optimizations. Run with `cargo test --release -- --ignored`
+    fn test_four_pieces() {
+        let mut tables = EndgameTables::new();
+        tables.generate("KQvKR").unwrap();
+        assert_eq!(tables.names(), vec!["KQvK", "KQvKR", "KRvK"]);
+        // The longest win takes 35 moves
+        assert_eq!(longest_mate(&tables, "KQvKR"), 69);
+        // Philidor's position, which Black loses whoever moves
+        let philidor = "3k4/2r5/3K4/4Q3/8/8/8/8";
+        let dtm = probe(&tables, &format!("{} b - - 0 1", philidor));
+        assert!(matches!(dtm, Some(Dtm::Mated(_))));
+    }
+    #[test]
+    fn test_file() {
+        let mut tables = EndgameTables::new();
+        tables.generate("KQvK").unwrap();
+        let bytes = tables.to_bytes();
+        assert_eq!(EndgameTables::from_bytes(&bytes), Ok(tables));
+        assert!(EndgameTables::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(EndgameTables::from_bytes(b"CETX").is_err());
+        assert_eq!(EndgameTables::from_bytes(MAGIC), Ok(EndgameTables::new()));
+        // Too many pieces, or no King
+        assert!(EndgameTables::new().generate("KQQvKQ").is_err());
+        assert!(EndgameTables::new().generate("KQvQ").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+/** Why a piece cannot make a move. Display gives a message for the player. */
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveError {
+    OutOfBounds(Square),
+    EmptySquare(Square),
+    NotYourPiece(Square),
+    SquareOccupied(Square), // By a piece of the same color
+    CaptureKing(Square),
+    // The piece does not move this way, or something is in its path
+    IllegalPattern { piece: Piece, from: Square, to: Square },
+    CastlingRightLost(CastleSide),
+    CastlingRookMissing(CastleSide),
+    CastlingBlocked(CastleSide),
+    CastleOutOfCheck,
+    CastleThroughCheck(Square),
+    CastleIntoCheck(Square),
+    IntoCheck(Square), // The King moving to a square that is attacked
+    StillInCheck { checkers: Vec<Square> },
+    MustMoveKing { checkers: Vec<Square> }, // Only the King can escape a double check
+    // Moving the piece would expose the King to PINNER
+    PinnedPiece { piece: PieceType, pinner: Square },
+    PinnedInCheck { pinner: Square },
+    PinnedEnPassant { pinner: Square },
+    MissingPromotion,
+    InvalidPromotion(PieceType),
+    UnexpectedPromotion,
+    MustCapture, // In variants where capturing is compulsory
+    EmptyPocket(PieceType), // No piece of this type to drop
+    PawnDropRow(Square),    // Pawns cannot be dropped on the first or last row
+}
+
+/** Why text could not be read as a square, piece, move, position, game or book */
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    InvalidSquare(String),
+    InvalidPiece(String),
+    InvalidFen(String),   // Explains which field is wrong
+    InvalidBoard(String), // In the format written by the CLI
+    NotSan(String),
+    NotLongAlgebraic(String),
+    NoPieceCanMove { notation: String, piece: PieceType, to: Square },
+    IllegalMove { notation: String, error: MoveError },
+    AmbiguousMove { notation: String, options: Vec<String> },
+    InvalidPgn(String),
+    InvalidBook(String), // A Polyglot opening book
+    InvalidEndgame(String), // An endgame table or its file
+    // An error within the GAMEth game of a PGN file, counting from 1
+    Pgn { game: usize, move_number: Option<String>, error: Box<ParseError> },
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MoveError::OutOfBounds(loc) => {
+                write!(f, "{} {} is out of bounds.", loc.0 + 1, loc.1 + 1)
+            }
+            MoveError::EmptySquare(_) => write!(f, "No piece exists in this location."),
+            MoveError::NotYourPiece(_) => write!(f, "This piece does not belong to you."),
+            MoveError::SquareOccupied(_) => write!(f, "Can't move here. Square occupied."),
+            MoveError::CaptureKing(_) => write!(f, "Cannot capture the King."),
+            MoveError::IllegalPattern { .. } => write!(f, "Invalid move."),
+            MoveError::CastlingRightLost(_) => {
+                write!(f, "Cannot castle. The King or Rook has already moved.")
+            }
+            MoveError::CastlingRookMissing(_) => write!(f, "Cannot castle. The Rook is missing."),
+            MoveError::CastlingBlocked(_) => write!(f, "Cannot castle. Pieces are in the way."),
+            MoveError::CastleOutOfCheck => write!(f, "Cannot castle out of check."),
+            MoveError::CastleThroughCheck(_) => write!(f, "Cannot castle through check."),
+            MoveError::CastleIntoCheck(_) => write!(f, "Cannot castle into check."),
+            MoveError::IntoCheck(_) => write!(f, "King cannot place himself in check."),
+            MoveError::StillInCheck { .. } => write!(f, "King is still in check"),
+            MoveError::MustMoveKing { .. } => write!(f, "Must move King out of check"),
+            MoveError::PinnedPiece { piece: Knight, .. } => {
+                write!(f, "This Knight is pinned. It cannot be moved.")
+            }
+            MoveError::PinnedPiece { .. } => {
+                write!(f, "This piece is pinned. It cannot be moved in this direction.")
+            }
+            MoveError::PinnedInCheck { .. } => write!(
+                f,
+                "This piece is pinned. Move another piece to get King out of check."
+            ),
+            MoveError::PinnedEnPassant { .. } => {
+                write!(f, "This piece is pinned. It cannot capture en passant.")
+            }
+            MoveError::MissingPromotion => write!(f, "Must choose a piece to promote the Pawn to."),
+            MoveError::InvalidPromotion(_) => write!(
+                f,
+                "A Pawn can only be promoted to a Queen, Rook, Bishop or Knight."
+            ),
+            MoveError::UnexpectedPromotion => {
+                write!(f, "Only a Pawn reaching the opposite row can be promoted.")
+            }
+            MoveError::MustCapture => write!(f, "You must capture a piece when you can."),
+            MoveError::EmptyPocket(piece_type) => {
+                write!(f, "You have no {} in your pocket to drop.", piece_type)
+            }
+            MoveError::PawnDropRow(_) => {
+                write!(f, "A Pawn cannot be dropped on the first or last row.")
+            }
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidSquare(s) => write!(f, "{} is not a square.", s),
+            ParseError::InvalidPiece(_) => write!(f, "Not a recognized piece"),
+            ParseError::InvalidFen(msg)
+            | ParseError::InvalidBoard(msg)
+            | ParseError::InvalidPgn(msg)
+            | ParseError::InvalidBook(msg)
+            | ParseError::InvalidEndgame(msg) => write!(f, "{}", msg),
+            ParseError::NotSan(s) => {
+                write!(f, "'{}' is not written in Standard Algebraic Notation.", s)
+            }
+            ParseError::NotLongAlgebraic(s) => {
+                write!(f, "'{}' is not a move in long algebraic notation.", s)
+            }
+            ParseError::NoPieceCanMove {
+                notation,
+                piece,
+                to,
+            } => write!(
+                f,
+                "{} is not a valid move. No {} can move to {}.",
+                notation,
+                piece,
+                square_name(*to)
+            ),
+            ParseError::IllegalMove { notation, error } => {
+                write!(f, "{} is not a valid move. {}", notation, error)
+            }
+            ParseError::AmbiguousMove { notation, options } => write!(
+                f,
+                "{} is ambiguous. It could mean {}.",
+                notation,
+                options.join(" or ")
+            ),
+            ParseError::Pgn {
+                game,
+                move_number: Some(number),
+                error,
+            } => write!(f, "PGN game {}: Move {} {}", game, number, error),
+            ParseError::Pgn { game, error, .. } => write!(f, "PGN game {}: {}", game, error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MoveError {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::IllegalMove { error, .. } => Some(error),
+            ParseError::Pgn { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_move_errors() {
+        // The Bishop on d2 is pinned by the Queen on b4
+        let board = Board::from_fen("4k3/8/8/8/1q6/8/3B4/4K2R w K - 0 1").unwrap();
+        let err = board.validate_move((1, 3), (2, 4), None).unwrap_err();
+        assert_eq!(
+            err,
+            MoveError::PinnedPiece {
+                piece: Bishop,
+                pinner: (3, 1)
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "This piece is pinned. It cannot be moved in this direction."
+        );
+        assert_eq!(
+            board.validate_from((7, 4), None),
+            Err(MoveError::NotYourPiece((7, 4)))
+        );
+        assert_eq!(
+            board.validate_move((0, 7), (0, 8), None),
+            Err(MoveError::OutOfBounds((0, 8)))
+        );
+        assert!(matches!(
+            board.validate_move((0, 7), (2, 5), None),
+            Err(MoveError::IllegalPattern { piece: Piece(Rook, White), .. })
+        ));
+
+        // The Rook on e8 gives check
+        let board = Board::from_fen("4r1k1/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        assert_eq!(
+            board.validate_move((0, 0), (0, 1), None),
+            Err(MoveError::StillInCheck {
+                checkers: vec![(7, 4)]
+            })
+        );
+        assert_eq!(
+            board.validate_move((0, 4), (0, 2), None),
+            Err(MoveError::CastleOutOfCheck)
+        );
+    }
+}
@@ -0,0 +1,422 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+use core::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+/**
+A score in the middlegame and in the endgame, in centipawns. The two are blended
+by how much material is left on the board, so a term can matter more in one phase.
+*/
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Weight(pub i32, pub i32);
+
+impl Add for Weight {
+    type Output = Weight;
+    fn add(self, other: Weight) -> Weight {
+        Weight(self.0 + other.0, self.1 + other.1)
+    }
+}
+impl AddAssign for Weight {
+    fn add_assign(&mut self, other: Weight) {
+        *self = *self + other;
+    }
+}
+impl Sub for Weight {
+    type Output = Weight;
+    fn sub(self, other: Weight) -> Weight {
+        self + -other
+    }
+}
+impl Neg for Weight {
+    type Output = Weight;
+    fn neg(self) -> Weight {
+        Weight(-self.0, -self.1)
+    }
+}
+impl Mul<i32> for Weight {
+    type Output = Weight;
+    fn mul(self, times: i32) -> Weight {
+        Weight(self.0 * times, self.1 * times)
+    }
+}
+
+/**
+Every weight used by Board::evaluate_with. Arrays indexed by PieceType hold one
+entry per type of piece. The default weights are a reasonable starting point for tuning.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalConfig {
+    pub material: [Weight; 6], // The King's value is never counted
+    // Bonus for each piece on each square, from White's side of the board with a8 first
+    // and h1 last. Black's pieces use the same tables flipped.
+    pub piece_square: [[Weight; 64]; 6],
+    // Bonus for each square a piece can move to that is not attacked by an enemy Pawn
+    pub mobility: [Weight; 6],
+    pub doubled_pawn: Weight, // For each Pawn behind another of the same color
+    pub isolated_pawn: Weight, // For each Pawn with no friendly Pawns on either side file
+    pub passed_pawn: [Weight; 8], // By how many rows the Pawn has advanced
+    pub king_shield: Weight, // For each friendly Pawn in the two rows in front of the King
+    pub king_attack: Weight, // For each enemy attack on the King or the squares around it
+    // How much each piece counts towards the middlegame. With all pieces on the board the
+    // middlegame weights are used, and with none of them the endgame weights.
+    pub phase: [i32; 6],
+}
+
+// The tables below are laid out as the board is seen by White, with a8 first
+#[rustfmt::skip]
+const PAWN_MIDDLEGAME: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const PAWN_ENDGAME: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    15, 15, 15, 15, 15, 15, 15, 15,
+     5,  5,  5,  5,  5,  5,  5,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+// The King hides behind its Pawns while the Queens are on, then heads for the center
+#[rustfmt::skip]
+const KING_MIDDLEGAME: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+#[rustfmt::skip]
+const KING_ENDGAME: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+// Pair up a middlegame and an endgame table
+const fn taper(middlegame: &[i32; 64], endgame: &[i32; 64]) -> [Weight; 64] {
+    let mut table = [Weight(0, 0); 64];
+    let mut i = 0;
+    while i < 64 {
+        table[i] = Weight(middlegame[i], endgame[i]);
+        i += 1;
+    }
+    table
+}
+
+impl EvalConfig {
+    pub const DEFAULT: EvalConfig = EvalConfig {
+        material: [
+            Weight(82, 94),
+            Weight(477, 512),
+            Weight(337, 281),
+            Weight(365, 297),
+            Weight(1025, 936),
+            Weight(0, 0),
+        ],
+        piece_square: [
+            taper(&PAWN_MIDDLEGAME, &PAWN_ENDGAME),
+            taper(&ROOK_TABLE, &ROOK_TABLE),
+            taper(&KNIGHT_TABLE, &KNIGHT_TABLE),
+            taper(&BISHOP_TABLE, &BISHOP_TABLE),
+            taper(&QUEEN_TABLE, &QUEEN_TABLE),
+            taper(&KING_MIDDLEGAME, &KING_ENDGAME),
+        ],
+        mobility: [
+            Weight(0, 0),
+            Weight(2, 4),
+            Weight(4, 4),
+            Weight(5, 5),
+            Weight(1, 2),
+            Weight(0, 0),
+        ],
+        doubled_pawn: Weight(-10, -20),
+        isolated_pawn: Weight(-15, -10),
+        passed_pawn: [
+            Weight(0, 0),
+            Weight(5, 10),
+            Weight(5, 15),
+            Weight(10, 25),
+            Weight(20, 45),
+            Weight(35, 75),
+            Weight(60, 120),
+            Weight(0, 0),
+        ],
+        king_shield: Weight(10, 0),
+        king_attack: Weight(-8, -2),
+        phase: [0, 2, 1, 1, 4, 0],
+    };
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        EvalConfig::DEFAULT
+    }
+}
+
+// How many of each type of piece both players start the game with
+const STARTING_COUNTS: [i32; 6] = [16, 4, 4, 4, 2, 2];
+
+// Every square on column COL
+const fn file(col: isize) -> Bitboard {
+    0x0101_0101_0101_0101 << col
+}
+
+// Every square on the columns beside COL
+fn adjacent_files(col: isize) -> Bitboard {
+    let left = if col > 0 { file(col - 1) } else { 0 };
+    let right = if col < 7 { file(col + 1) } else { 0 };
+    left | right
+}
+
+// Every square on the rows in front of ROW, as seen by PLAYER
+fn rows_ahead(player: Color, row: isize) -> Bitboard {
+    match player {
+        White if row < 7 => !0 << (8 * (row + 1)),
+        Black => (1 << (8 * row)) - 1,
+        _ => 0,
+    }
+}
+
+impl Board {
+    /**
+    Scores the position in centipawns for the player to move, using the default weights.
+    Positive scores are good for them.
+    */
+    pub fn evaluate(&self) -> i32 {
+        self.evaluate_with(&EvalConfig::DEFAULT)
+    }
+
+    /** Scores the position in centipawns for the player to move, using CONFIG's weights */
+    pub fn evaluate_with(&self, config: &EvalConfig) -> i32 {
+        let white = self.side_score(White, config);
+        let black = self.side_score(Black, config);
+        let Weight(middlegame, endgame) = white - black;
+
+        // How much of the material needed for the middlegame is still on the board
+        let count = |piece_type| self.pieces[piece_type as usize].count_ones() as i32;
+        let phase: i32 = PIECE_TYPES
+            .iter()
+            .map(|&piece_type| count(piece_type) * config.phase[piece_type as usize])
+            .sum();
+        let full: i32 = PIECE_TYPES
+            .iter()
+            .map(|&piece_type| STARTING_COUNTS[piece_type as usize] * config.phase[piece_type as usize])
+            .sum();
+        let phase = phase.min(full);
+        let score = if full == 0 {
+            endgame
+        } else {
+            (middlegame * phase + endgame * (full - phase)) / full
+        };
+        match self.player {
+            White => score,
+            Black => -score,
+        }
+    }
+
+    // The sum of every weight that applies to PLAYER's pieces
+    fn side_score(&self, player: Color, config: &EvalConfig) -> Weight {
+        let own = self.colors[player as usize];
+        let occupied = self.occupied();
+        let pawns = self.piece_bitboard(Piece(Pawn, player));
+        let enemy_pawns = self.piece_bitboard(Piece(Pawn, player.other()));
+        let enemy_pawn_attacks = Squares(enemy_pawns).fold(0, |attacks, loc| {
+            attacks | PAWN_ATTACKS[player.other() as usize][square_index(loc)]
+        });
+
+        let mut score = Weight(0, 0);
+        for loc in Squares(own) {
+            let piece_type = self.get(loc).unwrap().piece_type();
+            let index = square_index(loc);
+            // The tables are laid out from White's side with a8 first
+            let table_index = match player {
+                White => (7 - loc.0) * 8 + loc.1,
+                Black => loc.0 * 8 + loc.1,
+            } as usize;
+            score += config.material[piece_type as usize];
+            score += config.piece_square[piece_type as usize][table_index];
+            let moves = match piece_type {
+                Knight => KNIGHT_ATTACKS[index],
+                Bishop => bishop_attacks(index, occupied),
+                Rook => rook_attacks(index, occupied),
+                Queen => queen_attacks(index, occupied),
+                Pawn | King => 0,
+            };
+            let safe_moves = moves & !own & !enemy_pawn_attacks;
+            score += config.mobility[piece_type as usize] * safe_moves.count_ones() as i32;
+        }
+
+        // Pawn structure
+        for col in 0..8 {
+            let on_file = (pawns & file(col)).count_ones() as i32;
+            if on_file > 1 {
+                score += config.doubled_pawn * (on_file - 1);
+            }
+            if on_file > 0 && pawns & adjacent_files(col) == 0 {
+                score += config.isolated_pawn * on_file;
+            }
+        }
+        for loc in Squares(pawns) {
+            let front_span = (file(loc.1) | adjacent_files(loc.1)) & rows_ahead(player, loc.0);
+            if enemy_pawns & front_span == 0 {
+                let advanced = (loc.0 - player.home_row()).abs();
+                score += config.passed_pawn[advanced as usize];
+            }
+        }
+
+        // King safety
+        if let Some(king) = self.find_king(Some(player)) {
+            let forward = match player {
+                White => 1,
+                Black => -1,
+            };
+            let shield_rows = [king.0 + forward, king.0 + 2 * forward]
+                .iter()
+                .filter(|&&row| (0..8).contains(&row))
+                .fold(0, |rows, &row| rows | 0xFF << (8 * row));
+            let shield = shield_rows & (file(king.1) | adjacent_files(king.1)) & pawns;
+            score += config.king_shield * shield.count_ones() as i32;
+            let zone = KING_ATTACKS[square_index(king)] | square_bit(king);
+            let attacks: u32 = Squares(zone)
+                .map(|loc| {
+                    self.attackers(square_index(loc), player.other(), occupied)
+                        .count_ones()
+                })
+                .sum();
+            score += config.king_attack * attacks as i32;
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same position with the colors swapped and the board turned around
+    fn flip(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect()
+        };
+        let rows: Vec<&str> = fields[0].split('/').rev().collect();
+        let player = if fields[1] == "w" { "b" } else { "w" };
+        format!("{} {} - - 0 1", swap_case(&rows.join("/")), player)
+    }
+    fn evaluate(fen: &str) -> i32 {
+        Board::from_fen(fen).unwrap().evaluate()
+    }
+    #[test]
+    fn test_symmetry() {
+        assert_eq!(Board::new().evaluate(), 0);
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w - - 1 8",
+        ];
+        for fen in fens.iter() {
+            assert_eq!(evaluate(fen), evaluate(&flip(fen)), "{}", fen);
+        }
+    }
+    #[test]
+    fn test_material() {
+        // White is a Knight up, which is good for White and bad for Black
+        let fen = "rnbqkb1r/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(evaluate(fen) > 250);
+        assert_eq!(evaluate(fen), -evaluate(&fen.replace(" w ", " b ")));
+    }
+    #[test]
+    fn test_pawn_structure() {
+        // A passed Pawn beats one blocked by an enemy Pawn on the next file
+        let passed = evaluate("4k3/p7/8/3P4/8/8/8/4K3 w - - 0 1");
+        let blocked = evaluate("4k3/4p3/8/3P4/8/8/8/4K3 w - - 0 1");
+        assert!(passed > blocked);
+        // Doubled, isolated Pawns are worth less than Pawns side by side
+        let doubled = evaluate("4k3/8/8/8/3P4/3P4/8/4K3 w - - 0 1");
+        let connected = evaluate("4k3/8/8/8/8/3PP3/8/4K3 w - - 0 1");
+        assert!(connected > doubled);
+    }
+    #[test]
+    fn test_custom_weights() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let mut config = EvalConfig {
+            piece_square: [[Weight(0, 0); 64]; 6],
+            mobility: [Weight(0, 0); 6],
+            king_attack: Weight(0, 0),
+            // With no pieces left to count towards the middlegame, this is an endgame
+            phase: [0, 0, 1, 1, 4, 0],
+            ..EvalConfig::default()
+        };
+        config.material[Rook as usize] = Weight(400, 600);
+        assert_eq!(board.evaluate_with(&config), 600);
+    }
+}
@@ -1,76 +1,111 @@
 // Jack Alpert 2020
 
 use crate::board::*;
-use std::str::FromStr;
+use core::str::FromStr;
 
+#[cfg(feature = "std")]
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+#[cfg(feature = "std")]
+use rand::{thread_rng, Rng};
 
 impl Board {
     pub fn new() -> Board {
-        Board {
-            player: White,
-            turn_no: 0,
-            data: [
-                [
-                    Some(Piece(Rook, White)), // Row 1
-                    Some(Piece(Knight, White)),
-                    Some(Piece(Bishop, White)),
-                    Some(Piece(Queen, White)),
-                    Some(Piece(King, White)),
-                    Some(Piece(Bishop, White)),
-                    Some(Piece(Knight, White)),
-                    Some(Piece(Rook, White)),
-                ],
-                [Some(Piece(Pawn, White)); 8], // Row 2
-                [None; 8],                     // Row 3
-                [None; 8],                     // Row 4
-                [None; 8],                     // Row 5
-                [None; 8],                     // Row 6
-                [Some(Piece(Pawn, Black)); 8], // Row 7
-                [
-                    Some(Piece(Rook, Black)), // Row 8
-                    Some(Piece(Knight, Black)),
-                    Some(Piece(Bishop, Black)),
-                    Some(Piece(Queen, Black)),
-                    Some(Piece(King, Black)),
-                    Some(Piece(Bishop, Black)),
-                    Some(Piece(Knight, Black)),
-                    Some(Piece(Rook, Black)),
-                ],
-            ],
+        let mut board = Board::empty(White, 1);
+        // Rooks, Knights and Bishops on either side of the Queen and King
+        let back_row = [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook];
+        for (col, &piece_type) in back_row.iter().enumerate() {
+            let col = col as isize;
+            board
+                .set((0, col), Some(Piece(piece_type, White))) // Row 1
+                .set((1, col), Some(Piece(Pawn, White))) // Row 2
+                .set((6, col), Some(Piece(Pawn, Black))) // Row 7
+                .set((7, col), Some(Piece(piece_type, Black))); // Row 8
+        }
+        board.infer_castling_rights();
+        board
+    }
+    /**
+    The Chess960 starting position numbered INDEX, from 0 to 959, as in the Scharnagl
+    numbering, where 518 is the standard position. None if INDEX is 960 or more.
+    */
+    pub fn new_chess960(index: u32) -> Option<Board> {
+        if index >= 960 {
+            return None;
+        }
+        let mut back_row = [None; 8];
+        let mut n = index as usize;
+        // One Bishop on a light square and one on a dark square
+        back_row[n % 4 * 2 + 1] = Some(Bishop);
+        n /= 4;
+        back_row[n % 4 * 2] = Some(Bishop);
+        n /= 4;
+        // Then the Queen and Knights on the empty squares that are left
+        let mut place = |piece_type, nth: usize| {
+            let col = back_row.iter().enumerate().filter(|(_, p)| p.is_none()).nth(nth).unwrap().0;
+            back_row[col] = Some(piece_type);
+        };
+        place(Queen, n % 6);
+        n /= 6;
+        // Which two of the five squares left each number gives the Knights
+        let knights = [
+            (0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4),
+        ];
+        let (first, second) = knights[n];
+        place(Knight, second);
+        place(Knight, first);
+        // And the King between the Rooks
+        for &piece_type in &[Rook, King, Rook] {
+            place(piece_type, 0);
+        }
+        let mut board = Board::empty(White, 1);
+        for (col, piece_type) in back_row.iter().enumerate() {
+            let col = col as isize;
+            board
+                .set((0, col), piece_type.map(|p| Piece(p, White)))
+                .set((1, col), Some(Piece(Pawn, White)))
+                .set((6, col), Some(Piece(Pawn, Black)))
+                .set((7, col), piece_type.map(|p| Piece(p, Black)));
+        }
+        let rooks: Vec<isize> = (0..8).filter(|&col| back_row[col as usize] == Some(Rook)).collect();
+        for &color in &[White, Black] {
+            board.castling[color as usize] = [Some(rooks[1]), Some(rooks[0])];
         }
+        board.chess960 = true;
+        Some(board)
     }
-    pub fn empty(player: Color, turn_no: u8) -> Board {
+    /** A Chess960 starting position chosen at random */
+    #[cfg(feature = "std")]
+    pub fn random_chess960() -> Board {
+        Board::new_chess960(thread_rng().gen_range(0, 960)).unwrap()
+    }
+    /** A board with no pieces and PLAYER to move. A FULLMOVE of 0 is taken as 1. */
+    pub fn empty(player: Color, fullmove: u32) -> Board {
         Board {
-            player: player,
-            turn_no: turn_no,
-            data: [[None; 8]; 8],
+            player,
+            fullmove: fullmove.max(1),
+            halfmove_clock: 0,
+            castling: [[None; 2]; 2],
+            chess960: false,
+            en_passant: None,
+            checks: [0; 2],
+            pockets: [[0; 5]; 2],
+            promoted: 0,
+            pieces: [0; 6],
+            colors: [0; 2],
+            piece_hash: 0,
         }
     }
-    pub fn random_move(board: &Board) -> (Square, Square) {
-        let mut rng = thread_rng();
-        //
-        // Choose a piece at random
-        let mut from_sqaures: Vec<Square> = board.find_pieces(None);
-        from_sqaures.shuffle(&mut rng);
-        for from in from_sqaures {
-            //
-            // Choose a valid TO at random
-            let to_squares: Vec<Square> = iproduct!(0..8, 0..8)
-                .filter(|&loc| board.validate_move(from, loc, None).is_ok())
-                .collect();
-            // If TO_SQAURES is Empty, no valid moves exist between FROM and TO
-            // Try another FROM
-            if let Some(&to) = to_squares.choose(&mut rng) {
-                return (from, to);
-            }
+    #[cfg(feature = "std")]
+    pub fn random_move(board: &Board) -> Move {
+        let moves = board.legal_moves();
+        match moves.choose(&mut thread_rng()) {
+            Some(&mv) => mv,
+            None => panic!(
+                "On turn {}, {} has no valid moves",
+                board.turn(),
+                board.player()
+            ),
         }
-        panic!(
-            "On turn {}, {} has no valid moves",
-            board.turn(),
-            board.player()
-        );
     }
     // pub fn later_board() -> Board {
     //     let mut board = Board::empty(White, 25);
@@ -92,32 +127,45 @@ impl Board {
     // }
 }
 
+impl Default for Board {
+    fn default() -> Board {
+        Board::new()
+    }
+}
+
 impl FromStr for Board {
-    type Err = String;
+    type Err = ParseError;
 
-    fn from_str(contents: &str) -> std::result::Result<Self, <Self as FromStr>::Err> {
+    fn from_str(contents: &str) -> core::result::Result<Self, <Self as FromStr>::Err> {
         let mut lines = contents.lines();
         // Read the player
         let player: Color = match lines.next() {
             Some("White") => White,
             Some("Black") => Black,
-            _ => return Err("Couldn't parse player!".to_string()),
+            _ => return Err(ParseError::InvalidBoard(String::from("Couldn't parse player!"))),
         };
         //
-        // Read the turn number
-        let turn_no: u8 = lines
+        // Read the fullmove number, optionally followed by the halfmove clock
+        let mut counters = lines.next().unwrap_or("").split_whitespace();
+        let fullmove: u32 = counters
             .next()
-            .map(|s| s.parse().ok())
-            .flatten()
-            .ok_or("Couldn't parse turn number!".to_string())?;
+            .and_then(|s| s.parse().ok())
+            .filter(|&n| n > 0)
+            .ok_or_else(|| ParseError::InvalidBoard(String::from("Couldn't parse turn number!")))?;
+        let halfmove_clock: u32 = match counters.next() {
+            Some(s) => s
+                .parse()
+                .map_err(|_| ParseError::InvalidBoard(String::from("Couldn't parse halfmove clock!")))?,
+            None => 0,
+        };
         //
         // Read the board setup
         let (mut num_white_kings, mut num_black_kings) = (0, 0);
-        let mut data: [[Option<Piece>; 8]; 8] = [[None; 8]; 8];
+        let mut board = Board::empty(player, fullmove);
         for (line, row) in lines.zip(0..8) {
             for (piece, col) in line.split_whitespace().zip(0..8) {
                 let piece = piece.parse().ok();
-                data[row][col] = piece;
+                board.set((row, col), piece);
                 if Some(Piece(King, White)) == piece {
                     num_white_kings += 1;
                 } else if Some(Piece(King, Black)) == piece {
@@ -127,13 +175,14 @@ impl FromStr for Board {
         }
 
         if (num_white_kings, num_black_kings) == (1, 1) {
-            Ok(Board {
-                player,
-                turn_no,
-                data,
-            })
+            board.halfmove_clock = halfmove_clock;
+            // This format does not record castling rights
+            board.infer_castling_rights();
+            Ok(board)
         } else {
-            Err(String::from("Wrong number of Kings on the board."))
+            Err(ParseError::InvalidBoard(String::from(
+                "Wrong number of Kings on the board.",
+            )))
         }
     }
 }
@@ -0,0 +1,492 @@
+// Jack Alpert 2020
+
+use crate::board::path::*;
+use crate::board::*;
+
+impl Piece {
+    // Letter for this piece in FEN. Uppercase for White, lowercase for Black.
+    pub fn fen_char(&self) -> char {
+        let c = match self.piece_type() {
+            Pawn => 'p',
+            Rook => 'r',
+            Knight => 'n',
+            Bishop => 'b',
+            Queen => 'q',
+            King => 'k',
+        };
+        match self.color() {
+            White => c.to_ascii_uppercase(),
+            Black => c,
+        }
+    }
+    pub fn from_fen_char(c: char) -> Option<Piece> {
+        let color = if c.is_ascii_uppercase() { White } else { Black };
+        let piece_type = match c.to_ascii_lowercase() {
+            'p' => Pawn,
+            'r' => Rook,
+            'n' => Knight,
+            'b' => Bishop,
+            'q' => Queen,
+            'k' => King,
+            _ => return None,
+        };
+        Some(Piece(piece_type, color))
+    }
+}
+
+impl Board {
+    /**
+    Parse a position in Forsyth–Edwards Notation. The halfmove clock and fullmove
+    number may be left off, in which case they default to 0 and 1.
+    Returns an error naming the field that could not be parsed.
+    */
+    pub fn from_fen(fen: &str) -> Result<Board, ParseError> {
+        let board = Self::parse_fen(fen, true).map_err(ParseError::InvalidFen)?;
+        board
+            .check_variant_fields(false, false)
+            .map_err(ParseError::InvalidFen)?;
+        Ok(board)
+    }
+
+    // Returns an error if the FEN gave what only some variants use: pieces in pockets or
+    // marked as promoted, unless POCKETS, or checks given, unless CHECKS
+    pub(crate) fn check_variant_fields(&self, pockets: bool, checks: bool) -> Result<(), String> {
+        if !pockets && (self.pockets != [[0; 5]; 2] || self.promoted != 0) {
+            return Err(String::from(
+                "FEN pockets and promoted pieces ('~') are only used in Crazyhouse.",
+            ));
+        }
+        if !checks && self.checks != [0; 2] {
+            return Err(String::from(
+                "FEN checks given are only used in Three-check.",
+            ));
+        }
+        Ok(())
+    }
+
+    // Parse FEN, returning a message explaining which field is invalid.
+    // Unless STANDARD_SETUP, any number of Kings is allowed and Pawns may stand on any row,
+    // leaving variants to check the setup themselves.
+    pub(crate) fn parse_fen(fen: &str, standard_setup: bool) -> Result<Board, String> {
+        let mut fields: Vec<&str> = fen.split_whitespace().collect();
+        // In Three-check, the checks each player has given may follow the move counters,
+        // e.g. +1+0, or the checks each has left come before them, e.g. 2+3
+        let checks_field = fields.iter().skip(4).position(|field| field.contains('+'));
+        let checks_field = checks_field.map(|i| fields.remove(4 + i));
+        if fields.len() != 6 && fields.len() != 4 {
+            return Err(format!(
+                "FEN must have 6 fields, or 4 without the move counters. Found {}.",
+                fields.len()
+            ));
+        }
+        //
+        // Active color
+        let player = match fields[1] {
+            "w" => White,
+            "b" => Black,
+            s => {
+                return Err(format!(
+                    "FEN active color must be 'w' or 'b', found '{}'.",
+                    s
+                ))
+            }
+        };
+        //
+        // Move counters
+        let halfmove_clock: u32 = match fields.get(4) {
+            Some(s) => s
+                .parse()
+                .map_err(|_| format!("FEN halfmove clock '{}' is not a valid number.", s))?,
+            None => 0,
+        };
+        let fullmove: u32 = match fields.get(5) {
+            Some(s) => s.parse().ok().filter(|&n| n > 0).ok_or(format!(
+                "FEN fullmove number '{}' is not a valid number.",
+                s
+            ))?,
+            None => 1,
+        };
+        let mut board = Board::empty(player, fullmove);
+        board.halfmove_clock = halfmove_clock;
+        //
+        // Checks given
+        if let Some(field) = checks_field {
+            let (given, counts) = match field.strip_prefix('+') {
+                Some(counts) => (true, counts),
+                None => (false, field),
+            };
+            let counts: Option<Vec<u8>> = counts
+                .split('+')
+                .map(|n| n.parse().ok().filter(|&n| n <= CHECKS_TO_WIN))
+                .collect();
+            board.checks = match counts.as_deref() {
+                Some(&[white, black]) if given => [white, black],
+                Some(&[white, black]) => [CHECKS_TO_WIN - white, CHECKS_TO_WIN - black],
+                _ => {
+                    return Err(format!(
+                        "FEN checks '{}' must be those given, e.g. +1+0, or those left, e.g. 2+3.",
+                        field
+                    ))
+                }
+            };
+        }
+        //
+        // Piece placement, starting from row 8, followed in Crazyhouse by the pieces in
+        // each pocket in brackets, e.g. [QNpp]
+        let (placement, pockets) = match fields[0].split_once('[') {
+            Some((placement, pockets)) => match pockets.strip_suffix(']') {
+                Some(pockets) => (placement, pockets),
+                None => return Err(String::from("FEN pockets must end with ']'.")),
+            },
+            None => (fields[0], ""),
+        };
+        for c in pockets.chars() {
+            match Piece::from_fen_char(c) {
+                Some(Piece(piece_type, color)) if piece_type != King => {
+                    let count = &mut board.pockets[color as usize][piece_type as usize];
+                    *count = count
+                        .checked_add(1)
+                        .ok_or_else(|| format!("FEN pockets have too many of '{}'.", c))?;
+                }
+                _ => return Err(format!("FEN pockets have unrecognized piece '{}'.", c)),
+            }
+        }
+        let rows: Vec<&str> = placement.split('/').collect();
+        if rows.len() != 8 {
+            return Err(format!(
+                "FEN piece placement must have 8 rows, found {}.",
+                rows.len()
+            ));
+        }
+        for (pieces, row) in rows.iter().zip((0..8).rev()) {
+            let mut col = 0;
+            let mut after_empty = false;
+            for c in pieces.chars() {
+                let empty = c.to_digit(10).filter(|n| (1..=8).contains(n));
+                // A run of empty squares is written as a single digit
+                if empty.is_some() && after_empty {
+                    return Err(format!(
+                        "FEN piece placement row {} has two numbers in a row.",
+                        row + 1
+                    ));
+                }
+                after_empty = empty.is_some();
+                if let Some(empty) = empty {
+                    col += empty as isize;
+                } else if c == '~' && col > 0 && board.get((row, col - 1)).is_some() {
+                    // The piece before was promoted from a Pawn, in Crazyhouse
+                    board.promoted |= square_bit((row, col - 1));
+                } else if let Some(piece) = Piece::from_fen_char(c) {
+                    if col < 8 {
+                        board.set((row, col), Some(piece));
+                    }
+                    col += 1;
+                } else {
+                    return Err(format!(
+                        "FEN piece placement has unrecognized piece '{}' in row {}.",
+                        c,
+                        row + 1
+                    ));
+                }
+            }
+            if col != 8 {
+                return Err(format!(
+                    "FEN piece placement row {} describes {} squares instead of 8.",
+                    row + 1,
+                    col
+                ));
+            }
+        }
+        if standard_setup {
+            for &color in &[White, Black] {
+                let num_kings = board
+                    .find_pieces(Some(color))
+                    .into_iter()
+                    .filter(|&loc| board.get(loc) == Some(Piece(King, color)))
+                    .count();
+                if num_kings != 1 {
+                    return Err(format!(
+                        "FEN must have one {} King, found {}.",
+                        color, num_kings
+                    ));
+                }
+            }
+            for col in 0..8 {
+                for &row in &[0, 7] {
+                    if board
+                        .get((row, col))
+                        .is_some_and(|p| p.piece_type() == Pawn)
+                    {
+                        return Err(format!(
+                            "FEN piece placement has a Pawn on {}.",
+                            square_name((row, col))
+                        ));
+                    }
+                }
+            }
+        }
+        //
+        // Castling rights: KQkq for the outermost Rooks, or the Rooks' columns as in
+        // Shredder-FEN and X-FEN, e.g. HAha
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let color = if c.is_ascii_uppercase() { White } else { Black };
+                let row = color.home_row();
+                let king = board.find_king(Some(color));
+                let is_rook = |&col: &isize| board.get((row, col)) == Some(Piece(Rook, color));
+                let king_col = king.map_or(KING_HOME_COL, |king| king.1);
+                let rook_col = match c.to_ascii_lowercase() {
+                    'k' => (king_col + 1..8).rev().find(is_rook),
+                    'q' => (0..king_col).find(is_rook),
+                    file @ 'a'..='h' => Some(file as isize - 'a' as isize).filter(is_rook),
+                    _ => {
+                        return Err(format!(
+                            "FEN castling availability has unrecognized right '{}'.",
+                            c
+                        ))
+                    }
+                };
+                let (king, rook_col) = match (king, rook_col) {
+                    (Some(king), Some(col)) if king.0 == row && col != king.1 => (king, col),
+                    _ => {
+                        return Err(format!(
+                            "FEN castling availability has '{}', but the King and Rook are not on their home squares.",
+                            c
+                        ))
+                    }
+                };
+                let side = if rook_col > king.1 { KingSide } else { QueenSide };
+                if board.can_castle(color, side) {
+                    return Err(format!(
+                        "FEN castling availability lists '{}' more than once.",
+                        c
+                    ));
+                }
+                board.castling[color as usize][side as usize] = Some(rook_col);
+                // Only Chess960 castles from anywhere else
+                if king.1 != KING_HOME_COL || rook_col != side.rook_from() {
+                    board.chess960 = true;
+                }
+            }
+        }
+        //
+        // En passant target square
+        if fields[3] != "-" {
+            let target = parse_square(fields[3])
+                .map_err(|_| format!("FEN en passant target '{}' is not a square.", fields[3]))?;
+            // The target is the square behind a Pawn of the other player that just moved two
+            // squares, so it and the square the Pawn started from are empty
+            let (target_row, pawn_row) = match player {
+                White => (5, 4),
+                Black => (2, 3),
+            };
+            let start_row = 2 * target_row - pawn_row;
+            if target.0 != target_row
+                || board.get((pawn_row, target.1)) != Some(Piece(Pawn, player.other()))
+                || board.get(target).is_some()
+                || board.get((start_row, target.1)).is_some()
+            {
+                return Err(format!(
+                    "FEN en passant target {} does not follow a Pawn's two-square advance.",
+                    fields[3]
+                ));
+            }
+            board.en_passant = Some(target);
+        }
+        Ok(board)
+    }
+
+    /**
+    Describe this position in Forsyth–Edwards Notation. Castling rights are written
+    as in X-FEN: KQkq, unless in Chess960 another Rook stands further out than the one
+    the right is with, when the Rook's column is given instead. Once a check is given in
+    Three-check, the checks each player has given follow the move counters, e.g. +1+0.
+    */
+    pub fn to_fen(self) -> String {
+        self.fen_with(false)
+    }
+
+    /** Describe this position in Shredder-FEN, which gives each castling Rook's column, e.g. HAha */
+    pub fn to_shredder_fen(self) -> String {
+        self.fen_with(true)
+    }
+
+    // FEN, with the castling rights written as the Rooks' columns if SHREDDER is true
+    fn fen_with(self, shredder: bool) -> String {
+        let mut rows = Vec::new();
+        for row in (0..8).rev() {
+            let mut pieces = String::new();
+            let mut empty = 0;
+            for col in 0..8 {
+                match self.get((row, col)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            pieces.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        pieces.push(piece.fen_char());
+                        if self.is_promoted((row, col)) {
+                            pieces.push('~');
+                        }
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                pieces.push_str(&empty.to_string());
+            }
+            rows.push(pieces);
+        }
+        // Pockets are only written when something is in them
+        if self.pockets != [[0; 5]; 2] {
+            let mut pockets = String::from("[");
+            for &color in &[White, Black] {
+                for &piece_type in POCKET_TYPES.iter().rev() {
+                    let c = Piece(piece_type, color).fen_char();
+                    pockets.extend(core::iter::repeat_n(c, self.pocket(color, piece_type) as usize));
+                }
+            }
+            pockets.push(']');
+            rows.last_mut().unwrap().push_str(&pockets); // After row 1
+        }
+        let mut castling = String::new();
+        for &(color, side, c) in &[
+            (White, KingSide, 'K'),
+            (White, QueenSide, 'Q'),
+            (Black, KingSide, 'k'),
+            (Black, QueenSide, 'q'),
+        ] {
+            let rook = match self.castling_rook(color, side) {
+                Some(rook) => rook,
+                None => continue,
+            };
+            // KQkq stand for the outermost Rooks
+            let outer = match side {
+                KingSide => rook.1 + 1..8,
+                QueenSide => 0..rook.1,
+            };
+            let outermost = outer
+                .into_iter()
+                .all(|col| self.get((rook.0, col)) != Some(Piece(Rook, color)));
+            if shredder || !outermost {
+                let file = (b'a' + rook.1 as u8) as char;
+                castling.push(if color == White { file.to_ascii_uppercase() } else { file });
+            } else {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let mut fen = format!(
+            "{} {} {} {} {} {}",
+            rows.join("/"),
+            match self.player {
+                White => "w",
+                Black => "b",
+            },
+            castling,
+            self.en_passant.map_or(String::from("-"), square_name),
+            self.halfmove_clock,
+            self.fullmove
+        );
+        if self.checks != [0; 2] {
+            fen.push_str(&format!(" +{}+{}", self.checks[0], self.checks[1]));
+        }
+        fen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    #[test]
+    fn test_start_position() {
+        assert_eq!(Board::new().to_fen(), START_FEN);
+        let board = Board::from_fen(START_FEN).unwrap();
+        assert_eq!(board.to_fen(), START_FEN);
+        assert_eq!(board.legal_moves().len(), 20);
+    }
+    #[test]
+    fn test_round_trip() {
+        let board = Board::new()
+            .execute_move((1, 4), (3, 4), None)
+            .execute_move((7, 6), (5, 5), None)
+            .execute_move((0, 4), (1, 4), None);
+        let fen = "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2";
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+
+        let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3";
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.en_passant(), Some((2, 4)));
+        assert_eq!(board.turn(), 3);
+        assert_eq!(board.to_fen(), fen);
+        board.validate_move((3, 3), (2, 4), None).unwrap();
+    }
+    #[test]
+    fn test_chess960_castling() {
+        // Shredder-FEN names the Rooks' columns, which X-FEN only does for inner Rooks
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let board = Board::from_fen(fen).unwrap();
+        assert!(board.is_chess960());
+        assert_eq!(board.castling_rook(White, QueenSide), Some((0, 5)));
+        assert_eq!(board.to_shredder_fen(), fen);
+        assert_eq!(board.to_fen(), fen.replace("HFhf", "KQkq"));
+        assert_eq!(Board::from_fen(&board.to_fen()).unwrap().to_shredder_fen(), fen);
+        // KQkq are the outermost Rooks
+        let fen = "1k6/8/8/8/8/8/8/1K1R2R1 w K - 0 1";
+        assert_eq!(Board::from_fen(fen).unwrap().castling_rook(White, KingSide), Some((0, 6)));
+        let fen = "1k6/8/8/8/8/8/8/1K1R2R1 w D - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.castling_rook(White, KingSide), Some((0, 3)));
+        assert_eq!(board.to_fen(), fen);
+        // The standard position is only Chess960 if asked
+        assert!(!Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1").unwrap().is_chess960());
+        // No Rook on c1, and two rights on the same side
+        for bad in ["1k6/8/8/8/8/8/8/1K1R2R1 w C - 0 1", "1k6/8/8/8/8/8/8/1K1R2R1 w KD - 0 1"] {
+            assert!(Board::from_fen(bad).is_err(), "{} should not parse", bad);
+        }
+    }
+    #[test]
+    fn test_optional_counters() {
+        let board = Board::from_fen("8/8/8/4k3/8/8/8/4K3 w - -").unwrap();
+        assert_eq!(board.to_fen(), "8/8/8/4k3/8/8/8/4K3 w - - 0 1");
+    }
+    #[test]
+    fn test_large_counters() {
+        let board = Board::from_fen("8/8/8/4k3/8/8/8/4K3 b - - 299 1000").unwrap();
+        assert_eq!(board.turn(), 1000);
+        let board = board.execute_move((4, 4), (4, 5), None);
+        assert_eq!(board.to_fen(), "8/8/8/5k2/8/8/8/4K3 w - - 300 1001");
+    }
+    #[test]
+    fn test_malformed() {
+        let bad = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1", // 7 rows
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", // bad digit
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN w KQkq - 0 1", // short row
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1", // long row
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", // color
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1", // castling
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1", // repeated right
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1", // missing Rook
+            "rnbqkbnr/pppppppp/44/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", // two digits
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1", // en passant
+            "rnbqkbnr/pppppppp/8/8/4P3/4N3/PPPP1PPP/RNBQKB1R b KQkq e3 0 1", // target taken
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPPPPPP/RNBQKBNR b KQkq e3 0 1", // start taken
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1", // halfmove clock
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", // fullmove number
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w kq - 0 1", // no White King
+            "rnbqkbnP/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQq - 0 1", // Pawn on row 8
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -  0", // 5 fields
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Pp] w KQkq - 0 1", // pockets
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ~KBNR w KQkq - 0 1", // promoted piece
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +1+0", // checks given
+        ];
+        for fen in bad.iter() {
+            assert!(Board::from_fen(fen).is_err(), "{} should not parse", fen);
+        }
+    }
+}
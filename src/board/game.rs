@@ -0,0 +1,161 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+
+// Tags every PGN game must have, in the order they are written
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/**
+A record of a whole game: the variant being played, the position it started from,
+each move played and the tag pairs describing it. The position after every move is
+kept so moves can be undone.
+*/
+#[derive(Clone)]
+pub struct Game {
+    variant: &'static dyn Variant,
+    tags: Vec<(String, String)>,
+    boards: Vec<Board>, // boards[i] is the position before moves[i]
+    moves: Vec<Move>,
+}
+
+impl Game {
+    pub fn new() -> Game {
+        Game::from_board(Board::new())
+    }
+    // Start a game of standard chess from BOARD
+    pub fn from_board(board: Board) -> Game {
+        Game::with_variant(&Standard, board)
+    }
+    // Start a game of VARIANT from BOARD. Positions other than the variant's start are
+    // recorded in a FEN tag
+    pub fn with_variant(variant: &'static dyn Variant, board: Board) -> Game {
+        let mut game = Game {
+            variant,
+            tags: Vec::new(),
+            boards: vec![board],
+            moves: Vec::new(),
+        };
+        for &name in &SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Date" => "????.??.??",
+                "Result" => "*",
+                _ => "?",
+            };
+            game.set_tag(name, value);
+        }
+        if board.is_chess960() {
+            game.set_tag("Variant", "Chess960");
+        } else if variant.name() != Standard.name() {
+            game.set_tag("Variant", variant.name());
+        }
+        if board.to_fen() != variant.start().to_fen() || board.is_chess960() {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &board.to_fen());
+        }
+        game
+    }
+    // The rules the game is played by
+    pub fn variant(&self) -> &'static dyn Variant {
+        self.variant
+    }
+    // The current position
+    pub fn board(&self) -> Board {
+        *self.boards.last().unwrap()
+    }
+    // Every position reached so far, starting with the initial position
+    pub fn boards(&self) -> &[Board] {
+        &self.boards
+    }
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+    // Record MV, which must be valid in the current position
+    pub fn play(&mut self, mv: Move) {
+        let board = self.variant.play(&self.board(), mv);
+        self.moves.push(mv);
+        self.boards.push(board);
+    }
+    // Take back the last move, returning it. Returns None if no moves have been played.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.moves.pop()?;
+        self.boards.pop();
+        Some(mv)
+    }
+
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+    // Set the value of the tag NAME, adding it after the existing tags if it is new
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = String::from(value),
+            None => self.tags.push((String::from(name), String::from(value))),
+        }
+    }
+    // "1-0", "0-1", "1/2-1/2", or "*" if the game is unfinished
+    pub fn result(&self) -> &str {
+        self.tag("Result").unwrap_or("*")
+    }
+    // Record the outcome of the game. None means White and Black drew.
+    pub fn set_winner(&mut self, winner: Option<Color>) {
+        let result = match winner {
+            Some(White) => "1-0",
+            Some(Black) => "0-1",
+            None => "1/2-1/2",
+        };
+        self.set_tag("Result", result);
+    }
+}
+
+impl Default for Game {
+    fn default() -> Game {
+        Game::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_play_and_undo() {
+        let mut game = Game::new();
+        let first = game.board().parse_san("e4").unwrap();
+        game.play(first);
+        game.play(game.board().parse_san("e5").unwrap());
+        assert_eq!(game.moves().len(), 2);
+        assert_eq!(game.boards().len(), 3);
+        assert_eq!(game.board().player(), White);
+        game.undo();
+        assert_eq!(game.undo(), Some(first));
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.board().to_fen(), Board::new().to_fen());
+    }
+    #[test]
+    fn test_tags() {
+        let mut game = Game::new();
+        assert_eq!(game.tags().len(), 7);
+        assert_eq!(game.result(), "*");
+        game.set_winner(Some(Black));
+        game.set_tag("Annotator", "Jack");
+        assert_eq!(game.result(), "0-1");
+        assert_eq!(game.tag("Annotator"), Some("Jack"));
+        assert_eq!(game.tags().len(), 8);
+
+        let board = Board::from_fen("8/8/8/4k3/8/8/8/4K3 w - - 0 1").unwrap();
+        let game = Game::from_board(board);
+        assert_eq!(game.tag("SetUp"), Some("1"));
+        assert_eq!(game.tag("FEN"), Some("8/8/8/4k3/8/8/8/4K3 w - - 0 1"));
+
+        // Chess960 games say so, even from the standard position
+        let game = Game::from_board(Board::new_chess960(518).unwrap());
+        assert_eq!(game.tag("Variant"), Some("Chess960"));
+        assert_eq!(game.tag("FEN"), Some(Board::new().to_fen().as_str()));
+    }
+}
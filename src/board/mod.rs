@@ -1,16 +1,56 @@
 // Jack Alpert 2020
 
 use crate::board::path::*;
-use crate::board::piece::*;
-use itertools::iproduct;
+pub use crate::board::bitboard::*;
+pub use crate::board::book::*;
+pub use crate::board::castle::*;
+pub use crate::board::draw::*;
+pub use crate::board::endgame::*;
+pub use crate::board::error::*;
+pub use crate::board::eval::*;
+pub use crate::board::game::*;
+pub use crate::board::moves::*;
+pub use crate::board::path::{in_bounds, parse_square, square_name, Direction, Sign};
+pub use crate::board::piece::*;
+pub use crate::board::pocket::*;
+pub use crate::board::search::*;
+#[cfg(feature = "std")]
+pub use crate::board::syzygy::*;
+pub use crate::board::transposition::*;
+pub use crate::board::variant::*;
+pub use crate::board::zobrist::*;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
 
+mod bitboard;
+mod book;
+mod castle;
 mod check;
 mod display;
+mod draw;
+mod endgame;
+mod error;
+mod eval;
 mod factory;
+mod fen;
+mod game;
+mod moves;
 mod path;
+mod perft;
+mod pgn;
 mod piece;
+mod pocket;
+mod san;
+mod search;
+#[cfg(feature = "std")]
+mod syzygy;
+mod transposition;
+mod variant;
+mod zobrist;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Color {
     White,
     Black,
@@ -31,38 +71,91 @@ pub type Square = (isize, isize);
 
 #[derive(Copy, Clone)]
 pub struct Board {
-    data: [[Option<Piece>; 8]; 8],
+    pieces: [Bitboard; 6], // Squares holding each PieceType, of either color
+    colors: [Bitboard; 2], // Squares holding each Color's pieces
     player: Color,
-    turn_no: u8,
+    fullmove: u32,       // Starts at 1 and increases after each of Black's moves
+    halfmove_clock: u32, // Moves since the last capture or Pawn move
+    castling: [[Option<isize>; 2]; 2], // Column of the Rook for each right, by Color then CastleSide
+    chess960: bool,             // Castling is written as the King taking its own Rook
+    en_passant: Option<Square>, // Square skipped over by a Pawn's two-square advance last turn
+    checks: [u8; 2],            // Checks given by each Color, counted only by variants that need them
+    pockets: [[u8; 5]; 2], // Pieces each Color may drop in Crazyhouse, by PieceType below the King
+    promoted: Bitboard,    // Pieces promoted from Pawns, which go back to a pocket as Pawns
+    piece_hash: u64,            // Zobrist keys of the pieces XORed together, kept up to date by set
 }
 
 impl Board {
     pub fn get(&self, loc: Square) -> Option<Piece> {
-        self.data[loc.0 as usize][loc.1 as usize]
+        let bit = square_bit(loc);
+        let color = if self.colors[White as usize] & bit != 0 {
+            White
+        } else if self.colors[Black as usize] & bit != 0 {
+            Black
+        } else {
+            return None;
+        };
+        let piece_type = PIECE_TYPES
+            .iter()
+            .find(|&&piece_type| self.pieces[piece_type as usize] & bit != 0)?;
+        Some(Piece(*piece_type, color))
     }
     fn set(&mut self, loc: Square, piece: Option<Piece>) -> &mut Self {
-        self.data[loc.0 as usize][loc.1 as usize] = piece;
+        if let Some(old) = self.get(loc) {
+            self.piece_hash ^= piece_key(&POLYGLOT, old, loc);
+        }
+        if let Some(new) = piece {
+            self.piece_hash ^= piece_key(&POLYGLOT, new, loc);
+        }
+        let bit = square_bit(loc);
+        for bitboard in self.pieces.iter_mut().chain(self.colors.iter_mut()) {
+            *bitboard &= !bit;
+        }
+        if let Some(Piece(piece_type, color)) = piece {
+            self.pieces[piece_type as usize] |= bit;
+            self.colors[color as usize] |= bit;
+        }
         self
     }
     pub fn player(&self) -> Color {
         self.player
     }
-    pub fn turn(&self) -> u8 {
-        self.turn_no
+    /** Returns the fullmove number, which starts at 1 and increases after Black moves */
+    pub fn turn(&self) -> u32 {
+        self.fullmove
+    }
+    /** Returns the number of moves by either player since the last capture or Pawn move */
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+    /** Returns the square a Pawn may move to when capturing en passant this turn, if any */
+    pub fn en_passant(&self) -> Option<Square> {
+        self.en_passant
+    }
+    /** Returns how many times PLAYER has given check, in variants that count them */
+    pub fn checks_given(&self, player: Color) -> u8 {
+        self.checks[player as usize]
+    }
+    /** Returns true if moving FROM -> TO is a Pawn capturing en passant */
+    pub fn is_en_passant(&self, from: Square, to: Square) -> bool {
+        self.get(from)
+            .is_some_and(|piece| piece.piece_type() == Pawn)
+            && from.1 != to.1
+            && Some(to) == self.en_passant
     }
     /**
     Ensure player owns this piece and a piece exists in this location
     If player is None, defaults to current player
     */
-    pub fn validate_from(&self, from: Square, player: Option<Color>) -> Result<(), String> {
+    pub fn validate_from(&self, from: Square, player: Option<Color>) -> Result<(), MoveError> {
         let player = player.unwrap_or(self.player);
         // Ensure square is in bounds
         if !in_bounds(from) {
-            Err(format!("{} {} is out of bounds.", from.0 + 1, from.1 + 1))
+            Err(MoveError::OutOfBounds(from))
         } else if self.get(from).is_none() {
-            Err(String::from("No piece exists in this location."))
+            Err(MoveError::EmptySquare(from))
         } else if self.get(from).unwrap().color() != player {
-            Err(String::from("This piece does not belong to you."))
+            Err(MoveError::NotYourPiece(from))
         } else {
             Ok(())
         }
@@ -73,12 +166,12 @@ impl Board {
         from: Square,
         to: Square,
         player: Option<Color>,
-    ) -> Result<(), String> {
+    ) -> Result<(), MoveError> {
         let player = player.unwrap_or(self.player);
         // Check for out of bounds
         assert!(in_bounds(from)); // coder error
         if to.0 < 0 || to.0 >= 8 || to.1 < 0 || to.1 >= 8 {
-            return Err(format!("{} {} is out of bounds.", to.0 + 1, to.1 + 1));
+            return Err(MoveError::OutOfBounds(to));
         }
         //
         // Get the piece
@@ -89,9 +182,9 @@ impl Board {
                                                                   // Universal rules
         if let Some(piece_occupying_to) = self.get(to) {
             if piece_occupying_to.is_king() {
-                return Err(String::from("Cannot capture the King."));
-            } else if piece_occupying_to.color() == player {
-                return Err(String::from("Can't move here. Square occupied."));
+                return Err(MoveError::CaptureKing(to));
+            } else if piece_occupying_to.color() == player && self.castle_side(from, to).is_none() {
+                return Err(MoveError::SquareOccupied(to));
             }
         }
         // capturing_piece // Capture must be none or a piece belonging to the other player
@@ -115,11 +208,31 @@ impl Board {
         // Apply Checking Rules for Moving the King
         if from == king_position {
             // Cannot move King to location that is being attacked
+            if let Some(side) = self.castle_side(from, to) {
+                // Cannot castle out of, through, or into check
+                let king_to = (from.0, side.king_to());
+                let step = (king_to.1 - from.1).signum();
+                let mut passing = (1..(king_to.1 - from.1).abs()).map(|i| (from.0, from.1 + i * step));
+                return if !squares_checking.is_empty() {
+                    Err(MoveError::CastleOutOfCheck)
+                } else if let Some(loc) = passing.find(|&loc| self.num_checking(loc, Some(player)) > 0) {
+                    Err(MoveError::CastleThroughCheck(loc))
+                } else if self.num_checking(king_to, Some(player)) > 0
+                    // In Chess960 the Rook may have been shielding the King's landing square
+                    || self.execute_move(from, to, None).is_attacked(king_to, player.other())
+                {
+                    Err(MoveError::CastleIntoCheck(king_to))
+                } else {
+                    Ok(())
+                };
+            }
             if self.num_checking(to, Some(player)) > 0 {
-                if squares_checking.len() > 0 {
-                    Err(String::from("King is still in check"))
+                if !squares_checking.is_empty() {
+                    Err(MoveError::StillInCheck {
+                        checkers: squares_checking,
+                    })
                 } else {
-                    Err(String::from("King cannot place himself in check."))
+                    Err(MoveError::IntoCheck(to))
                 }
             } else {
                 // Edge case can arise when the King is in check and moves in the direction
@@ -131,36 +244,65 @@ impl Board {
                         // If None, attacker is Knight, so this case will not occur
                         continue;
                     }
+                    if let Some(Piece(Pawn, _)) = self.get(*attacker) {
+                        // A Pawn only attacks the squares next to it, so stepping away is safe
+                        continue;
+                    }
                     // MOVE_DIR Cannot be None if Moving King
                     if attack_dir == move_dir {
-                        return Err(String::from("King is still in check"));
+                        return Err(MoveError::StillInCheck {
+                            checkers: squares_checking.clone(),
+                        });
                     }
                 }
                 // Successfully moving the King out of check
                 Ok(())
             }
+        } else if self.is_en_passant(from, to) {
+            // Both Pawns leave the same row, so a single blocker may be hiding the King from a
+            // horizontal attack, and the captured Pawn may be pinned or giving check itself.
+            // The pin logic below does not handle these, so play the move out instead.
+            let after = self.execute_move(from, to, None);
+            let checkers_after = after.squares_checking(king_position, Some(player));
+            if checkers_after.is_empty() {
+                Ok(())
+            } else if !squares_checking.is_empty() {
+                Err(MoveError::StillInCheck {
+                    checkers: squares_checking,
+                })
+            } else {
+                Err(MoveError::PinnedEnPassant {
+                    pinner: checkers_after[0],
+                })
+            }
         } else {
             match (self.is_pinned(from, king_position, Some(player)), &squares_checking.len()) {
-                (Some(pin_dir), 0) => {
-                    if move_dir.is_none() {
-                        Err(String::from("This Knight is pinned. It cannot be moved."))
-                    } else if pin_dir == move_dir.unwrap() {
+                (Some(pin_dir), 0) => match move_dir {
+                    None => Err(MoveError::PinnedPiece {
+                        piece: piece.piece_type(),
+                        pinner: self.pinner(from, pin_dir),
+                    }),
+                    Some(move_dir)
+                        if pin_dir == move_dir || Self::get_direction(to, from) == Some(pin_dir) =>
+                    {
                         // You are allowed to capture the pinning piece
                         // or maintain the pin by moving this piece between
-                        // the pinner and the King
+                        // the pinner and the King, in either direction
                         Ok(())
-                    } else {
-                        Err(String::from("This piece is pinned. It cannot be moved in this direction."))
                     }
-                }
-                (Some(_pin_dir), _n) => {
+                    Some(_) => Err(MoveError::PinnedPiece {
+                        piece: piece.piece_type(),
+                        pinner: self.pinner(from, pin_dir),
+                    }),
+                },
+                (Some(pin_dir), _n) => {
                     // When in check, cannot move a pinned piece no matter what.
                     // Even if capturing/blocking the checking piece, since this piece
                     // is pinned,the King would then still be in check, although
                     // from a different piece as before.
-                    Err(String::from(
-                        "This piece is pinned. Move another piece to get King out of check.",
-                    ))
+                    Err(MoveError::PinnedInCheck {
+                        pinner: self.pinner(from, pin_dir),
+                    })
                 }
                 (None, 0) => {
                     // Not pinned, not in check. All good.
@@ -170,65 +312,135 @@ impl Board {
                     if to == squares_checking[0] {
                         // Capturing the attacking piece
                         Ok(())
-                    } else if Self::get_path(squares_checking[0], king_position).map_or(false, |path| path.contains(&to)) {
+                    } else if Self::get_path(squares_checking[0], king_position).is_some_and(|path| path.contains(&to)) {
                         // Blocking i.e. moving between the King and the attacking piece
                         // Path being None indicates this is a Knight checking, which cannot be blocked,
                         // hence return false
                         Ok(())
                     } else {
-                        Err(String::from("King is still in check"))
+                        Err(MoveError::StillInCheck {
+                            checkers: squares_checking,
+                        })
                     }
                 }
                 (None, _n) => {
                     // Must move the King to get out of a multi-check
-                    Err(String::from("Must move King out of check"))
+                    Err(MoveError::MustMoveKing {
+                        checkers: squares_checking,
+                    })
                 }
             }
         }
     }
+    // The square of the piece pinning the piece at FROM along PIN_DIR, as found by is_pinned
+    fn pinner(&self, from: Square, pin_dir: Direction) -> Square {
+        Self::get_directed_path(from, pin_dir)
+            .into_iter()
+            .find(|&loc| self.get(loc).is_some())
+            .expect("This piece is not pinned.") // Coder error
+    }
+    /** Returns true if moving FROM -> TO brings a Pawn to the opposite row */
+    pub fn is_promotion(&self, from: Square, to: Square) -> bool {
+        match self.get(from) {
+            Some(Piece(Pawn, color)) => to.0 == color.other().home_row(),
+            _ => false,
+        }
+    }
+    /**
+    Ensure that PROMOTION is given exactly when moving FROM -> TO promotes a Pawn,
+    and that it names a piece a Pawn may become
+    */
+    pub fn validate_promotion(
+        &self,
+        from: Square,
+        to: Square,
+        promotion: Option<PieceType>,
+    ) -> Result<(), MoveError> {
+        match (self.is_promotion(from, to), promotion) {
+            (true, None) => Err(MoveError::MissingPromotion),
+            (true, Some(piece_type @ Pawn)) | (true, Some(piece_type @ King)) => {
+                Err(MoveError::InvalidPromotion(piece_type))
+            }
+            (false, Some(_)) => Err(MoveError::UnexpectedPromotion),
+            _ => Ok(()),
+        }
+    }
     // Execute the move by returning a copy of self with the changes applied
-    pub fn execute_move(&self, from: Square, to: Square) -> Board {
+    // PROMOTION is the piece a Pawn becomes on reaching the opposite row
+    pub fn execute_move(&self, from: Square, to: Square, promotion: Option<PieceType>) -> Board {
         assert!(self.get(from).is_some());
         let mut new_board = *self; // make a copy
         new_board.set(from, None);
-        // Pawns become Queens on opposite row
-        let piece = match self.get(from) {
-            Some(Piece(Pawn, White)) if to.0 == 7 => Some(Piece(Queen, White)),
-            Some(Piece(Pawn, Black)) if to.0 == 0 => Some(Piece(Queen, Black)),
-            p => p,
+        let moved = self.get(from).unwrap();
+        let piece = match moved {
+            Piece(Pawn, color) if self.is_promotion(from, to) => Some(Piece(
+                promotion.expect("No promotion given. Must validate promotion."), // Coder error
+                color,
+            )),
+            p => Some(p),
         };
-        new_board.set(to, piece);
+        let castle = self.castle_side(from, to);
+        match castle {
+            // Castling also moves the Rook, which in Chess960 may stand on any column
+            Some(side) => {
+                let rook_from = self
+                    .castling_rook(moved.color(), side)
+                    .unwrap_or((from.0, side.rook_from()));
+                new_board.set(rook_from, None);
+                new_board.set((from.0, side.rook_to()), Some(Piece(Rook, moved.color())));
+                new_board.set((from.0, side.king_to()), piece);
+            }
+            None => {
+                new_board.set(to, piece);
+            }
+        }
+        // Capturing en passant removes the Pawn that was passed
+        if self.is_en_passant(from, to) {
+            new_board.set((from.0, to.1), None);
+        }
+        // A Pawn advancing two squares can be captured en passant next turn
+        new_board.en_passant = match piece {
+            Some(Piece(Pawn, _)) if (to.0 - from.0).abs() == 2 => {
+                Some(((from.0 + to.0) / 2, from.1))
+            }
+            _ => None,
+        };
+        // Captures and Pawn moves reset the clock
+        new_board.halfmove_clock = match self.get(from) {
+            Some(Piece(Pawn, _)) => 0,
+            _ if self.get(to).is_some() && castle.is_none() => 0,
+            _ => self.halfmove_clock.saturating_add(1),
+        };
+        // Promoted pieces stay marked as they move, for Crazyhouse
+        if self.promoted != 0 {
+            let was_promoted = self.is_promoted(from);
+            new_board.promoted &= !(square_bit(from) | square_bit(to));
+            if was_promoted {
+                new_board.promoted |= square_bit(to);
+            }
+        }
+        new_board.update_castling_rights(from, to, moved);
         new_board.player = self.player.other();
-        new_board.turn_no = self.turn_no + 1;
+        if self.player == Black {
+            new_board.fullmove = self.fullmove.saturating_add(1);
+        }
         new_board
     }
 
     pub fn find_king(&self, player: Option<Color>) -> Option<Square> {
         let player = player.unwrap_or(self.player);
-        for loc in iproduct!(0..8, 0..8) {
-            if Some(Piece(King, player)) == self.get(loc) {
-                return Some(loc);
-            }
-        }
-        None
+        Squares(self.piece_bitboard(Piece(King, player))).next()
     }
     pub fn find_pieces(&self, player: Option<Color>) -> Vec<Square> {
         let player = player.unwrap_or(self.player);
-        iproduct!(0..8, 0..8)
-            .filter_map(|loc| {
-                if self.get(loc)?.color() == player {
-                    Some(loc)
-                } else {
-                    None
-                }
-            })
-            .collect()
+        Squares(self.colors[player as usize]).collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use itertools::iproduct;
     #[test]
     fn test_find_king() {
         let board = Board::new();
@@ -244,16 +456,120 @@ mod tests {
         let black_locs_test = board.find_pieces(Some(Black));
         // Test for set equality
         for loc in &white_locs_true {
-            assert!(white_locs_test.contains(&loc));
+            assert!(white_locs_test.contains(loc));
         }
         for loc in &white_locs_test {
-            assert!(white_locs_true.contains(&loc));
+            assert!(white_locs_true.contains(loc));
         }
         for loc in &black_locs_true {
-            assert!(black_locs_test.contains(&loc));
+            assert!(black_locs_test.contains(loc));
         }
         for loc in &black_locs_test {
-            assert!(black_locs_true.contains(&loc));
+            assert!(black_locs_true.contains(loc));
+        }
+    }
+    #[test]
+    fn test_promotion() {
+        let mut board = Board::empty(White, 1);
+        board
+            .set((6, 1), Some(Piece(Pawn, White)))
+            .set((0, 4), Some(Piece(King, White)))
+            .set((7, 4), Some(Piece(King, Black)));
+        board.validate_move((6, 1), (7, 1), None).unwrap();
+        assert!(board.is_promotion((6, 1), (7, 1)));
+        assert!(board.validate_promotion((6, 1), (7, 1), None).is_err());
+        assert!(board.validate_promotion((6, 1), (7, 1), Some(Pawn)).is_err());
+        assert!(board.validate_promotion((6, 1), (7, 1), Some(King)).is_err());
+        assert!(board.validate_promotion((0, 4), (0, 3), Some(Queen)).is_err());
+        for &piece_type in &[Queen, Rook, Bishop, Knight] {
+            board
+                .validate_promotion((6, 1), (7, 1), Some(piece_type))
+                .unwrap();
+            let promoted = board.execute_move((6, 1), (7, 1), Some(piece_type));
+            assert!(promoted.get((7, 1)) == Some(Piece(piece_type, White)));
         }
     }
+    #[test]
+    fn test_en_passant() {
+        let board = Board::new()
+            .execute_move((1, 4), (3, 4), None)
+            .execute_move((6, 0), (5, 0), None)
+            .execute_move((3, 4), (4, 4), None);
+        assert_eq!(board.en_passant(), None);
+        // Black advances two squares next to the White Pawn
+        let board = board.execute_move((6, 3), (4, 3), None);
+        assert_eq!(board.en_passant(), Some((5, 3)));
+        board.validate_move((4, 4), (5, 3), None).unwrap();
+        let captured = board.execute_move((4, 4), (5, 3), None);
+        assert!(captured.get((5, 3)) == Some(Piece(Pawn, White)));
+        assert!(captured.get((4, 3)).is_none());
+        assert_eq!(captured.en_passant(), None);
+        // The chance is lost if not taken immediately
+        let board = board
+            .execute_move((0, 6), (2, 5), None)
+            .execute_move((7, 6), (5, 5), None);
+        assert!(board.validate_move((4, 4), (5, 3), None).is_err());
+    }
+    #[test]
+    fn test_en_passant_horizontal_pin() {
+        // Both Pawns would leave the row, exposing the King to the Rook
+        let mut board = Board::empty(Black, 1);
+        board
+            .set((4, 0), Some(Piece(King, White)))
+            .set((4, 4), Some(Piece(Pawn, White)))
+            .set((6, 3), Some(Piece(Pawn, Black)))
+            .set((4, 7), Some(Piece(Rook, Black)))
+            .set((7, 7), Some(Piece(King, Black)));
+        let board = board.execute_move((6, 3), (4, 3), None);
+        assert!(board.validate_move((4, 4), (5, 3), None).is_err());
+        assert!(board.validate_move((4, 4), (5, 4), None).is_ok());
+    }
+    #[test]
+    fn test_en_passant_out_of_check() {
+        // Capturing en passant removes the Pawn giving check
+        let mut board = Board::empty(Black, 1);
+        board
+            .set((3, 4), Some(Piece(King, White)))
+            .set((4, 4), Some(Piece(Pawn, White)))
+            .set((6, 3), Some(Piece(Pawn, Black)))
+            .set((7, 7), Some(Piece(King, Black)));
+        let board = board.execute_move((6, 3), (4, 3), None);
+        assert_eq!(board.num_checking((3, 4), None), 1);
+        board.validate_move((4, 4), (5, 3), None).unwrap();
+    }
+    #[test]
+    fn test_validate_move_edge_cases() {
+        // A Pawn cannot advance two squares over a piece
+        let board = Board::from_fen("rnbqkbnr/pppppppp/8/8/8/N7/PPPPPPPP/R1BQKBNR w KQkq - 0 1")
+            .unwrap();
+        assert!(board.validate_move((1, 0), (3, 0), None).is_err());
+        // A pinned Rook may slide towards its King
+        let board = Board::from_fen("8/8/7k/8/7r/7R/8/K7 b - - 0 1").unwrap();
+        board.validate_move((3, 7), (4, 7), None).unwrap();
+        // The King may step directly away from a Pawn giving check
+        let board = Board::from_fen("8/8/2p5/1K6/8/8/8/6k1 w - - 0 1").unwrap();
+        board.validate_move((4, 1), (3, 0), None).unwrap();
+    }
+    #[test]
+    fn test_long_game() {
+        // Shuffle the Knights back and forth for 300 moves by each player
+        let mut board = Board::new();
+        for _ in 0..150 {
+            board = board
+                .execute_move((0, 6), (2, 5), None)
+                .execute_move((7, 6), (5, 5), None)
+                .execute_move((2, 5), (0, 6), None)
+                .execute_move((5, 5), (7, 6), None);
+        }
+        assert_eq!(board.turn(), 301);
+        assert_eq!(board.halfmove_clock(), 600);
+        assert_eq!(board.player(), White);
+    }
+    #[test]
+    fn test_fullmove_starts_at_one() {
+        assert_eq!(Board::empty(White, 0).turn(), 1);
+        let rows = String::from("♔ _ _ _ _ _ _ ♚\n") + &"_ _ _ _ _ _ _ _\n".repeat(7);
+        assert_eq!(format!("White\n1 0\n{}", rows).parse::<Board>().unwrap().turn(), 1);
+        assert!(format!("White\n0 0\n{}", rows).parse::<Board>().is_err());
+    }
 }
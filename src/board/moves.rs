@@ -0,0 +1,313 @@
+// Jack Alpert 2020
+
+use crate::board::path::*;
+use crate::board::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<PieceType>, // The piece a Pawn becomes on reaching the opposite row
+    pub capture: bool,
+    pub castle: bool,
+    pub en_passant: bool,
+    pub drop: Option<PieceType>, // The piece placed from the pocket in Crazyhouse, when FROM is TO
+}
+
+impl Move {
+    /**
+    Describe moving the piece at FROM to TO on BOARD, filling in the flags.
+    Does not check that the move is valid.
+    */
+    pub fn new(board: &Board, from: Square, to: Square, promotion: Option<PieceType>) -> Move {
+        let en_passant = board.is_en_passant(from, to);
+        let castle = board.castle_side(from, to).is_some();
+        Move {
+            from,
+            to,
+            promotion,
+            // Castling in Chess960 is written as the King taking its own Rook
+            capture: (board.get(to).is_some() && !castle) || en_passant,
+            castle,
+            en_passant,
+            drop: None,
+        }
+    }
+}
+
+impl Board {
+    /** Returns every valid move available to the current player */
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        for from in Squares(self.colors[self.player as usize]) {
+            self.push_legal_moves(from, &mut moves);
+        }
+        moves
+    }
+
+    /**
+    Returns every valid move for the piece at FROM, with a separate move for each
+    piece a Pawn may be promoted to. Returns an empty vector if FROM is empty.
+    */
+    pub fn legal_moves_from(&self, from: Square) -> Vec<Move> {
+        let mut moves = Vec::new();
+        self.push_legal_moves(from, &mut moves);
+        moves
+    }
+
+    // Add every valid move for the piece at FROM to MOVES
+    fn push_legal_moves(&self, from: Square, moves: &mut Vec<Move>) {
+        let piece = match self.get(from) {
+            Some(piece) => piece,
+            None => return,
+        };
+        for to in Squares(self.target_squares(from, piece)) {
+            let legal = if piece.piece_type() == King && self.castle_side(from, to).is_some() {
+                // Castling is rare enough to go through the full set of rules
+                self.validate_move(from, to, Some(piece.color())).is_ok()
+            } else {
+                self.keeps_king_safe(from, to, piece)
+            };
+            if !legal {
+                continue;
+            }
+            if self.is_promotion(from, to) {
+                for &piece_type in &[Queen, Rook, Bishop, Knight] {
+                    moves.push(Move::new(self, from, to, Some(piece_type)));
+                }
+            } else {
+                moves.push(Move::new(self, from, to, None));
+            }
+        }
+    }
+
+    // Execute MOVE by returning a copy of self with the changes applied
+    pub fn play(&self, mv: Move) -> Board {
+        match mv.drop {
+            Some(piece_type) => self.execute_drop(piece_type, mv.to),
+            None => self.execute_move(mv.from, mv.to, mv.promotion),
+        }
+    }
+
+    /**
+    Find the valid move written in the long algebraic notation used by UCI: the starting
+    and ending squares followed by the piece to promote to, if any. E.g. "e2e4" or "e7e8q".
+    Castling is written as the King's move, e.g. "e1g1", or in Chess960 as the King
+    taking its own Rook, e.g. "e1h1".
+    */
+    pub fn parse_uci(&self, uci: &str) -> Result<Move, ParseError> {
+        let uci = uci.trim();
+        if uci.len() < 4 || !uci.is_char_boundary(2) || !uci.is_char_boundary(4) {
+            return Err(ParseError::NotLongAlgebraic(String::from(uci)));
+        }
+        let from = parse_square(&uci[..2])?;
+        let to = parse_square(&uci[2..4])?;
+        let promotion = match &uci[4..] {
+            "" => None,
+            piece => Some(piece.parse()?),
+        };
+        self.validate_from(from, None)
+            .and_then(|()| self.validate_move(from, to, None))
+            .and_then(|()| self.validate_promotion(from, to, promotion))
+            .map_err(|error| ParseError::IllegalMove {
+                notation: String::from(uci),
+                error,
+            })?;
+        Ok(Move::new(self, from, to, promotion))
+    }
+
+    /**
+    Returns the squares PIECE at FROM could move to, ignoring whether its King would be
+    left in check. Castling squares are included while the player holds the right.
+    */
+    pub(crate) fn target_squares(&self, from: Square, piece: Piece) -> Bitboard {
+        let Piece(piece_type, player) = piece;
+        let index = square_index(from);
+        let occupied = self.occupied();
+        let own = self.colors[player as usize];
+        let enemy = self.colors[player.other() as usize];
+        let targets = match piece_type {
+            Pawn => {
+                // En passant only belongs to the player about to move
+                let en_passant = match self.en_passant {
+                    Some(loc) if player == self.player => square_bit(loc),
+                    _ => 0,
+                };
+                let mut targets = PAWN_ATTACKS[player as usize][index] & (enemy | en_passant);
+                let forward = match player {
+                    White => 1,
+                    Black => -1,
+                };
+                let one = (from.0 + forward, from.1);
+                if in_bounds(one) && occupied & square_bit(one) == 0 {
+                    targets |= square_bit(one);
+                    let two = (from.0 + 2 * forward, from.1);
+                    if from.0 == player.home_row() + forward && occupied & square_bit(two) == 0 {
+                        targets |= square_bit(two);
+                    }
+                }
+                targets
+            }
+            Knight => KNIGHT_ATTACKS[index],
+            Bishop => bishop_attacks(index, occupied),
+            Rook => rook_attacks(index, occupied),
+            Queen => queen_attacks(index, occupied),
+            King => KING_ATTACKS[index],
+        };
+        // Castling squares are added after removing those of the player's own pieces,
+        // as in Chess960 the King moves to its own Rook's square
+        let mut castling = 0;
+        if piece_type == King {
+            for &side in &[KingSide, QueenSide] {
+                castling |= self.castle_target(player, side).map_or(0, square_bit);
+            }
+        }
+        // The enemy King can never be captured
+        (targets & !own & !self.pieces[King as usize]) | castling
+    }
+
+    /**
+    Returns true if PIECE moving FROM -> TO does not leave its own King attacked.
+    Only the occupancy changes are applied, so the board is not copied.
+    */
+    pub(crate) fn keeps_king_safe(&self, from: Square, to: Square, piece: Piece) -> bool {
+        let Piece(piece_type, player) = piece;
+        let mut captured = square_bit(to);
+        if self.is_en_passant(from, to) && player == self.player {
+            captured |= square_bit((from.0, to.1));
+        }
+        let occupied = (self.occupied() & !captured & !square_bit(from)) | square_bit(to);
+        let king = if piece_type == King {
+            to
+        } else {
+            match self.find_king(Some(player)) {
+                Some(king) => king,
+                None => return true, // Nothing to protect
+            }
+        };
+        self.attackers(square_index(king), player.other(), occupied) & !captured == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_opening_moves() {
+        let board = Board::new();
+        assert_eq!(board.legal_moves().len(), 20);
+        assert_eq!(board.legal_moves_from((0, 1)).len(), 2); // Knight
+        assert_eq!(board.legal_moves_from((0, 0)).len(), 0); // Rook
+        assert_eq!(board.legal_moves_from((4, 4)).len(), 0); // Empty square
+        let board = board.execute_move((1, 4), (3, 4), None);
+        assert_eq!(board.legal_moves().len(), 20);
+        // White's Bishop and Queen have been freed, although it is Black's turn
+        assert_eq!(board.legal_moves_from((0, 5)).len(), 5);
+        assert_eq!(board.legal_moves_from((0, 3)).len(), 4);
+    }
+    #[test]
+    fn test_move_flags() {
+        let mut board = Board::empty(White, 1);
+        board
+            .set((0, 4), Some(Piece(King, White)))
+            .set((0, 7), Some(Piece(Rook, White)))
+            .set((6, 0), Some(Piece(Pawn, White)))
+            .set((4, 4), Some(Piece(Pawn, White)))
+            .set((7, 1), Some(Piece(Knight, Black)))
+            .set((6, 3), Some(Piece(Pawn, Black)))
+            .set((7, 7), Some(Piece(King, Black)));
+        board.infer_castling_rights();
+        board.player = Black;
+        let board = board.execute_move((6, 3), (4, 3), None);
+        let moves = board.legal_moves();
+
+        let castle = Move::new(&board, (0, 4), (0, 6), None);
+        assert!(castle.castle && !castle.capture);
+        assert!(moves.contains(&castle));
+
+        let en_passant = Move::new(&board, (4, 4), (5, 3), None);
+        assert!(en_passant.en_passant && en_passant.capture);
+        assert!(moves.contains(&en_passant));
+
+        // Each promotion is a separate move
+        let promotions = board.legal_moves_from((6, 0));
+        assert_eq!(promotions.len(), 8);
+        assert!(promotions.iter().all(|mv| mv.promotion.is_some()));
+        assert_eq!(promotions.iter().filter(|mv| mv.capture).count(), 4);
+    }
+    #[test]
+    fn test_uci() {
+        let board = Board::new();
+        let mv = board.parse_uci("g1f3").unwrap();
+        assert_eq!(mv, Move::new(&board, (0, 6), (2, 5), None));
+        assert_eq!(mv.to_string(), "g1f3");
+        assert!(board.parse_uci("e2e5").is_err());
+        assert!(board.parse_uci("e7e5").is_err());
+        assert!(board.parse_uci("e2").is_err());
+        assert!(board.parse_uci("e2e4x").is_err());
+
+        let board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let mv = board.parse_uci("b7b8n").unwrap();
+        assert_eq!(mv.promotion, Some(Knight));
+        assert_eq!(mv.to_string(), "b7b8n");
+        assert!(board.parse_uci("b7b8").is_err());
+        assert!(board.parse_uci("e1g1").unwrap().castle);
+    }
+    // Every move validate_move accepts for the current player, found by trying every pair of squares
+    fn validated_moves(board: &Board) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for from in board.find_pieces(None) {
+            for to in Squares(!0) {
+                if board.validate_move(from, to, None).is_err() {
+                    continue;
+                }
+                if board.is_promotion(from, to) {
+                    for &piece_type in &[Queen, Rook, Bishop, Knight] {
+                        moves.push(Move::new(board, from, to, Some(piece_type)));
+                    }
+                } else {
+                    moves.push(Move::new(board, from, to, None));
+                }
+            }
+        }
+        moves
+    }
+    #[test]
+    fn test_moves_match_validation() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        ];
+        // A fixed seed, so any failure can be reproduced
+        let mut state = 2020;
+        for fen in fens.iter() {
+            let mut board = Board::from_fen(fen).unwrap();
+            // Follow a pseudorandom game, comparing the moves found in each position
+            for _ in 0..40 {
+                let moves = board.legal_moves();
+                let expected = validated_moves(&board);
+                assert_eq!(moves.len(), expected.len(), "{}", board.to_fen());
+                assert!(expected.iter().all(|mv| moves.contains(mv)));
+                if moves.is_empty() {
+                    break;
+                }
+                let choice = splitmix64(&mut state) % moves.len() as u64;
+                board = board.play(moves[choice as usize]);
+            }
+        }
+    }
+    #[test]
+    fn test_checkmate_has_no_moves() {
+        // Fool's mate
+        let board = Board::new()
+            .execute_move((1, 5), (2, 5), None)
+            .execute_move((6, 4), (4, 4), None)
+            .execute_move((1, 6), (3, 6), None)
+            .execute_move((7, 3), (3, 7), None);
+        assert!(board.legal_moves().is_empty());
+        assert!(board.has_no_moves(None));
+        assert!(!board.has_no_moves(Some(Black)));
+    }
+}
@@ -1,7 +1,7 @@
 // Jack Alpert 2020
 
 use crate::board::*;
-use std::iter;
+use core::iter;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Sign {
@@ -22,6 +22,29 @@ pub use Sign::*;
 
 pub type Direction = (Sign, Sign);
 
+// Offsets from a Knight's square to each square it can jump to
+pub const KNIGHT_JUMPS: [Square; 8] = [
+    (1, 2),
+    (1, -2),
+    (-1, 2),
+    (-1, -2),
+    (2, 1),
+    (2, -1),
+    (-2, 1),
+    (-2, -1),
+];
+// Offsets from a King's square to each square it can step to
+pub const KING_STEPS: [Square; 8] = [
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, -1),
+    (0, 1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
 pub fn is_horizontal(from: Square, to: Square) -> bool {
     from.0 == to.0
 }
@@ -38,6 +61,24 @@ pub fn in_bounds(loc: Square) -> bool {
     0 <= loc.0 && loc.0 < 8 && 0 <= loc.1 && loc.1 < 8
 }
 
+// Name of the square in algebraic notation, e.g. (0, 4) -> "e1"
+pub fn square_name(loc: Square) -> String {
+    assert!(in_bounds(loc));
+    format!("{}{}", (b'a' + loc.1 as u8) as char, loc.0 + 1)
+}
+
+// Parse a square in algebraic notation, e.g. "e1" -> (0, 4)
+pub fn parse_square(s: &str) -> Result<Square, ParseError> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(file @ 'a'..='h'), Some(rank @ '1'..='8'), None) => Ok((
+            rank as isize - '1' as isize,
+            file as isize - 'a' as isize,
+        )),
+        _ => Err(ParseError::InvalidSquare(String::from(s))),
+    }
+}
+
 impl Board {
     /**
     Returns the direction from FROM to TO
@@ -103,16 +144,8 @@ impl Board {
     */
     pub fn clear_path(&self, from: Square, to: Square) -> bool {
         assert_ne!(from, to); // TODO TBD
-        match Self::get_path(from, to) {
-            Some(path) => {
-                // Check each square and ensure it is clear
-                for loc in path {
-                    if let Some(_) = self.get(loc) {
-                        return false;
-                    }
-                }
-                return true;
-            }
+        match Self::get_direction(from, to) {
+            Some(_) => between(from, to) & self.occupied() == 0,
             None => panic!("TBD"), // TODO
         }
     }
@@ -181,6 +214,17 @@ mod tests {
         assert!(!is_diagonal((2, 7), (6, 7)));
     }
     #[test]
+    fn test_square_names() {
+        assert_eq!(square_name((0, 0)), "a1");
+        assert_eq!(square_name((3, 4)), "e4");
+        assert_eq!(square_name((7, 7)), "h8");
+        assert_eq!(parse_square("e4"), Ok((3, 4)));
+        assert_eq!(parse_square("h8"), Ok((7, 7)));
+        assert!(parse_square("i1").is_err());
+        assert!(parse_square("a9").is_err());
+        assert!(parse_square("e44").is_err());
+    }
+    #[test]
     fn test_diagonal_path() {
         // A) (0,1) -> (2,3)
         assert_eq!(Board::get_path((0, 1), (2, 3)), Some(vec![(1, 2)]));
@@ -0,0 +1,115 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+
+impl Board {
+    /**
+    Counts the positions reached by playing every sequence of DEPTH legal moves.
+    Comparing the counts with published values verifies the move generator.
+    */
+    pub fn perft(&self, depth: u32) -> u64 {
+        match depth {
+            0 => 1,
+            // The leaves don't need to be played out, only counted
+            1 => self.legal_moves().len() as u64,
+            _ => self
+                .legal_moves()
+                .into_iter()
+                .map(|mv| self.play(mv).perft(depth - 1))
+                .sum(),
+        }
+    }
+
+    /**
+    Splits perft(DEPTH) by the first move, giving the count after each one.
+    Comparing these with another engine narrows a wrong count down to a move.
+    */
+    pub fn divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        assert!(depth > 0, "Cannot divide perft at depth 0."); // Coder error
+        self.legal_moves()
+            .into_iter()
+            .map(|mv| (mv, self.play(mv).perft(depth - 1)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Published perft counts for well-known positions, starting at depth 1
+    // See https://www.chessprogramming.org/Perft_Results
+    const REFERENCE: [(&str, [u64; 4]); 9] = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            [20, 400, 8902, 197_281],
+        ),
+        // "Kiwipete", full of castling, en passant and promotion
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            [48, 2039, 97_862, 4_085_603],
+        ),
+        (
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            [14, 191, 2812, 43_238],
+        ),
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            [6, 264, 9467, 422_333],
+        ),
+        (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            [44, 1486, 62_379, 2_103_487],
+        ),
+        (
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            [46, 2079, 89_890, 3_894_594],
+        ),
+        // Chess960, castling with Rooks on the F, E and G columns
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            [21, 528, 12_189, 326_672],
+        ),
+        (
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            [21, 807, 18_002, 667_366],
+        ),
+        (
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            [20, 479, 10_471, 273_318],
+        ),
+    ];
+
+    // Checks every reference position up to MAX_NODES nodes
+    fn check_reference(max_nodes: u64) {
+        for (fen, counts) in REFERENCE.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            for (depth, &count) in (1..).zip(counts.iter()) {
+                if count <= max_nodes {
+                    assert_eq!(board.perft(depth), count, "depth {} of {}", depth, fen);
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_perft() {
+        check_reference(100_000);
+    }
+    #[test]
+    #[ignore] // Slow without optimizations. Run with `cargo test --release -- --ignored`
+    fn test_perft_deep() {
+        check_reference(u64::MAX);
+    }
+    #[test]
+    fn test_divide() {
+        let board = Board::new();
+        assert_eq!(board.perft(0), 1);
+        let divided = board.divide(2);
+        assert_eq!(divided.len(), 20);
+        assert!(divided.iter().all(|&(_, count)| count == 20));
+        let divided = board.divide(3);
+        assert_eq!(divided.iter().map(|&(_, count)| count).sum::<u64>(), 8902);
+        let e4 = Move::new(&board, (1, 4), (3, 4), None);
+        assert!(divided.contains(&(e4, 600)));
+    }
+}
@@ -0,0 +1,351 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+use core::iter::Peekable;
+use core::str::Chars;
+
+// Tokens that end the movetext of a game
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+impl Game {
+    /**
+    Write the game in Portable Game Notation: the Seven Tag Roster, any other tags,
+    then the moves in SAN followed by the result.
+    */
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        // The Seven Tag Roster always comes first, in order
+        let mut tags: Vec<&(String, String)> = SEVEN_TAG_ROSTER
+            .iter()
+            .filter_map(|&name| self.tags().iter().find(|(tag, _)| tag == name))
+            .collect();
+        tags.extend(
+            self.tags()
+                .iter()
+                .filter(|(tag, _)| !SEVEN_TAG_ROSTER.contains(&tag.as_str())),
+        );
+        for (name, value) in tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        pgn.push('\n');
+        //
+        // Movetext, wrapped to fit in 80 columns
+        let mut tokens = Vec::new();
+        for (i, (board, &mv)) in self.boards().iter().zip(self.moves()).enumerate() {
+            let move_no = board.turn();
+            if board.player() == White {
+                tokens.push(format!("{}.", move_no));
+            } else if i == 0 {
+                tokens.push(format!("{}...", move_no));
+            }
+            tokens.push(self.variant().san(board, mv));
+        }
+        tokens.push(String::from(self.result()));
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > 79 {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            line_len += token.len();
+            pgn.push_str(&token);
+        }
+        pgn.push('\n');
+        pgn
+    }
+
+    /** Read the first game in PGN. Comments, NAGs and variations are skipped. */
+    pub fn from_pgn(pgn: &str) -> Result<Game, ParseError> {
+        Self::parse_pgn_games(pgn)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ParseError::InvalidPgn(String::from("No game found in PGN.")))
+    }
+
+    /**
+    Read every game in PGN, such as a file collecting many games.
+    Only the main line of each game is kept: comments, NAGs and variations are skipped.
+    */
+    pub fn parse_pgn_games(pgn: &str) -> Result<Vec<Game>, ParseError> {
+        let mut games = Vec::new();
+        let mut parser = PgnParser::new(pgn);
+        while let Some(game) = parser.next_game()? {
+            games.push(game);
+        }
+        Ok(games)
+    }
+}
+
+struct PgnParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    at_line_start: bool,
+    num_games: usize,
+}
+
+impl<'a> PgnParser<'a> {
+    fn new(pgn: &'a str) -> PgnParser<'a> {
+        PgnParser {
+            chars: pgn.chars().peekable(),
+            at_line_start: true,
+            num_games: 0,
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.at_line_start = c == '\n';
+        Some(c)
+    }
+
+    // Skip characters until just after END, or until the input runs out
+    fn skip_past(&mut self, end: char) {
+        while let Some(c) = self.next_char() {
+            if c == end {
+                break;
+            }
+        }
+    }
+
+    // Parse the next game, returning None if only whitespace and comments remain
+    fn next_game(&mut self) -> Result<Option<Game>, ParseError> {
+        let mut tags: Vec<(String, String)> = Vec::new();
+        let mut game: Option<Game> = None;
+        let mut variation_depth = 0;
+        while let Some(&c) = self.chars.peek() {
+            if c == '%' && self.at_line_start {
+                // Escaped line
+                self.skip_past('\n');
+            } else if c.is_whitespace() {
+                self.next_char();
+            } else if c == '{' {
+                self.skip_past('}');
+            } else if c == ';' {
+                self.skip_past('\n');
+            } else if c == '(' {
+                self.next_char();
+                variation_depth += 1;
+            } else if c == ')' {
+                self.next_char();
+                variation_depth -= 1;
+                if variation_depth < 0 {
+                    return Err(self.error("Unmatched ')' in movetext."));
+                }
+            } else if c == '$' {
+                // Numeric Annotation Glyph
+                self.next_char();
+                while self.chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.next_char();
+                }
+            } else if c == '[' && variation_depth == 0 {
+                if game.is_some() {
+                    // The previous game did not end with a result
+                    break;
+                }
+                self.next_char();
+                tags.push(self.tag_pair()?);
+            } else {
+                let token = self.symbol();
+                if token.is_empty() {
+                    // A stray closing bracket or brace
+                    self.next_char();
+                    continue;
+                } else if variation_depth > 0 {
+                    continue;
+                }
+                if RESULTS.contains(&token.as_str()) {
+                    let mut game = match game {
+                        Some(game) => game,
+                        None => self.start_game(&tags)?,
+                    };
+                    game.set_tag("Result", &token);
+                    self.num_games += 1;
+                    return Ok(Some(game));
+                }
+                // Remove the move number and any annotation symbols
+                let san = match token.find('.') {
+                    Some(i) if token[..i].chars().all(|c| c.is_ascii_digit()) => {
+                        token[i..].trim_start_matches('.')
+                    }
+                    _ => token.as_str(),
+                }
+                .trim_end_matches(['!', '?']);
+                if san.is_empty() {
+                    continue;
+                }
+                if game.is_none() {
+                    game = Some(self.start_game(&tags)?);
+                }
+                let game = game.as_mut().unwrap();
+                let board = game.board();
+                let mv = game.variant().parse_move(&board, san).map_err(|error| {
+                    let dots = if board.player() == White { "." } else { "..." };
+                    self.wrap(Some(format!("{}{}", board.turn(), dots)), error)
+                })?;
+                game.play(mv);
+            }
+        }
+        if variation_depth > 0 {
+            return Err(self.error("Unclosed variation in movetext."));
+        }
+        match game {
+            Some(game) => {
+                self.num_games += 1;
+                Ok(Some(game))
+            }
+            None if !tags.is_empty() => {
+                self.num_games += 1;
+                self.start_game(&tags).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Create a game from its tags, starting from the FEN tag if one is given.
+    // Variants not known are played as standard chess.
+    fn start_game(&self, tags: &[(String, String)]) -> Result<Game, ParseError> {
+        let tag = |tag: &str| tags.iter().find(|(name, _)| name == tag).map(|(_, value)| value);
+        let variant = tag("Variant").and_then(|name| find_variant(name)).unwrap_or(&Standard);
+        let mut board = match tag("FEN") {
+            Some(fen) => variant.parse_fen(fen).map_err(|error| self.wrap(None, error))?,
+            None => variant.start(),
+        };
+        if tag("Variant").is_some_and(|variant| variant.eq_ignore_ascii_case("chess960")) {
+            board.set_chess960(true);
+        }
+        let mut game = Game::with_variant(variant, board);
+        for (name, value) in tags {
+            game.set_tag(name, value);
+        }
+        Ok(game)
+    }
+
+    // Parse the remainder of a tag pair after its opening '['
+    fn tag_pair(&mut self) -> Result<(String, String), ParseError> {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.next_char();
+        }
+        let mut name = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_alphanumeric() || c == '_' {
+                name.push(c);
+                self.next_char();
+            } else {
+                break;
+            }
+        }
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.next_char();
+        }
+        if name.is_empty() || self.next_char() != Some('"') {
+            return Err(self.error("Tag pairs must be written [Name \"Value\"]."));
+        }
+        let mut value = String::new();
+        loop {
+            match self.next_char() {
+                Some('\\') => match self.next_char() {
+                    Some(c) => value.push(c),
+                    None => break,
+                },
+                Some('"') => break,
+                Some(c) => value.push(c),
+                None => return Err(self.error("Tag value is missing its closing '\"'.")),
+            }
+        }
+        self.skip_past(']');
+        Ok((name, value))
+    }
+
+    // Read a move, move number or result up to the next space or special character
+    fn symbol(&mut self) -> String {
+        let mut token = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || "{}()[];$".contains(c) {
+                break;
+            }
+            token.push(c);
+            self.next_char();
+        }
+        token
+    }
+
+    fn error(&self, msg: &str) -> ParseError {
+        self.wrap(None, ParseError::InvalidPgn(String::from(msg)))
+    }
+
+    // Place ERROR in the game being parsed, optionally at the move numbered MOVE_NUMBER
+    fn wrap(&self, move_number: Option<String>, error: ParseError) -> ParseError {
+        ParseError::Pgn {
+            game: self.num_games + 1,
+            move_number,
+            error: Box::new(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_write_pgn() {
+        let mut game = Game::new();
+        game.set_tag("White", "Alice");
+        game.set_tag("Annotator", "Bob \"B\"");
+        for san in ["e4", "e5", "Nf3", "Nc6", "Bb5"].iter() {
+            game.play(game.board().parse_san(san).unwrap());
+        }
+        let pgn = game.to_pgn();
+        let expected = "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+            [White \"Alice\"]\n[Black \"?\"]\n[Result \"*\"]\n[Annotator \"Bob \\\"B\\\"\"]\n\n\
+            1. e4 e5 2. Nf3 Nc6 3. Bb5 *\n";
+        assert_eq!(pgn, expected);
+        let parsed = Game::from_pgn(&pgn).unwrap();
+        assert_eq!(parsed.to_pgn(), pgn);
+    }
+    #[test]
+    fn test_read_annotated_pgn() {
+        let pgn = "% Exported by hand\n\
+            [Event \"Casual\"]\n[White \"A\"]\n[Black \"B\"]\n[Result \"1-0\"]\n\n\
+            1. e4 {King's pawn} e5 2. Nf3!? $1 Nc6 (2... d6 3. d4 (3. Bc4) exd4) ; main line\n\
+            3.Bb5 a6 4. Ba4 1-0\n";
+        let game = Game::from_pgn(pgn).unwrap();
+        assert_eq!(game.moves().len(), 7);
+        assert_eq!(game.result(), "1-0");
+        assert_eq!(game.tag("Event"), Some("Casual"));
+        assert_eq!(
+            game.board().to_fen(),
+            "r1bqkbnr/1ppp1ppp/p1n5/4p3/B3P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 1 4"
+        );
+    }
+    #[test]
+    fn test_multiple_games() {
+        let pgn = "[Event \"One\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n\
+            [Event \"Two\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/4K2R w K - 0 40\"]\n\n\
+            40. O-O Kd7 1/2-1/2\n";
+        let games = Game::parse_pgn_games(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].result(), "0-1");
+        assert!(games[0].board().has_no_moves(None));
+        assert_eq!(games[1].moves().len(), 2);
+        assert_eq!(
+            games[1].to_pgn().lines().last(),
+            Some("40. O-O Kd7 1/2-1/2")
+        );
+    }
+    #[test]
+    fn test_illegal_move() {
+        let pgn = "[Event \"?\"]\n\n1. e4 e5 2. Ke3 *";
+        let err = Game::from_pgn(pgn).err().unwrap();
+        assert!(err.to_string().contains("Move 2. Ke3"), "{}", err);
+        match err {
+            ParseError::Pgn { game: 1, error, .. } => assert!(matches!(
+                *error,
+                ParseError::NoPieceCanMove { piece: King, .. }
+            )),
+            _ => panic!("Expected an error in the first game: {}", err),
+        }
+    }
+}
@@ -2,7 +2,7 @@
 
 use crate::board::*;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PieceType {
     Pawn,
     Rook,
@@ -13,7 +13,10 @@ pub enum PieceType {
 }
 pub use PieceType::*;
 
-#[derive(Copy, Clone, PartialEq)]
+// Every type of piece, in the order of its discriminant
+pub const PIECE_TYPES: [PieceType; 6] = [Pawn, Rook, Knight, Bishop, Queen, King];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Piece(pub PieceType, pub Color);
 
 impl Piece {
@@ -23,44 +26,46 @@ impl Piece {
     pub fn color(&self) -> Color {
         self.1
     }
-    pub fn can_move(&self, board: &Board, from: Square, to: Square) -> Result<(), String> {
+    pub fn can_move(&self, board: &Board, from: Square, to: Square) -> Result<(), MoveError> {
         let capturing_piece = board.get(to);
         match self {
             Piece(Pawn, White) => {
-                if capturing_piece.is_none() {
+                if capturing_piece.is_none() && Some(to) != board.en_passant() {
                     // No capturing allowed
-                    if to.1 == from.1 && to.0 - from.0 == 1 {
-                       return Ok(()) // Move forward one square
-                    } else if to.1 == from.1 && from.0 == 1 && to.0 - from.0 == 2 {
-                       return Ok(()) // Starting at home, move forward 2 squares
+                    // Move forward one square, or starting at home, move forward 2 squares
+                    if to.1 == from.1
+                        && (to.0 - from.0 == 1 || (from.0 == 1 && to.0 - from.0 == 2 && board.clear_path(from, to)))
+                    {
+                        return Ok(());
                     }
                 } else {
-                    // Capture a piece by moving one square forward and one square to either side
+                    // Capture a piece (possibly en passant) by moving one square forward and one
+                    // square to either side
                     if (to.1 - from.1).abs() == 1 && to.0 - from.0 == 1 {
                        return Ok(())
                     }
                 }
             }
             Piece(Pawn, Black) => {
-                if capturing_piece.is_none() {
+                if capturing_piece.is_none() && Some(to) != board.en_passant() {
                     // No capturing allowed
-                    if to.1 == from.1 && to.0 - from.0 == -1 {
-                       return Ok(())// Move forward one square
-                    } else if to.1 == from.1 && from.0 == 6 && to.0 - from.0 == -2 {
-                       return Ok(())// Starting at home, move forward 2 squares
+                    // Move forward one square, or starting at home, move forward 2 squares
+                    if to.1 == from.1
+                        && (to.0 - from.0 == -1 || (from.0 == 6 && to.0 - from.0 == -2 && board.clear_path(from, to)))
+                    {
+                        return Ok(());
                     }
                 } else {
-                    // Capturing allowed
+                    // Capturing allowed, including en passant
                     if (to.1 - from.1).abs() == 1 && to.0 - from.0 == -1 {
                        return Ok(())// Capture a piece by moving one square forward and one square to either side
                     }
                 }
             }
             Piece(Rook, ..) => {
-                if is_horizontal(from, to) && board.clear_path(from, to) {
-                   return Ok(())// Horizontal Move
-                } else if is_vertical(from, to) && board.clear_path(from, to) {
-                   return Ok(())// Vertical move
+                // Horizontal or vertical move
+                if (is_horizontal(from, to) || is_vertical(from, to)) && board.clear_path(from, to) {
+                    return Ok(());
                 }
             }
             Piece(Bishop, ..) => {
@@ -81,15 +86,22 @@ impl Piece {
                 (1, 2) => return Ok(()),
                 _ => (),
             },
-            Piece(King, ..) => {
-                if (from.0 - to.0).abs() <= 1 && (from.1 - to.1).abs() <= 1 {
+            Piece(King, color) => {
+                // In Chess960 the King may castle with a Rook right beside it
+                if let Some(side) = board.castle_side(from, to) {
+                    return board.validate_castle(*color, side) // Castling
+                } else if (from.0 - to.0).abs() <= 1 && (from.1 - to.1).abs() <= 1 {
                     return Ok(())
                 }
             }
         };
-        return Err(String::from("Invalid move."));
+        Err(MoveError::IllegalPattern {
+            piece: *self,
+            from,
+            to,
+        })
     }
     pub fn is_king(&self) -> bool {
-        return self.piece_type() == King;
+        self.piece_type() == King
     }
 }
@@ -0,0 +1,207 @@
+// Jack Alpert 2020
+
+use crate::board::path::*;
+use crate::board::*;
+
+// Every type of piece a pocket may hold, in the order of its discriminant
+pub const POCKET_TYPES: [PieceType; 5] = [Pawn, Rook, Knight, Bishop, Queen];
+
+// The first and last rows, where Pawns cannot be dropped
+const END_ROWS: Bitboard = 0xFF | 0xFF << 56;
+
+impl Move {
+    /**
+    Describe dropping a PIECE_TYPE from the current player's pocket onto TO.
+    Does not check that the drop is valid.
+    */
+    pub fn new_drop(piece_type: PieceType, to: Square) -> Move {
+        Move {
+            from: to,
+            to,
+            promotion: None,
+            capture: false,
+            castle: false,
+            en_passant: false,
+            drop: Some(piece_type),
+        }
+    }
+}
+
+impl Board {
+    /** Returns how many pieces of PIECE_TYPE PLAYER holds in their pocket, ready to drop */
+    pub fn pocket(&self, player: Color, piece_type: PieceType) -> u8 {
+        match piece_type {
+            King => 0,
+            _ => self.pockets[player as usize][piece_type as usize],
+        }
+    }
+
+    /** Put COUNT pieces of PIECE_TYPE in PLAYER's pocket. A King can never be held. */
+    pub fn set_pocket(&mut self, player: Color, piece_type: PieceType, count: u8) {
+        assert!(piece_type != King, "A King cannot be held in a pocket."); // Coder error
+        self.pockets[player as usize][piece_type as usize] = count;
+    }
+
+    /** Returns true if the piece at LOC was promoted from a Pawn, so is pocketed as a Pawn when captured */
+    pub fn is_promoted(&self, loc: Square) -> bool {
+        self.promoted & square_bit(loc) != 0
+    }
+
+    /**
+    Ensure PLAYER may drop a PIECE_TYPE from their pocket onto TO: they hold one, the square
+    is empty, a Pawn is not dropped on the first or last row and the King is not left in check.
+    Dropping a piece between a checking piece and the King blocks the check.
+    If PLAYER is None, defaults to current player.
+    */
+    pub fn validate_drop(
+        &self,
+        piece_type: PieceType,
+        to: Square,
+        player: Option<Color>,
+    ) -> Result<(), MoveError> {
+        let player = player.unwrap_or(self.player);
+        if !in_bounds(to) {
+            return Err(MoveError::OutOfBounds(to));
+        } else if self.pocket(player, piece_type) == 0 {
+            return Err(MoveError::EmptyPocket(piece_type));
+        } else if self.get(to).is_some() {
+            return Err(MoveError::SquareOccupied(to));
+        } else if piece_type == Pawn && END_ROWS & square_bit(to) != 0 {
+            return Err(MoveError::PawnDropRow(to));
+        }
+        let king = match self.find_king(Some(player)) {
+            Some(king) => king,
+            None => return Ok(()), // Nothing to protect
+        };
+        let checkers = self.squares_checking(king, Some(player));
+        match checkers.len() {
+            0 => Ok(()),
+            1 if between(checkers[0], king) & square_bit(to) != 0 => Ok(()),
+            1 => Err(MoveError::StillInCheck { checkers }),
+            _ => Err(MoveError::MustMoveKing { checkers }),
+        }
+    }
+
+    /** Returns every valid drop for PLAYER, or the current player if None */
+    pub fn legal_drops(&self, player: Option<Color>) -> Vec<Move> {
+        let player = player.unwrap_or(self.player);
+        let mut drops = Vec::new();
+        if self.pockets[player as usize] == [0; 5] {
+            return drops;
+        }
+        // Dropping a piece never exposes the King, but only blocking can answer a check
+        let mut targets = !self.occupied();
+        if let Some(king) = self.find_king(Some(player)) {
+            match self.squares_checking(king, Some(player)).as_slice() {
+                [] => (),
+                [checker] => targets &= between(*checker, king),
+                _ => targets = 0,
+            }
+        }
+        for &piece_type in &POCKET_TYPES {
+            if self.pocket(player, piece_type) == 0 {
+                continue;
+            }
+            let squares = match piece_type {
+                Pawn => targets & !END_ROWS,
+                _ => targets,
+            };
+            drops.extend(Squares(squares).map(|to| Move::new_drop(piece_type, to)));
+        }
+        drops
+    }
+
+    // Drop a PIECE_TYPE from the current player's pocket onto TO by returning a copy of
+    // self with the changes applied
+    pub(crate) fn execute_drop(&self, piece_type: PieceType, to: Square) -> Board {
+        assert!(self.pocket(self.player, piece_type) > 0); // Coder error
+        let mut new_board = *self;
+        new_board.set(to, Some(Piece(piece_type, self.player)));
+        new_board.pockets[self.player as usize][piece_type as usize] -= 1;
+        new_board.en_passant = None;
+        // A Pawn arriving on the board can't be undone, like a Pawn move
+        new_board.halfmove_clock = match piece_type {
+            Pawn => 0,
+            _ => self.halfmove_clock.saturating_add(1),
+        };
+        new_board.player = self.player.other();
+        if self.player == Black {
+            new_board.fullmove = self.fullmove.saturating_add(1);
+        }
+        new_board
+    }
+
+    /**
+    Find the valid drop written as TEXT: the piece's letter, '@' and the square, e.g. "N@f3".
+    A Pawn's letter may be left out, e.g. "@e4". Check and checkmate symbols are ignored.
+    */
+    pub fn parse_drop(&self, text: &str) -> Result<Move, ParseError> {
+        let text = text.trim();
+        let stripped = text.trim_end_matches(|c| "+#!?".contains(c));
+        let (piece, square) = stripped
+            .split_once('@')
+            .ok_or_else(|| ParseError::NotSan(String::from(text)))?;
+        let piece_type = match piece {
+            "" => Pawn,
+            piece => piece.parse()?,
+        };
+        let to = parse_square(square)?;
+        self.validate_drop(piece_type, to, None)
+            .map_err(|error| ParseError::IllegalMove {
+                notation: String::from(text),
+                error,
+            })?;
+        Ok(Move::new_drop(piece_type, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_drops() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(board.legal_drops(None).is_empty());
+        board.set_pocket(White, Pawn, 2);
+        board.set_pocket(White, Knight, 1);
+        // Pawns may not be dropped on the first or last row
+        assert_eq!(board.legal_drops(None).len(), 48 + 62);
+        assert_eq!(Crazyhouse.legal_moves(&board).len(), 5 + 48 + 62);
+        assert_eq!(board.legal_moves().len(), 5); // Only Crazyhouse has drops
+        assert_eq!(board.validate_drop(Pawn, (7, 0), None), Err(MoveError::PawnDropRow((7, 0))));
+        assert_eq!(board.validate_drop(Knight, (0, 4), None), Err(MoveError::SquareOccupied((0, 4))));
+        assert_eq!(board.validate_drop(Queen, (3, 3), None), Err(MoveError::EmptyPocket(Queen)));
+        assert_eq!(MoveError::EmptyPocket(Queen).to_string(), "You have no Queen in your pocket to drop.");
+        let drop = board.parse_drop("N@f3").unwrap();
+        assert_eq!(drop, Crazyhouse.parse_move(&board, "N@f3").unwrap());
+        assert!(board.parse_uci("N@f3").is_err());
+        assert!(board.parse_san("N@f3").is_err());
+        assert_eq!(drop.to_string(), "N@f3");
+        assert_eq!(Crazyhouse.san(&board, drop), "N@f3");
+        assert_eq!(board.parse_drop("@e4").unwrap(), Move::new_drop(Pawn, (3, 4)));
+        let after = board.play(drop);
+        assert_eq!(after.get((2, 5)), Some(Piece(Knight, White)));
+        assert_eq!(after.pocket(White, Knight), 0);
+        assert_eq!(after.pocket(White, Pawn), 2);
+        assert_eq!(after.player(), Black);
+    }
+    #[test]
+    fn test_drop_blocks_check() {
+        // The Rook checks along the first row, so only b1, c1 and d1 block it
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1").unwrap();
+        board.set_pocket(White, Bishop, 1);
+        let drops: Vec<Square> = board.legal_drops(None).iter().map(|mv| mv.to).collect();
+        assert_eq!(drops, vec![(0, 1), (0, 2), (0, 3)]);
+        assert!(matches!(
+            board.validate_drop(Bishop, (4, 4), None),
+            Err(MoveError::StillInCheck { .. })
+        ));
+        // A back row mate, unless a piece can be dropped in the way
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/5PPP/r5K1 w - - 0 1").unwrap();
+        assert!(Crazyhouse.legal_moves(&board).is_empty());
+        board.set_pocket(White, Pawn, 1);
+        assert!(Crazyhouse.legal_moves(&board).is_empty()); // A Pawn can't go on the first row
+        board.set_pocket(White, Knight, 1);
+        assert_eq!(Crazyhouse.legal_moves(&board).len(), 5);
+    }
+}
@@ -0,0 +1,417 @@
+// Jack Alpert 2020
+
+use crate::board::path::*;
+use crate::board::*;
+
+impl PieceType {
+    // Letter for this type of piece in algebraic notation
+    pub fn letter(&self) -> char {
+        Piece(*self, White).fen_char()
+    }
+}
+
+impl Board {
+    /**
+    Describe MV in Standard Algebraic Notation, e.g. "Nf3", "exd5", "O-O" or "e8=Q+".
+    MV must be a valid move for the current player.
+    */
+    pub fn san(&self, mv: Move) -> String {
+        let mut san = self.san_without_suffix(mv, &self.legal_moves());
+        let after = self.play(mv);
+        if after.in_check() {
+            san.push(if after.has_no_moves(None) { '#' } else { '+' });
+        }
+        san
+    }
+
+    /**
+    Find the valid move described by SAN. Besides strict SAN, this accepts captures
+    without the 'x', castling written with zeros ("0-0"), promotions without the '='
+    ("e8Q") and moves giving the starting square ("Ng1-f3", "e2e4").
+    Check, checkmate and annotation symbols at the end are ignored.
+    Returns an error explaining why SAN is malformed, ambiguous or not a valid move.
+    */
+    pub fn parse_san(&self, san: &str) -> Result<Move, ParseError> {
+        let san = san.trim();
+        let stripped = san
+            .trim_end_matches(|c| "+#!?".contains(c))
+            .trim_end_matches("e.p.")
+            .trim_end();
+        let side = match stripped {
+            "O-O" | "0-0" => Some(KingSide),
+            "O-O-O" | "0-0-0" => Some(QueenSide),
+            _ => None,
+        };
+        if let Some(side) = side {
+            let king = self.find_king(None).expect("This player has no King!");
+            let to = self.castle_target(self.player, side).unwrap_or((king.0, side.king_to()));
+            let result = if self.castle_side(king, to) != Some(side) {
+                Err(MoveError::CastlingRightLost(side))
+            } else {
+                self.validate_move(king, to, None)
+            };
+            return match result {
+                Ok(()) => Ok(Move::new(self, king, to, None)),
+                Err(error) => Err(ParseError::IllegalMove {
+                    notation: String::from(san),
+                    error,
+                }),
+            };
+        }
+        let parts =
+            SanParts::parse(stripped).ok_or_else(|| ParseError::NotSan(String::from(san)))?;
+        //
+        // Find every piece that could be making this move
+        let movers: Vec<Square> = self
+            .find_pieces(None)
+            .into_iter()
+            .filter(|&from| parts.could_move_from(self, from))
+            .collect();
+        let mut valid: Vec<Square> = movers
+            .iter()
+            .copied()
+            .filter(|&from| self.validate_move(from, parts.to, None).is_ok())
+            .collect();
+        match valid.len() {
+            0 => Err(match movers.first() {
+                // Explain why the only piece that could make this move cannot
+                Some(&from) if movers.len() == 1 => ParseError::IllegalMove {
+                    notation: String::from(san),
+                    error: self.validate_move(from, parts.to, None).unwrap_err(),
+                },
+                _ => ParseError::NoPieceCanMove {
+                    notation: String::from(san),
+                    piece: parts.piece_type,
+                    to: parts.to,
+                },
+            }),
+            1 => {
+                let from = valid.pop().unwrap();
+                self.validate_promotion(from, parts.to, parts.promotion)
+                    .map_err(|error| ParseError::IllegalMove {
+                        notation: String::from(san),
+                        error,
+                    })?;
+                Ok(Move::new(self, from, parts.to, parts.promotion))
+            }
+            _ => {
+                let options: Vec<String> = valid
+                    .into_iter()
+                    .map(|from| {
+                        let promotion = parts.promotion.or(Some(Queen));
+                        let promotion = promotion.filter(|_| self.is_promotion(from, parts.to));
+                        self.san(Move::new(self, from, parts.to, promotion))
+                    })
+                    .collect();
+                Err(ParseError::AmbiguousMove {
+                    notation: String::from(san),
+                    options,
+                })
+            }
+        }
+    }
+
+    /**
+    Describe MV in SAN without a check symbol, using MOVES, every valid move in this
+    position, to tell it apart from the others. For variants whose moves differ from
+    those of standard chess.
+    */
+    pub fn san_among(&self, mv: Move, moves: &[Move]) -> String {
+        self.san_without_suffix(mv, moves)
+    }
+
+    /**
+    Find the move among MOVES, every valid move in this position, written as TEXT in SAN
+    or long algebraic notation. For variants whose moves differ from those of standard
+    chess, so the reasons a move is invalid are not given.
+    */
+    pub fn parse_move_among(&self, text: &str, moves: &[Move]) -> Result<Move, ParseError> {
+        let text = text.trim();
+        if let Some(&mv) = moves.iter().find(|mv| mv.to_string() == text) {
+            return Ok(mv);
+        }
+        let stripped = text
+            .trim_end_matches(|c| "+#!?".contains(c))
+            .trim_end_matches("e.p.")
+            .trim_end();
+        let side = match stripped {
+            "O-O" | "0-0" => Some(KingSide),
+            "O-O-O" | "0-0-0" => Some(QueenSide),
+            _ => None,
+        };
+        let found: Vec<Move> = match side {
+            Some(side) => {
+                let found: Vec<Move> = moves
+                    .iter()
+                    .filter(|mv| mv.castle && self.castle_side(mv.from, mv.to) == Some(side))
+                    .copied()
+                    .collect();
+                if found.is_empty() {
+                    return Err(ParseError::IllegalMove {
+                        notation: String::from(text),
+                        error: MoveError::CastlingRightLost(side),
+                    });
+                }
+                found
+            }
+            None => {
+                let parts = SanParts::parse(stripped)
+                    .ok_or_else(|| ParseError::NotSan(String::from(text)))?;
+                let found: Vec<Move> = moves
+                    .iter()
+                    .filter(|mv| parts.describes(self, mv))
+                    .copied()
+                    .collect();
+                if found.is_empty() {
+                    return Err(ParseError::NoPieceCanMove {
+                        notation: String::from(text),
+                        piece: parts.piece_type,
+                        to: parts.to,
+                    });
+                }
+                found
+            }
+        };
+        if found.len() == 1 {
+            Ok(found[0])
+        } else {
+            Err(ParseError::AmbiguousMove {
+                notation: String::from(text),
+                options: found.iter().map(|&mv| self.san_among(mv, moves)).collect(),
+            })
+        }
+    }
+
+    // SAN for MV without the check or checkmate symbol.
+    // LEGAL_MOVES are used to tell apart pieces of the same type moving to the same square
+    fn san_without_suffix(&self, mv: Move, legal_moves: &[Move]) -> String {
+        if mv.drop.is_some() {
+            return mv.to_string(); // e.g. N@f3
+        }
+        let piece = self.get(mv.from).expect("No piece exists here."); // Coder error
+        match self.castle_side(mv.from, mv.to) {
+            Some(KingSide) => return String::from("O-O"),
+            Some(QueenSide) => return String::from("O-O-O"),
+            None => (),
+        }
+        let from_name = square_name(mv.from);
+        let mut san = String::new();
+        if piece.piece_type() == Pawn {
+            // Pawns are identified by their column when capturing
+            if mv.capture {
+                san.push_str(&from_name[..1]);
+            }
+        } else {
+            san.push(piece.piece_type().letter());
+            let others: Vec<Square> = legal_moves
+                .iter()
+                .filter(|other| {
+                    other.to == mv.to
+                        && other.from != mv.from
+                        && self.get(other.from) == Some(piece)
+                })
+                .map(|other| other.from)
+                .collect();
+            // Prefer the column, then the row, then both
+            if !others.is_empty() {
+                if others.iter().all(|other| other.1 != mv.from.1) {
+                    san.push_str(&from_name[..1]);
+                } else if others.iter().all(|other| other.0 != mv.from.0) {
+                    san.push_str(&from_name[1..]);
+                } else {
+                    san.push_str(&from_name);
+                }
+            }
+        }
+        if mv.capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(mv.to));
+        if let Some(piece_type) = mv.promotion {
+            san.push('=');
+            san.push(piece_type.letter());
+        }
+        san
+    }
+}
+
+// The pieces of a move written in algebraic notation, other than castling
+struct SanParts {
+    piece_type: PieceType,
+    from_col: Option<isize>, // Given to tell apart pieces of the same type
+    from_row: Option<isize>,
+    to: Square,
+    promotion: Option<PieceType>,
+}
+
+impl SanParts {
+    // Returns None if SAN is not made up of the expected parts
+    fn parse(san: &str) -> Option<SanParts> {
+        // Capture symbols and dashes between squares carry no information
+        let mut chars: Vec<char> = san.chars().filter(|c| !"x:-=".contains(*c)).collect();
+        // A piece letter following the row it moves to is a promotion
+        let promotion = match chars.as_slice() {
+            [.., '1'..='8', c] => Some(c.to_string().parse().ok()?),
+            _ => None,
+        };
+        if promotion.is_some() {
+            chars.pop();
+        }
+        let to_len = chars.len().checked_sub(2)?;
+        let to = parse_square(&chars[to_len..].iter().collect::<String>()).ok()?;
+        chars.truncate(to_len);
+        // The piece letter must be capitalized to tell Bishops apart from the b column
+        let piece_type = match chars.first() {
+            Some(c) if "KQRBN".contains(*c) => {
+                let piece_type = c.to_string().parse().ok()?;
+                chars.remove(0);
+                piece_type
+            }
+            _ => Pawn,
+        };
+        let (mut from_col, mut from_row) = (None, None);
+        for c in chars {
+            match c {
+                'a'..='h' if from_col.is_none() && from_row.is_none() => {
+                    from_col = Some(c as isize - 'a' as isize)
+                }
+                '1'..='8' if from_row.is_none() => from_row = Some(c as isize - '1' as isize),
+                _ => return None,
+            }
+        }
+        Some(SanParts {
+            piece_type,
+            from_col,
+            from_row,
+            to,
+            promotion,
+        })
+    }
+
+    // Returns true if MV, a valid move on BOARD, is the one these parts describe
+    fn describes(&self, board: &Board, mv: &Move) -> bool {
+        let from_col = match self.from_col {
+            None if self.piece_type == Pawn => Some(self.to.1),
+            from_col => from_col,
+        };
+        board
+            .get(mv.from)
+            .is_some_and(|piece| piece.piece_type() == self.piece_type)
+            && !mv.castle
+            && mv.to == self.to
+            && mv.promotion == self.promotion
+            && from_col.is_none_or(|col| col == mv.from.1)
+            && self.from_row.is_none_or(|row| row == mv.from.0)
+    }
+
+    // Returns true if the piece at FROM matches these parts and moves there in the usual way
+    fn could_move_from(&self, board: &Board, from: Square) -> bool {
+        let piece = match board.get(from) {
+            Some(piece) if piece.piece_type() == self.piece_type => piece,
+            _ => return false,
+        };
+        // A Pawn moving forward keeps its column, which is left out of SAN
+        let from_col = match self.from_col {
+            None if self.piece_type == Pawn => Some(self.to.1),
+            from_col => from_col,
+        };
+        from_col.is_none_or(|col| col == from.1)
+            && self.from_row.is_none_or(|row| row == from.0)
+            && board
+                .get(self.to)
+                .is_none_or(|other| other.color() != piece.color())
+            && piece.can_move(board, from, self.to).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_san_round_trip() {
+        let mut board = Board::new();
+        for san in [
+            "e4", "d5", "exd5", "Qxd5", "Nc3", "Qa5", "Nf3", "Nf6", "Bc4", "Bg4",
+        ]
+        .iter()
+        {
+            let mv = board.parse_san(san).unwrap();
+            assert_eq!(board.san(mv), *san);
+            board = board.play(mv);
+        }
+        assert_eq!(board.san(board.parse_san("O-O").unwrap()), "O-O");
+        assert!(board.parse_san("O-O-O").is_err());
+    }
+    #[test]
+    fn test_disambiguation() {
+        // Three Queens can reach c3
+        let board = Board::from_fen("7K/8/8/7k/8/Q7/8/Q1Q5 w - - 0 1").unwrap();
+        let san_from = |from| board.san(Move::new(&board, from, (2, 2), None));
+        assert_eq!(san_from((0, 0)), "Qa1c3");
+        assert_eq!(san_from((2, 0)), "Q3c3");
+        assert_eq!(san_from((0, 2)), "Qcc3");
+        for san in ["Qa1c3", "Q3c3", "Qcc3", "Qa1xc3", "Qa1-c3"].iter() {
+            let mv = board.parse_san(san).unwrap();
+            assert_eq!(mv.to, (2, 2));
+        }
+        let err = board.parse_san("Qc3").unwrap_err();
+        assert!(err.to_string().contains("ambiguous"), "{}", err);
+        match err {
+            ParseError::AmbiguousMove { options, .. } => assert_eq!(options.len(), 3),
+            _ => panic!("Expected Qc3 to be ambiguous: {}", err),
+        }
+        let err = board.parse_san("Qac3").unwrap_err();
+        assert!(matches!(err, ParseError::AmbiguousMove { .. }), "{}", err);
+    }
+    #[test]
+    fn test_lenient_forms() {
+        let board = Board::new();
+        assert_eq!(board.parse_san("e2e4"), board.parse_san("e4"));
+        assert_eq!(board.parse_san("Ng1-f3"), board.parse_san("Nf3"));
+        assert_eq!(board.parse_san("Nf3!?"), board.parse_san("Nf3"));
+
+        let board = Board::from_fen("r3k3/1P6/8/3pP3/8/8/8/4K2R w Kq d6 0 1").unwrap();
+        assert_eq!(board.parse_san("0-0"), board.parse_san("O-O"));
+        assert_eq!(board.parse_san("exd6 e.p."), board.parse_san("exd6"));
+        assert!(board.parse_san("exd6").unwrap().en_passant);
+        let promotion = board.parse_san("bxa8=N+").unwrap();
+        assert_eq!(promotion.promotion, Some(Knight));
+        assert_eq!(board.parse_san("bxa8N"), Ok(promotion));
+        assert_eq!(board.san(board.parse_san("b8Q").unwrap()), "b8=Q+");
+    }
+    #[test]
+    fn test_invalid() {
+        let board = Board::from_fen("r3k3/1P6/8/3pP3/8/8/8/4K2R w Kq d6 0 1").unwrap();
+        let expect_err = |san: &str, msg: &str| {
+            let err = board.parse_san(san).unwrap_err().to_string();
+            assert!(err.contains(msg), "{}: {}", san, err);
+        };
+        expect_err("Nf3", "No Knight can move to f3");
+        expect_err("Rh9", "not written in Standard Algebraic Notation");
+        expect_err("hello", "not written in Standard Algebraic Notation");
+        expect_err("O-O-O", "The King or Rook has already moved");
+        expect_err("b8", "Must choose a piece");
+        expect_err("b8=K", "can only be promoted");
+        expect_err("e4", "No Pawn can move to e4");
+        // The King may not move into check from the Pawn
+        let board = Board::from_fen("4k3/8/8/8/8/3p4/8/4K3 w - - 0 1").unwrap();
+        let err = board.parse_san("Ke2").unwrap_err();
+        assert!(err.to_string().contains("check"), "{}", err);
+        assert_eq!(
+            err,
+            ParseError::IllegalMove {
+                notation: String::from("Ke2"),
+                error: MoveError::IntoCheck((1, 4)),
+            }
+        );
+    }
+    #[test]
+    fn test_check_and_mate() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let mate = board.parse_san("Ra8#").unwrap();
+        assert_eq!(board.san(mate), "Ra8#");
+        let board = Board::from_fen("6k1/8/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let check = board.parse_san("Ra8").unwrap();
+        assert_eq!(board.san(check), "Ra8+");
+    }
+}
@@ -0,0 +1,714 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+use alloc::sync::Arc;
+use core::cmp::Reverse;
+use core::mem;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/** Score of checkmating on the next move. Mates further away score one less per ply. */
+pub const MATE: i32 = 30_000;
+// Greater than any score a search can return
+const INFINITY: i32 = 32_000;
+// The deepest the search goes, counting quiescence and check extensions
+pub const MAX_PLY: usize = 96;
+/** Scores within this of MATE are mates, even those endgame tables find hundreds of plies away */
+pub const MATE_RANGE: i32 = 512;
+// How many nodes are searched between checks of the clock
+const CHECK_INTERVAL: u64 = 1024;
+
+// Values used to order captures, indexed by PieceType
+const PIECE_VALUES: [i32; 6] = [100, 500, 320, 330, 900, 20_000];
+
+/**
+If SCORE is a forced mate, returns the number of moves until it happens: positive
+if the player to move gives mate and negative if they are mated.
+*/
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score.abs() < MATE - MATE_RANGE {
+        None
+    } else if score > 0 {
+        Some((MATE - score + 1) / 2)
+    } else {
+        Some(-(MATE + score) / 2)
+    }
+}
+
+/**
+When to stop searching. The search ends at whichever limit is reached first and
+only ever returns the result of the deepest iteration completed.
+The time limit is ignored without the std feature, as there is no clock.
+*/
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+impl SearchLimits {
+    /** Search exactly DEPTH moves deep */
+    pub fn depth(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        }
+    }
+    /** Search as deep as possible within TIME */
+    pub fn time(time: Duration) -> SearchLimits {
+        SearchLimits {
+            time: Some(time),
+            ..SearchLimits::default()
+        }
+    }
+}
+
+/** The outcome of a search: the move to play and what the engine thinks of it */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>, // None if the game is already over
+    pub score: i32, // In centipawns for the player to move
+    pub depth: u32, // Of the deepest iteration completed
+    pub nodes: u64,
+    pub pv: Vec<Move>, // The line of play expected, starting with best_move
+}
+
+/**
+Chooses moves by iterative deepening negamax search with alpha-beta pruning, then
+searches captures until the position is quiet. Positions already searched are kept in a
+transposition table. Moves are ordered by the best move found for the position before,
+then captures by most valuable victim and least valuable attacker, then killer moves and
+finally the history of quiet moves that caused cutoffs.
+*/
+pub struct Engine {
+    killers: Vec<[Option<Move>; 2]>, // Quiet moves that caused a cutoff, by ply
+    history: Vec<[[u32; 64]; 64]>, // Cutoffs by quiet moves, by Color, from and to
+    positions: Vec<Board>, // Positions since the last capture or Pawn move
+    pv: Vec<Vec<Move>>, // The best line found from each ply
+    last_pv: Vec<Move>, // The best line of the last iteration, searched first
+    root_depth: u32, // Of the iteration being searched
+    nodes: u64,
+    limits: SearchLimits,
+    #[cfg(feature = "std")]
+    start: Instant,
+    stopped: bool,
+    stop: Arc<AtomicBool>, // Set from another thread to end the search early
+    config: EvalConfig,
+    table: TranspositionTable,
+    book: Option<(Book, BookChoice)>, // Played from while it has a move for the position
+    random: u64, // State of the generator used to pick weighted book moves
+    #[cfg(feature = "std")]
+    tablebase: Option<Tablebase>,
+    root_moves: Vec<Move>, // The only moves searched at the root, if not empty
+    endgames: Option<EndgameTables>, // Looked up instead of searching the positions they cover
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::with_config(EvalConfig::default())
+    }
+
+    /** An engine that scores positions with the weights in CONFIG */
+    pub fn with_config(config: EvalConfig) -> Engine {
+        Engine::with_table(config, TranspositionTable::default())
+    }
+
+    fn with_table(config: EvalConfig, table: TranspositionTable) -> Engine {
+        Engine {
+            killers: vec![[None; 2]; MAX_PLY],
+            history: vec![[[0; 64]; 64]; 2],
+            positions: Vec::new(),
+            pv: vec![Vec::new(); MAX_PLY + 1],
+            last_pv: Vec::new(),
+            root_depth: 0,
+            nodes: 0,
+            limits: SearchLimits::default(),
+            #[cfg(feature = "std")]
+            start: Instant::now(),
+            stopped: false,
+            stop: Arc::new(AtomicBool::new(false)),
+            config,
+            table,
+            book: None,
+            random: first_random(),
+            #[cfg(feature = "std")]
+            tablebase: None,
+            root_moves: Vec::new(),
+            endgames: None,
+        }
+    }
+
+    /** Replace the transposition table with an empty one of about MEGABYTES */
+    pub fn set_table_size(&mut self, megabytes: usize) {
+        self.table = TranspositionTable::new(megabytes);
+    }
+
+    /** Play the moves BOOK gives, picked as CHOICE asks, instead of searching. None stops using a book. */
+    pub fn set_book(&mut self, book: Option<Book>, choice: BookChoice) {
+        self.book = book.map(|book| (book, choice));
+    }
+
+    /**
+    In positions TABLEBASE covers, only search the moves that keep the best result, and
+    of those that win, the ones that reach a capture or Pawn move soonest. None stops
+    using a tablebase.
+    */
+    #[cfg(feature = "std")]
+    pub fn set_tablebase(&mut self, tablebase: Option<Tablebase>) {
+        self.tablebase = tablebase;
+    }
+
+    /**
+    Play and score positions ENDGAMES covers from the tables instead of searching them.
+    The tables don't know the fifty-move rule. None stops using endgame tables.
+    */
+    pub fn set_endgame_tables(&mut self, endgames: Option<EndgameTables>) {
+        self.endgames = endgames;
+    }
+
+    /** Forget everything learned from earlier searches, e.g. when starting a new game */
+    pub fn clear(&mut self) {
+        let stop = self.stop.clone();
+        let book = self.book.take();
+        let endgames = self.endgames.take();
+        #[cfg(feature = "std")]
+        let tablebase = self.tablebase.take();
+        let mut table = mem::replace(&mut self.table, TranspositionTable::new(0));
+        table.clear();
+        *self = Engine::with_table(self.config.clone(), table);
+        self.stop = stop;
+        self.book = book;
+        self.endgames = endgames;
+        #[cfg(feature = "std")]
+        {
+            self.tablebase = tablebase;
+        }
+    }
+
+    /**
+    A flag that ends the current search as soon as it is set, e.g. from another thread.
+    The best move found so far is still returned. Searches started while the flag is
+    set stop straight away, so it must be cleared before searching again.
+    */
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /**
+    Finds the best move in the current position of GAME within LIMITS.
+    The earlier positions of the game are used to avoid or aim for repetitions.
+    */
+    pub fn search(&mut self, game: &Game, limits: SearchLimits) -> SearchResult {
+        self.search_with(game, limits, |_| ())
+    }
+
+    /** Like search, but calls ON_ITERATION with the result of each depth as it is completed */
+    pub fn search_with<F>(&mut self, game: &Game, limits: SearchLimits, mut on_iteration: F) -> SearchResult
+    where
+        F: FnMut(&SearchResult),
+    {
+        let board = game.board();
+        if let Some(mv) = self.book_move(&board) {
+            return SearchResult {
+                best_move: Some(mv),
+                score: 0,
+                depth: 0,
+                nodes: 0,
+                pv: vec![mv],
+            };
+        }
+        if let Some(result) = self.endgame_result(&board) {
+            return result;
+        }
+        self.root_moves = self.tablebase_moves(&board);
+        let reversible = board.halfmove_clock() as usize + 1;
+        let boards = game.boards();
+        self.positions = boards[boards.len().saturating_sub(reversible)..].to_vec();
+        self.nodes = 0;
+        self.limits = limits;
+        #[cfg(feature = "std")]
+        {
+            self.start = Instant::now();
+        }
+        self.stopped = false;
+        self.last_pv.clear();
+        self.table.new_search();
+        for killers in self.killers.iter_mut() {
+            *killers = [None; 2];
+        }
+        // Old history is still a good guide, but should not outweigh what is learned now
+        for moves in self.history.iter_mut().flat_map(|from| from.iter_mut()) {
+            for count in moves.iter_mut() {
+                *count /= 8;
+            }
+        }
+
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: Vec::new(),
+        };
+        let max_depth = limits.depth.unwrap_or(MAX_PLY as u32 / 2).max(1);
+        for depth in 1..=max_depth {
+            self.root_depth = depth;
+            let score = self.negamax(&board, depth, 0, -INFINITY, INFINITY);
+            // A search cut short may have missed a better move, unless it is the only one done
+            if self.stopped && result.best_move.is_some() {
+                break;
+            }
+            result = SearchResult {
+                best_move: self.pv[0].first().copied(),
+                score,
+                depth,
+                nodes: self.nodes,
+                pv: self.pv[0].clone(),
+            };
+            self.last_pv = result.pv.clone();
+            on_iteration(&result);
+            if self.stopped
+                || result.best_move.is_none()
+                || self.out_of_time(2)
+                || self.stop.load(Ordering::Relaxed)
+            {
+                break;
+            }
+        }
+        // Stopped before a single move was searched, so any move will have to do
+        if result.best_move.is_none() {
+            result.best_move = board.legal_moves().first().copied();
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    // A move from the book for BOARD, if there is a book and it has one
+    fn book_move(&mut self, board: &Board) -> Option<Move> {
+        let (book, choice) = self.book.as_ref()?;
+        book.choose(board, *choice, splitmix64(&mut self.random))
+    }
+
+    // The best move and line from the endgame tables for BOARD, if they cover it
+    fn endgame_result(&self, board: &Board) -> Option<SearchResult> {
+        let endgames = self.endgames.as_ref()?;
+        let (mv, dtm) = endgames.best_move(board)?;
+        Some(SearchResult {
+            best_move: Some(mv),
+            score: dtm.score(0),
+            depth: 0,
+            nodes: 0,
+            pv: endgames.line(board),
+        })
+    }
+
+    // The moves the tablebase ranks best on BOARD, or none if there is no tablebase or
+    // it doesn't cover BOARD
+    #[cfg(feature = "std")]
+    fn tablebase_moves(&mut self, board: &Board) -> Vec<Move> {
+        let moves = match self.tablebase.as_mut().and_then(|tablebase| tablebase.root_moves(board)) {
+            Some(moves) => moves,
+            None => return Vec::new(),
+        };
+        let clock = board.halfmove_clock() as i32;
+        let best = moves.iter().map(|&(_, dtz)| tablebase_rank(dtz, clock)).max();
+        moves
+            .into_iter()
+            .filter(|&(_, dtz)| Some(tablebase_rank(dtz, clock)) == best)
+            .map(|(mv, _)| mv)
+            .collect()
+    }
+    #[cfg(not(feature = "std"))]
+    fn tablebase_moves(&mut self, _board: &Board) -> Vec<Move> {
+        Vec::new()
+    }
+
+    // Returns the score of BOARD for the player to move, searching DEPTH more moves
+    // at PLY moves from the root. Scores outside ALPHA..BETA are not exact.
+    fn negamax(&mut self, board: &Board, depth: u32, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv[ply].clear();
+        if ply > 0 && self.is_draw(board) {
+            return 0;
+        }
+        let endgames = self.endgames.as_ref().filter(|_| ply > 0);
+        if let Some(dtm) = endgames.and_then(|endgames| endgames.probe(board)) {
+            self.count_node();
+            return dtm.score(ply);
+        }
+        let in_check = board.in_check();
+        // Look further when in check so the line is not cut off in the middle of an attack,
+        // but only for the first plies so a long run of checks can't extend it forever
+        let extend = in_check && (ply as u32) < 2 * self.root_depth;
+        let depth = if extend { depth + 1 } else { depth };
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiesce(board, ply, alpha, beta);
+        }
+        self.count_node();
+
+        // A search at least as deep may already have settled this position
+        let key = board.hash();
+        let entry = self.table.probe(key).copied();
+        if let Some(entry) = entry.filter(|entry| ply > 0 && entry.depth >= depth) {
+            let score = entry.score(ply);
+            let settled = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if settled {
+                if entry.bound == Bound::Exact {
+                    self.pv[ply] = self.table_line(board, depth as usize);
+                }
+                return score;
+            }
+        }
+
+        let mut moves = board.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        if ply == 0 && !self.root_moves.is_empty() {
+            moves.retain(|mv| self.root_moves.contains(mv));
+        }
+        let first_move = entry
+            .and_then(|entry| entry.best_move)
+            .or_else(|| self.last_pv.get(ply).copied());
+        self.order_moves(board, &mut moves, ply, first_move);
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for mv in moves {
+            let child = board.play(mv);
+            self.positions.push(child);
+            let score = -self.negamax(&child, depth - 1, ply + 1, -beta, -alpha);
+            self.positions.pop();
+            if self.stopped {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(mv);
+            }
+            if score > alpha {
+                alpha = score;
+                // The best line here is this move followed by the best line after it
+                let (pv, rest) = self.pv.split_at_mut(ply + 1);
+                pv[ply].clear();
+                pv[ply].push(mv);
+                pv[ply].extend_from_slice(&rest[0]);
+            }
+            if score >= beta {
+                if !mv.capture && mv.promotion.is_none() {
+                    self.remember_cutoff(board, mv, depth, ply);
+                }
+                break;
+            }
+        }
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            // Every move failed low, so none of them is known to be best
+            best_move = None;
+            Bound::Upper
+        };
+        self.table.store(key, depth, ply, bound, best, best_move);
+        best
+    }
+
+    // The line of best moves stored in the table from BOARD, at most LEN moves long
+    fn table_line(&self, board: &Board, len: usize) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut board = *board;
+        while line.len() < len {
+            match self.table.probe(board.hash()).and_then(|entry| entry.best_move) {
+                // Another position may share the key, so the move must be checked
+                Some(mv) if board.legal_moves().contains(&mv) => {
+                    line.push(mv);
+                    board = board.play(mv);
+                }
+                _ => break,
+            }
+        }
+        line
+    }
+
+    // Search only captures and promotions until the position is quiet, so a position
+    // is not judged in the middle of an exchange. The player may also stand pat, unless
+    // in check, when every way out of check is searched instead.
+    fn quiesce(&mut self, board: &Board, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.count_node();
+        let in_check = board.in_check();
+        let mut best = -INFINITY;
+        if !in_check || ply >= MAX_PLY - 1 {
+            let stand_pat = board.evaluate_with(&self.config);
+            if stand_pat >= beta || ply >= MAX_PLY - 1 {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            best = stand_pat;
+        }
+
+        let mut moves = board.legal_moves();
+        if in_check && moves.is_empty() {
+            return -MATE + ply as i32;
+        } else if !in_check {
+            moves.retain(|mv| mv.capture || mv.promotion == Some(Queen));
+        }
+        self.order_moves(board, &mut moves, ply, None);
+        for mv in moves {
+            let score = -self.quiesce(&board.play(mv), ply + 1, -beta, -alpha);
+            if self.stopped {
+                return 0;
+            }
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if score >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    // Sort MOVES so those most likely to be best are searched first
+    fn order_moves(&self, board: &Board, moves: &mut [Move], ply: usize, first_move: Option<Move>) {
+        let player = board.player() as usize;
+        moves.sort_by_cached_key(|&mv| {
+            Reverse(if Some(mv) == first_move {
+                i32::MAX
+            } else if mv.capture {
+                // Most valuable victim, least valuable attacker
+                let victim = board.get(mv.to).map_or(Pawn, |piece| piece.piece_type());
+                let attacker = board.get(mv.from).unwrap().piece_type();
+                1_000_000 + 10 * PIECE_VALUES[victim as usize] - PIECE_VALUES[attacker as usize]
+            } else if let Some(piece_type) = mv.promotion {
+                900_000 + PIECE_VALUES[piece_type as usize]
+            } else if self.killers[ply].contains(&Some(mv)) {
+                800_000
+            } else {
+                self.history[player][square_index(mv.from)][square_index(mv.to)] as i32
+            })
+        });
+    }
+
+    // Note that quiet move MV caused a cutoff DEPTH moves from the end of the search
+    fn remember_cutoff(&mut self, board: &Board, mv: Move, depth: u32, ply: usize) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mv) {
+            killers[1] = killers[0];
+            killers[0] = Some(mv);
+        }
+        let count = &mut self.history[board.player() as usize][square_index(mv.from)]
+            [square_index(mv.to)];
+        // Kept well below the scores of killers and captures
+        *count = (*count + depth * depth).min(500_000);
+    }
+
+    // Returns true if BOARD, the last position searched, is drawn whatever is played
+    fn is_draw(&self, board: &Board) -> bool {
+        if board.halfmove_clock() >= FIFTY_MOVES || board.is_insufficient_material() {
+            return true;
+        }
+        // A single repetition is enough, as whatever was best before still is
+        self.positions
+            .iter()
+            .rev()
+            .take(board.halfmove_clock() as usize + 1)
+            .skip(2)
+            .step_by(2)
+            .any(|earlier| earlier.hash() == board.hash() && earlier.is_same_position(board))
+    }
+
+    fn count_node(&mut self) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CHECK_INTERVAL)
+            && (self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes)
+                || self.out_of_time(1)
+                || self.stop.load(Ordering::Relaxed))
+        {
+            self.stopped = true;
+        }
+    }
+
+    // Returns true if more than 1/FRACTION of the time limit has been used
+    #[cfg(feature = "std")]
+    fn out_of_time(&self, fraction: u32) -> bool {
+        self.limits
+            .time
+            .is_some_and(|time| self.start.elapsed() >= time / fraction)
+    }
+    #[cfg(not(feature = "std"))]
+    fn out_of_time(&self, _fraction: u32) -> bool {
+        false
+    }
+}
+
+/**
+Ranks a root move by its distance to zero DTZ, CLOCK moves after the last capture or
+Pawn move: quicker wins first, then wins the fifty move rule turns into draws, then
+draws, then losses it saves and last losses, slower ones first.
+*/
+#[cfg(feature = "std")]
+pub fn tablebase_rank(dtz: i32, clock: i32) -> i32 {
+    if dtz > 0 {
+        if dtz + clock <= 100 {
+            1000 - dtz
+        } else {
+            1
+        }
+    } else if dtz < 0 {
+        if -dtz + clock <= 100 {
+            -1000 - dtz
+        } else {
+            -1
+        }
+    } else {
+        0
+    }
+}
+
+// Where the engine's random numbers start. Without std there is no source of
+// randomness, so every engine picks the same book moves.
+#[cfg(feature = "std")]
+fn first_random() -> u64 {
+    rand::random()
+}
+#[cfg(not(feature = "std"))]
+fn first_random() -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_fen(fen: &str, depth: u32) -> SearchResult {
+        let game = Game::from_board(Board::from_fen(fen).unwrap());
+        Engine::new().search(&game, SearchLimits::depth(depth))
+    }
+    #[test]
+    fn test_finds_mate() {
+        // Back rank mate in one
+        let result = search_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2);
+        assert_eq!(result.best_move.unwrap().to_string(), "a1a8");
+        assert_eq!(result.score, MATE - 1);
+        // Mate in two with the King and Rook, after a waiting move along the first row
+        let result = search_fen("k7/8/1K6/8/8/8/8/1R6 w - - 0 1", 4);
+        assert_eq!(result.score, MATE - 3);
+        assert_eq!(result.pv.len(), 3);
+    }
+    #[test]
+    fn test_wins_material() {
+        // Take a hanging Queen
+        let result = search_fen("4k3/8/3q4/8/4N3/8/8/4K3 w - - 0 1", 3);
+        assert_eq!(result.best_move.unwrap().to_string(), "e4d6");
+        // The Knight forks the King and Queen
+        let result = search_fen("q3k3/8/8/3N4/8/8/8/4K3 w - - 0 1", 3);
+        assert_eq!(result.best_move.unwrap().to_string(), "d5c7");
+        // Don't take a defended Pawn with the Queen
+        let result = search_fen("4k3/2p5/3p4/8/8/8/3Q4/4K3 w - - 0 1", 3);
+        assert_ne!(result.best_move.unwrap().to_string(), "d2d6");
+    }
+    #[test]
+    fn test_game_over() {
+        let board = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        let result = Engine::new().search(&Game::from_board(board), SearchLimits::depth(3));
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, -MATE);
+        // Quiescence finds the mate too, rather than standing pat
+        assert_eq!(Engine::new().quiesce(&board, 0, -INFINITY, INFINITY), -MATE);
+    }
+    #[test]
+    fn test_mate_in() {
+        assert_eq!(mate_in(MATE - 1), Some(1));
+        assert_eq!(mate_in(MATE - 3), Some(2));
+        assert_eq!(mate_in(-MATE + 2), Some(-1));
+        assert_eq!(mate_in(150), None);
+        assert_eq!(mate_in(-900), None);
+    }
+    #[test]
+    fn test_limits() {
+        let game = Game::new();
+        let mut engine = Engine::new();
+        let result = engine.search(
+            &game,
+            SearchLimits {
+                nodes: Some(5000),
+                ..SearchLimits::default()
+            },
+        );
+        assert!(result.best_move.is_some());
+        assert!(result.nodes < 5000 + CHECK_INTERVAL);
+        let result = engine.search(&game, SearchLimits::time(Duration::from_millis(50)));
+        assert!(result.best_move.is_some() && result.depth >= 1);
+        // Even a search stopped before it starts has a move to play
+        engine.stop_flag().store(true, Ordering::Relaxed);
+        let mut depths = Vec::new();
+        let result = engine.search_with(&game, SearchLimits::default(), |result| {
+            depths.push(result.depth)
+        });
+        assert!(result.best_move.is_some());
+        assert_eq!(depths, vec![1]);
+    }
+    #[test]
+    fn test_book() {
+        let pgn = "[Result \"1-0\"]\n\n1. d4 d5 2. c4 1-0\n";
+        let book = Book::from_games(&Game::parse_pgn_games(pgn).unwrap(), POLYGLOT.clone(), 10);
+        let mut engine = Engine::new();
+        engine.set_book(Some(book), BookChoice::Weighted);
+        engine.clear();
+        let mut game = Game::new();
+        let result = engine.search(&game, SearchLimits::depth(3));
+        assert_eq!(result.best_move.unwrap().to_string(), "d2d4");
+        assert_eq!((result.depth, result.nodes), (0, 0));
+        // Black's move lost, so it isn't in the book and the engine searches instead
+        game.play(result.best_move.unwrap());
+        assert_eq!(engine.search(&game, SearchLimits::depth(3)).depth, 3);
+    }
+    #[test]
+    fn test_endgame_tables() {
+        let mut endgames = EndgameTables::new();
+        endgames.generate("KRvK").unwrap();
+        let mut engine = Engine::new();
+        engine.set_endgame_tables(Some(endgames));
+        engine.clear();
+        let board = Board::from_fen("k7/8/1K6/8/8/8/8/1R6 w - - 0 1").unwrap();
+        let result = engine.search(&Game::from_board(board), SearchLimits::depth(4));
+        assert_eq!((result.score, result.depth, result.nodes), (MATE - 3, 0, 0));
+        assert_eq!(result.pv.len(), 3);
+        // A Rook ahead, the search finds its way into the table after the capture
+        let board = Board::from_fen("k7/8/1K6/8/8/8/8/1R5r w - - 0 1").unwrap();
+        let result = engine.search(&Game::from_board(board), SearchLimits::depth(2));
+        assert_eq!(result.best_move.unwrap().to_string(), "b1h1");
+        assert_eq!(mate_in(result.score), Some(2));
+    }
+    #[test]
+    fn test_transpositions() {
+        let game = Game::new();
+        let mut engine = Engine::new();
+        let first = engine.search(&game, SearchLimits::depth(4));
+        // The second search finds the answers in the table
+        let second = engine.search(&game, SearchLimits::depth(4));
+        assert_eq!(second.best_move, first.best_move);
+        assert!(second.nodes < first.nodes / 2);
+        // Until it is cleared
+        engine.clear();
+        assert_eq!(engine.search(&game, SearchLimits::depth(4)).nodes, first.nodes);
+        // A table too small for anything but the root still finds mates
+        engine.set_table_size(0);
+        let board = Board::from_fen("k7/8/1K6/8/8/8/8/1R6 w - - 0 1").unwrap();
+        let result = engine.search(&Game::from_board(board), SearchLimits::depth(4));
+        assert_eq!(result.score, MATE - 3);
+    }
+}
@@ -0,0 +1,1488 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+use core::cmp::Reverse;
+use core::convert::TryInto;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/**
+The result of a position with perfect play, for the player to move. A cursed win is
+a win that takes too long for the fifty move rule, and a blessed loss a loss that does.
+*/
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+}
+
+/**
+Syzygy endgame tablebases, read from .rtbw (win/draw/loss) and .rtbz (distance to
+zero) files. A table is loaded the first time a position needs it and kept in memory.
+Positions with castling rights or more pieces than the largest table are not covered.
+*/
+#[derive(Clone)]
+pub struct Tablebase {
+    dirs: Vec<PathBuf>,
+    max_pieces: usize,
+    tables: HashMap<(String, bool), Option<Table>>, // By material and whether it's a DTZ table
+}
+
+impl Tablebase {
+    /**
+    Use the tables in DIRS, separated by ':' (or ';' on Windows) as in the SyzygyPath
+    UCI option. Fails if a directory can't be read.
+    */
+    pub fn open(dirs: &str) -> io::Result<Tablebase> {
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        let dirs: Vec<PathBuf> = dirs
+            .split(separator)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        let mut max_pieces = 0;
+        for dir in dirs.iter() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|extension| extension == "rtbw") {
+                    if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        if parse_material(name).is_some() {
+                            max_pieces = max_pieces.max(name.len() - 1);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Tablebase {
+            dirs,
+            max_pieces,
+            tables: HashMap::new(),
+        })
+    }
+
+    /** The number of pieces, Kings included, in the largest table found */
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /** Returns true if BOARD has few enough pieces for the tables and no castling rights */
+    pub fn covers(&self, board: &Board) -> bool {
+        let pieces = board.occupied().count_ones() as usize;
+        let castling = [White, Black]
+            .iter()
+            .any(|&color| board.can_castle(color, KingSide) || board.can_castle(color, QueenSide));
+        (pieces <= self.max_pieces || pieces == 2) && !castling
+    }
+
+    /** The result of BOARD with perfect play, or None if the tables don't cover it */
+    pub fn probe_wdl(&mut self, board: &Board) -> Option<Wdl> {
+        if !self.covers(board) {
+            return None;
+        }
+        Some(Wdl::from_value(self.search(board, false)?.0))
+    }
+
+    /**
+    The number of plies until the next capture or Pawn move, playing to keep the result
+    of BOARD: positive if the player to move wins and negative if they lose, 0 for a
+    draw. Cursed wins and blessed losses count 100 extra plies. The count may be off by
+    one where the table stores moves rather than plies, and is not always the shortest
+    way to zero, but following it always keeps the result.
+    */
+    pub fn probe_dtz(&mut self, board: &Board) -> Option<i32> {
+        if !self.covers(board) {
+            return None;
+        }
+        self.dtz(board)
+    }
+
+    /**
+    The distance to zero of each legal move on BOARD, counted from BOARD: positive for
+    moves that win, negative for those that lose and 0 for those that draw.
+    A winning move with the smallest distance makes progress towards the win.
+    */
+    pub fn root_moves(&mut self, board: &Board) -> Option<Vec<(Move, i32)>> {
+        if !self.covers(board) {
+            return None;
+        }
+        let mut moves = Vec::new();
+        for mv in board.legal_moves() {
+            let child = board.play(mv);
+            let mut dtz = if child.halfmove_clock() == 0 {
+                // The move zeroes the count itself
+                dtz_before_zeroing(-self.search(&child, false)?.0)
+            } else {
+                let dtz = -self.dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            if dtz == 2 && child.in_check() && child.legal_moves().is_empty() {
+                dtz = 1;
+            }
+            moves.push((mv, dtz));
+        }
+        Some(moves)
+    }
+
+    // The result of BOARD as a value from -2 to 2, searching captures (and Pawn moves if
+    // ZEROING) as the tables don't store positions where one is best. Also returns whether
+    // the best move is such a move, as then the DTZ table doesn't hold the distance.
+    fn search(&mut self, board: &Board, zeroing: bool) -> Option<(i32, bool)> {
+        let moves = board.legal_moves();
+        let mut best = -2;
+        let mut searched = 0;
+        for &mv in moves.iter() {
+            let pawn = board.get(mv.from).is_some_and(|piece| piece.piece_type() == Pawn);
+            if !(mv.capture || zeroing && pawn) {
+                continue;
+            }
+            searched += 1;
+            let value = -self.search(&board.play(mv), false)?.0;
+            if value > best {
+                best = value;
+                if value >= 2 {
+                    return Some((value, true));
+                }
+            }
+        }
+        // With every move searched the table's value may be wrong, e.g. if en passant is possible
+        let all_searched = searched > 0 && searched == moves.len();
+        let value = if all_searched {
+            best
+        } else {
+            self.probe_table(board, false, 0).ok()?
+        };
+        if best >= value {
+            Some((best, best > 0 || all_searched))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn dtz(&mut self, board: &Board) -> Option<i32> {
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == 0 {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        match self.probe_table(board, true, wdl) {
+            Ok(dtz) => Some((dtz + if wdl.abs() == 1 { 100 } else { 0 }) * wdl.signum()),
+            Err(Probe::Fail) => None,
+            Err(Probe::OtherPlayer) => {
+                // The table only holds the other player's moves, so look one move ahead
+                let mut best = i32::MAX;
+                for mv in board.legal_moves() {
+                    let pawn = board.get(mv.from).is_some_and(|piece| piece.piece_type() == Pawn);
+                    let child = board.play(mv);
+                    let zeroing = mv.capture || pawn;
+                    let mut dtz = if zeroing {
+                        -dtz_before_zeroing(self.search(&child, false)?.0)
+                    } else {
+                        -self.dtz(&child)?
+                    };
+                    if dtz == 1 && child.in_check() && child.legal_moves().is_empty() {
+                        best = 1; // Mate
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < best && dtz.signum() == wdl.signum() {
+                        best = dtz;
+                    }
+                }
+                // Without a legal move the player to move is mated
+                Some(if best == i32::MAX { -1 } else { best })
+            }
+        }
+    }
+
+    // Look BOARD up in its WDL table, or its DTZ table given its result WDL
+    fn probe_table(&mut self, board: &Board, dtz: bool, wdl: i32) -> Result<i32, Probe> {
+        if board.occupied().count_ones() == 2 {
+            return Ok(0); // King against King
+        }
+        let (white, black) = material(board);
+        let table = self.table(&white, &black, dtz).ok_or(Probe::Fail)?;
+        table.probe(board, &format!("{}v{}", white, black), wdl)
+    }
+
+    // The table for WHITE against BLACK, loading it if it hasn't been tried yet
+    fn table(&mut self, white: &str, black: &str, dtz: bool) -> Option<&Table> {
+        let key = (format!("{}v{}", white, black), dtz);
+        if !self.tables.contains_key(&key) {
+            let table = self.load(white, black, dtz);
+            self.tables.insert(key.clone(), table);
+        }
+        self.tables[&key].as_ref()
+    }
+
+    // The stronger side comes first in file names, so either color may be first
+    fn load(&self, white: &str, black: &str, dtz: bool) -> Option<Table> {
+        let extension = if dtz { "rtbz" } else { "rtbw" };
+        for name in [format!("{}v{}", white, black), format!("{}v{}", black, white)].iter() {
+            for dir in self.dirs.iter() {
+                let path = Path::new(dir).join(format!("{}.{}", name, extension));
+                if let Ok(bytes) = fs::read(&path) {
+                    return Table::new(name, bytes, dtz);
+                }
+            }
+        }
+        None
+    }
+}
+
+// The DTZ of the move before a capture or Pawn move that leads to WDL
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        2 => 1,
+        1 => 101,
+        -1 => -101,
+        -2 => -1,
+        _ => 0,
+    }
+}
+
+// Why a table couldn't give a value
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Probe {
+    Fail, // Missing or corrupt
+    OtherPlayer, // DTZ tables only hold one player's moves
+}
+
+// The letters of a side's pieces in the order of table names
+const LETTERS: [(char, PieceType); 6] =
+    [('K', King), ('Q', Queen), ('R', Rook), ('B', Bishop), ('N', Knight), ('P', Pawn)];
+
+// The pieces of each player as in table names, e.g. ("KRP", "KR")
+fn material(board: &Board) -> (String, String) {
+    let side = |color: Color| {
+        LETTERS
+            .iter()
+            .map(|&(letter, piece_type)| {
+                let count = board.piece_bitboard(Piece(piece_type, color)).count_ones();
+                letter.to_string().repeat(count as usize)
+            })
+            .collect::<String>()
+    };
+    (side(White), side(Black))
+}
+
+// The number of each piece of each side of a name like "KRPvKR", indexed by table_piece
+fn parse_material(name: &str) -> Option<[[usize; 7]; 2]> {
+    let mut sides = name.split('v');
+    let mut counts = [[0; 7]; 2];
+    for side_counts in counts.iter_mut() {
+        let side = sides.next()?;
+        if !side.starts_with('K') {
+            return None;
+        }
+        for letter in side.chars() {
+            let &(_, piece_type) = LETTERS.iter().find(|&&(l, _)| l == letter)?;
+            side_counts[table_piece(Piece(piece_type, White)) as usize] += 1;
+        }
+        if side_counts[6] != 1 {
+            return None;
+        }
+    }
+    if sides.next().is_some() || name.len() - 1 > MAX_PIECES {
+        return None;
+    }
+    Some(counts)
+}
+
+// How tables number the pieces: Pawn 1 to King 6, plus 8 for Black
+fn table_piece(Piece(piece_type, color): Piece) -> u8 {
+    let number = match piece_type {
+        Pawn => 1,
+        Knight => 2,
+        Bishop => 3,
+        Rook => 4,
+        Queen => 5,
+        King => 6,
+    };
+    number + if color == Black { 8 } else { 0 }
+}
+
+// The most pieces a table can hold
+const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Flags of the table for one player and one file of the leading Pawn
+const STM: u8 = 1; // Which player a DTZ table is for
+const MAPPED: u8 = 2; // DTZ values go through a map
+const WIN_PLIES: u8 = 4; // DTZ of wins is in plies rather than moves
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16; // The DTZ map has 16-bit values
+const SINGLE_VALUE: u8 = 128; // Every position has the same value
+
+/*
+How positions are numbered. The pieces are split into groups of the same piece, except
+that the first two or three pieces (or the leading Pawns) form a group of their own.
+Each group's squares are numbered as a combination, with symmetry used to fold the board
+onto a triangle (or four files when there are Pawns), and the groups combined.
+*/
+struct Encoding {
+    binomial: [[u64; 64]; MAX_PIECES], // Ways to choose K of N squares, by K then N
+    kk: [[u64; 64]; 10], // The 462 placings of two Kings, by a1_d1_d4 of the first
+    a1_d1_d4: [u64; 64], // Squares of the triangle, those off the diagonal first
+    b1_h1_h7: [u64; 64], // Squares below the diagonal
+    pawns: [u64; 64], // Squares left for other Pawns when the leading one is here
+    lead_pawn: [[u64; 64]; MAX_PIECES], // By number of leading Pawns, then the first one's square
+    lead_pawns_size: [[u64; 4]; MAX_PIECES], // By number of leading Pawns, then file
+}
+
+// Rows above columns are above the a1-h8 diagonal
+const fn off_diagonal(index: usize) -> isize {
+    (index >> 3) as isize - (index & 7) as isize
+}
+
+impl Encoding {
+    const fn new() -> Encoding {
+        let mut e = Encoding {
+            binomial: [[0; 64]; MAX_PIECES],
+            kk: [[0; 64]; 10],
+            a1_d1_d4: [0; 64],
+            b1_h1_h7: [0; 64],
+            pawns: [0; 64],
+            lead_pawn: [[0; 64]; MAX_PIECES],
+            lead_pawns_size: [[0; 4]; MAX_PIECES],
+        };
+        let mut code = 0;
+        let mut s = 0;
+        while s < 64 {
+            if off_diagonal(s) < 0 {
+                e.b1_h1_h7[s] = code;
+                code += 1;
+            }
+            s += 1;
+        }
+
+        let mut diagonal = [0; 4];
+        let mut on_diagonal = 0;
+        code = 0;
+        s = 0;
+        while s <= 27 {
+            if off_diagonal(s) < 0 && s & 7 <= 3 {
+                e.a1_d1_d4[s] = code;
+                code += 1;
+            } else if off_diagonal(s) == 0 && s & 7 <= 3 {
+                diagonal[on_diagonal] = s;
+                on_diagonal += 1;
+            }
+            s += 1;
+        }
+        let mut i = 0;
+        while i < on_diagonal {
+            e.a1_d1_d4[diagonal[i]] = code;
+            code += 1;
+            i += 1;
+        }
+
+        // With the first King on the diagonal the second is not above it
+        let mut both_on_diagonal = [(0, 0); 64];
+        let mut both = 0;
+        code = 0;
+        let mut idx = 0;
+        while idx < 10 {
+            let mut s1 = 0;
+            while s1 <= 27 {
+                if e.a1_d1_d4[s1] == idx as u64 && (idx != 0 || s1 == 1) {
+                    let mut s2 = 0;
+                    while s2 < 64 {
+                        let near = KING_ATTACKS[s1] | 1 << s1;
+                        if near & 1 << s2 != 0 || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                            // Illegal, or a mirror of another placing
+                        } else if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                            both_on_diagonal[both] = (idx, s2);
+                            both += 1;
+                        } else {
+                            e.kk[idx][s2] = code;
+                            code += 1;
+                        }
+                        s2 += 1;
+                    }
+                }
+                s1 += 1;
+            }
+            idx += 1;
+        }
+        i = 0;
+        while i < both {
+            let (idx, s2) = both_on_diagonal[i];
+            e.kk[idx][s2] = code;
+            code += 1;
+            i += 1;
+        }
+
+        e.binomial[0][0] = 1;
+        let mut n = 1;
+        while n < 64 {
+            let mut k = 0;
+            while k < MAX_PIECES && k <= n {
+                e.binomial[k][n] = if k > 0 { e.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { e.binomial[k][n - 1] } else { 0 };
+                k += 1;
+            }
+            n += 1;
+        }
+
+        // Pawns nearer the edge, then on lower rows, come first as the leading Pawn
+        let mut available = 48;
+        let mut leading = 1;
+        while leading < MAX_PIECES {
+            let mut file = 0;
+            while file < 4 {
+                let mut idx = 0;
+                let mut row = 1;
+                while row <= 6 {
+                    let s = row * 8 + file;
+                    if leading == 1 {
+                        e.pawns[s] = available - 1;
+                        e.pawns[s ^ 7] = available - 2;
+                        available -= 2;
+                    }
+                    e.lead_pawn[leading][s] = idx;
+                    idx += e.binomial[leading - 1][e.pawns[s] as usize];
+                    row += 1;
+                }
+                e.lead_pawns_size[leading][file] = idx;
+                file += 1;
+            }
+            leading += 1;
+        }
+        e
+    }
+}
+
+static ENCODING: Encoding = Encoding::new();
+
+// How the values of one player's table for one file are numbered and compressed
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; MAX_PIECES], // In the order they are numbered
+    group_len: [usize; MAX_PIECES + 1], // Ends with a 0
+    group_idx: [u64; MAX_PIECES + 1],
+    min_sym_len: u8, // Or the value of every position, with SINGLE_VALUE
+    block_size: u64,
+    span: u64, // Values between entries of the sparse index
+    num_blocks: u64,
+    block_length_size: u64,
+    sparse_index_size: u64,
+    lowest_sym: usize, // Offsets into the file
+    sparse_index: usize,
+    block_length: usize,
+    btree: usize,
+    data: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    map_idx: [u64; 4], // Where the DTZ map of each result starts
+}
+
+// A table read from a file
+#[derive(Clone)]
+struct Table {
+    bytes: Vec<u8>,
+    name: String, // The material with White first, e.g. "KQvKR"
+    dtz: bool,
+    piece_count: usize,
+    has_pawns: bool,
+    unique_pieces: bool, // Some piece other than a King is the only one of its kind
+    pawn_count: [usize; 2], // Of the leading color and of the other
+    symmetric: bool, // Both sides have the same pieces
+    pairs: [[PairsData; 4]; 2], // By player to move (WDL tables only), then file
+    map: usize,
+}
+
+impl Table {
+    // Read the table for material NAME from BYTES, returning None if it is corrupt
+    fn new(name: &str, bytes: Vec<u8>, dtz: bool) -> Option<Table> {
+        let counts = parse_material(name)?;
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.len() < 5 || bytes[..4] != magic || bytes.len() % 64 != 16 {
+            return None;
+        }
+        let count = |color: usize, number: usize| counts[color][number];
+        let (white_pawns, black_pawns) = (count(0, 1), count(1, 1));
+        // The side with fewer Pawns leads, as that compresses better
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let mut table = Table {
+            bytes,
+            name: name.to_string(),
+            dtz,
+            piece_count: counts.iter().flatten().sum(),
+            has_pawns: white_pawns + black_pawns > 0,
+            unique_pieces: counts.iter().any(|side| side[1..6].contains(&1)),
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            symmetric: counts[0] == counts[1],
+            pairs: Default::default(),
+            map: 0,
+        };
+        table.read()?;
+        Some(table)
+    }
+
+    // Read the headers of the table, which follow the magic number
+    fn read(&mut self) -> Option<()> {
+        let mut pos = 4;
+        let flags = *self.bytes.get(pos)?;
+        if (flags & 2 != 0) != self.has_pawns || (flags & 1 != 0) == self.symmetric {
+            return None;
+        }
+        pos += 1;
+        let sides = if self.dtz || self.symmetric { 1 } else { 2 };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+
+        for file in 0..files {
+            let first = *self.bytes.get(pos)?;
+            let second = if both_pawns { *self.bytes.get(pos + 1)? } else { 0xFF };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            pos += 1 + both_pawns as usize;
+            for k in 0..self.piece_count {
+                let byte = *self.bytes.get(pos)?;
+                for side in 0..sides {
+                    self.pairs[side][file].pieces[k] = if side == 0 { byte & 0xF } else { byte >> 4 };
+                }
+                pos += 1;
+            }
+            for (side, &order) in order.iter().enumerate().take(sides) {
+                self.set_groups(side, file, order);
+            }
+        }
+        pos += pos & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                pos = read_sizes(&mut self.pairs[side][file], &self.bytes, pos)?;
+            }
+        }
+
+        if self.dtz {
+            self.map = pos;
+            for file in 0..files {
+                let d = &mut self.pairs[0][file];
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = ((pos - self.map) / 2 + 1) as u64;
+                        pos += 2 * read_u16(&self.bytes, pos)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = (pos - self.map + 1) as u64;
+                        pos += *self.bytes.get(pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.pairs[side][file];
+                d.sparse_index = pos;
+                pos += d.sparse_index_size as usize * 6;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.pairs[side][file];
+                d.block_length = pos;
+                pos += d.block_length_size as usize * 2;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.pairs[side][file];
+                pos = (pos + 0x3F) & !0x3F;
+                d.data = pos;
+                pos += (d.num_blocks * d.block_size) as usize;
+            }
+        }
+        if pos > self.bytes.len() {
+            return None;
+        }
+        Some(())
+    }
+
+    // Split the pieces into groups and work out how many numbers each group takes.
+    // ORDER gives the position of the leading group and of the other color's Pawns.
+    fn set_groups(&mut self, side: usize, file: usize, order: [u8; 2]) {
+        let (has_pawns, unique_pieces) = (self.has_pawns, self.unique_pieces);
+        let d = &mut self.pairs[side][file];
+        let mut first_len: isize = if has_pawns { 0 } else if unique_pieces { 3 } else { 2 };
+        let mut n = 0;
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            // The leading pieces are one group, then each run of the same piece
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let both_pawns = has_pawns && self.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+        let mut idx = 1;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                d.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    ENCODING.lead_pawns_size[d.group_len[0]][file]
+                } else if unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                d.group_idx[1] = idx;
+                idx *= ENCODING.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= ENCODING.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    // The value stored for BOARD, whose material with White first is NAME. A DTZ table
+    // needs the position's result WDL.
+    fn probe(&self, board: &Board, name: &str, wdl: i32) -> Result<i32, Probe> {
+        let (side, file, idx) = self.index(board, name)?;
+        let d = &self.pairs[side][file];
+        let value = self.decompress(d, idx).ok_or(Probe::Fail)?;
+        if !self.dtz {
+            return Ok(value - 2);
+        }
+        self.map_dtz(d, value, wdl).ok_or(Probe::Fail)
+    }
+
+    // Where BOARD is stored: the player and file of the values, and its number among them
+    fn index(&self, board: &Board, name: &str) -> Result<(usize, usize, u64), Probe> {
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        // Tables are stored with the stronger side as White, and symmetric tables only
+        // with White to move, so otherwise swap the colors and mirror the board
+        let black_to_move = board.player() == Black;
+        let flip = (self.symmetric && black_to_move) || name != self.name;
+        let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+        let player = (flip ^ black_to_move) as usize;
+
+        let mut lead_pawns = 0;
+        let mut lead_count = 0;
+        let mut file = 0;
+        if self.has_pawns {
+            let pawn = self.pairs[0][0].pieces[0] ^ flip_color;
+            let color = if pawn & 8 == 0 { White } else { Black };
+            lead_pawns = board.piece_bitboard(Piece(Pawn, color));
+            for index in Squares(lead_pawns).map(square_index) {
+                squares[size] = index ^ flip_squares;
+                size += 1;
+            }
+            lead_count = size;
+            let lead = (0..lead_count)
+                .max_by_key(|&i| (ENCODING.pawns[squares[i]], Reverse(i)))
+                .unwrap();
+            squares.swap(0, lead);
+            file = squares[0] & 7;
+            if file > 3 {
+                file = (squares[0] ^ 7) & 7;
+            }
+        }
+        if self.dtz {
+            let flags = self.pairs[0][file].flags;
+            // Symmetric tables without Pawns hold both players' moves
+            let both_players = self.symmetric && !self.has_pawns;
+            if (flags & STM) as usize != player && !both_players {
+                return Err(Probe::OtherPlayer);
+            }
+        }
+        for loc in Squares(board.occupied() & !lead_pawns) {
+            if size == MAX_PIECES {
+                return Err(Probe::Fail);
+            }
+            squares[size] = square_index(loc) ^ flip_squares;
+            pieces[size] = table_piece(board.get(loc).unwrap()) ^ flip_color;
+            size += 1;
+        }
+        if size != self.piece_count {
+            return Err(Probe::Fail);
+        }
+
+        let side = if self.dtz { 0 } else { player };
+        let d = &self.pairs[side][file];
+        // Put the pieces in the order the table numbers them
+        for i in lead_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+        // Mirror the board so the leading piece is on files a to d
+        if squares[0] & 7 > 3 {
+            for square in squares[..size].iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let e = &ENCODING;
+        let mut idx;
+        if self.has_pawns {
+            idx = e.lead_pawn[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|&s| e.pawns[s]);
+            for (i, &square) in squares.iter().enumerate().take(lead_count).skip(1) {
+                idx += e.binomial[i][e.pawns[square] as usize];
+            }
+        } else {
+            // Then below the fifth row
+            if squares[0] >> 3 > 3 {
+                for square in squares[..size].iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            // Then the first of the leading group off the diagonal below it
+            for i in 0..d.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in squares[i..size].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            idx = if self.unique_pieces {
+                let s = |i: usize| squares[i] as u64;
+                let rank = |i: usize| (squares[i] >> 3) as u64;
+                let adjust1 = (s(1) > s(0)) as u64;
+                let adjust2 = (s(2) > s(0)) as u64 + (s(2) > s(1)) as u64;
+                if off_diagonal(squares[0]) != 0 {
+                    (e.a1_d1_d4[squares[0]] * 63 + s(1) - adjust1) * 62 + s(2) - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank(0) * 28 + e.b1_h1_h7[squares[1]]) * 62 + s(2) - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(0) * 7 * 28
+                        + (rank(1) - adjust1) * 28
+                        + e.b1_h1_h7[squares[2]]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(0) * 7 * 6
+                        + (rank(1) - adjust1) * 6
+                        + (rank(2) - adjust2)
+                }
+            } else {
+                e.kk[e.a1_d1_d4[squares[0]] as usize][squares[1]]
+            };
+        }
+        idx *= d.group_idx[0];
+
+        // The other groups, each numbered among the squares the earlier groups left
+        let mut start = d.group_len[0];
+        let mut other_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| square > s).count();
+                // Pawns can't stand on the first row
+                let free = square - adjust - if other_pawns { 8 } else { 0 };
+                n += e.binomial[i + 1][free];
+            }
+            other_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+        Ok((side, file, idx))
+    }
+
+    // The IDXth value of a table, which is split into blocks of Huffman codes for symbols
+    // that each stand for a run of values
+    fn decompress(&self, d: &PairsData, idx: u64) -> Option<i32> {
+        if d.flags & SINGLE_VALUE != 0 {
+            return Some(d.min_sym_len as i32);
+        }
+        let bytes = &self.bytes;
+        // The sparse index gives the block and offset of a value near this one
+        let k = (idx / d.span) as usize;
+        let mut block = read_u32(bytes, d.sparse_index + 6 * k)? as usize;
+        let mut offset = read_u16(bytes, d.sparse_index + 6 * k + 4)? as i64;
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+        let block_length = |block: usize| -> Option<i64> {
+            Some(read_u16(bytes, d.block_length + 2 * block)? as i64)
+        };
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        let mut ptr = d.data + block * d.block_size as usize;
+        let mut buffer = read_u64_be(bytes, ptr)?;
+        ptr += 8;
+        let mut buffer_size = 64;
+        let mut sym;
+        loop {
+            // Longer codes have lower values, so the length is found by comparison
+            let mut len = 0;
+            while buffer < *d.base64.get(len)? {
+                len += 1;
+            }
+            let shift = 64 - len - d.min_sym_len as usize;
+            sym = ((buffer - d.base64[len]) >> shift) as usize;
+            sym += read_u16(bytes, d.lowest_sym + 2 * len)? as usize;
+            let run = *d.symlen.get(sym)? as i64 + 1;
+            if offset < run {
+                break;
+            }
+            offset -= run;
+            let len = len + d.min_sym_len as usize;
+            buffer = buffer.checked_shl(len as u32).unwrap_or(0);
+            buffer_size -= len as i32;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (read_u32_be(bytes, ptr)? as u64) << (64 - buffer_size);
+                ptr += 4;
+            }
+        }
+        // Expand the symbol's pairs down to the value
+        while d.symlen[sym] != 0 {
+            let (left, right) = read_pair(bytes, d.btree + 3 * sym)?;
+            let left_run = *d.symlen.get(left)? as i64 + 1;
+            if offset < left_run {
+                sym = left;
+            } else {
+                offset -= left_run;
+                sym = right;
+            }
+            d.symlen.get(sym)?;
+        }
+        Some(read_pair(bytes, d.btree + 3 * sym)?.0 as i32)
+    }
+
+    // Turn a stored DTZ VALUE into plies for a position with result WDL
+    fn map_dtz(&self, d: &PairsData, value: i32, wdl: i32) -> Option<i32> {
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        let mut value = value;
+        if d.flags & MAPPED != 0 {
+            let start = (d.map_idx[WDL_MAP[(wdl + 2) as usize]] as i64 + value as i64) as usize;
+            value = if d.flags & WIDE != 0 {
+                read_u16(&self.bytes, self.map + 2 * start)? as i32
+            } else {
+                *self.bytes.get(self.map + start)? as i32
+            };
+        }
+        let plies = match wdl {
+            2 => d.flags & WIN_PLIES != 0,
+            -2 => d.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        if !plies {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+}
+
+// Read the compression header of D at POS, returning where the next one starts
+fn read_sizes(d: &mut PairsData, bytes: &[u8], mut pos: usize) -> Option<usize> {
+    d.flags = *bytes.get(pos)?;
+    pos += 1;
+    if d.flags & SINGLE_VALUE != 0 {
+        d.min_sym_len = *bytes.get(pos)?;
+        return Some(pos + 1);
+    }
+    let groups = d.group_len.iter().position(|&len| len == 0)?;
+    let table_size = d.group_idx[groups];
+    d.block_size = 1u64.checked_shl(*bytes.get(pos)? as u32)?;
+    d.span = 1u64.checked_shl(*bytes.get(pos + 1)? as u32)?;
+    d.sparse_index_size = table_size.div_ceil(d.span);
+    let padding = *bytes.get(pos + 2)? as u64;
+    d.num_blocks = read_u32(bytes, pos + 3)? as u64;
+    d.block_length_size = d.num_blocks + padding;
+    pos += 7;
+    let max_sym_len = *bytes.get(pos)?;
+    d.min_sym_len = *bytes.get(pos + 1)?;
+    pos += 2;
+    if d.min_sym_len == 0 || max_sym_len < d.min_sym_len || max_sym_len > 64 {
+        return None;
+    }
+    d.lowest_sym = pos;
+
+    // Codes of each length, left-aligned in 64 bits, are at least base64 of that length
+    let lengths = (max_sym_len - d.min_sym_len + 1) as usize;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        let lowest = read_u16(bytes, pos + 2 * i)? as u64;
+        let next_lowest = read_u16(bytes, pos + 2 * (i + 1))? as u64;
+        d.base64[i] = (d.base64[i + 1].wrapping_add(lowest).wrapping_sub(next_lowest)) / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base.checked_shl((64 - i - d.min_sym_len as usize) as u32).unwrap_or(0);
+    }
+    pos += lengths * 2;
+
+    // Each symbol is a value or a pair of symbols, making a tree
+    let symbols = read_u16(bytes, pos)? as usize;
+    pos += 2;
+    d.btree = pos;
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = symbol_length(d, bytes, sym, &mut visited)?;
+        }
+    }
+    Some(pos + symbols * 3 + (symbols & 1))
+}
+
+// The number of values, less one, that SYM stands for
+fn symbol_length(d: &mut PairsData, bytes: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+    visited[sym] = true;
+    let (left, right) = read_pair(bytes, d.btree + 3 * sym)?;
+    if right == 0xFFF {
+        return Some(0);
+    }
+    for &child in [left, right].iter() {
+        if !*visited.get(child)? {
+            d.symlen[child] = symbol_length(d, bytes, child, visited)?;
+        }
+    }
+    Some(d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1))
+}
+
+// The two 12-bit symbols a symbol stands for. A value has 0xFFF on the right.
+fn read_pair(bytes: &[u8], pos: usize) -> Option<(usize, usize)> {
+    let lr = bytes.get(pos..pos + 3)?;
+    let left = ((lr[1] as usize & 0xF) << 8) | lr[0] as usize;
+    let right = ((lr[2] as usize) << 4) | (lr[1] as usize >> 4);
+    Some((left, right))
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn read_u32_be(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn read_u64_be(bytes: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(pos..pos + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::iproduct;
+    use std::collections::BinaryHeap;
+
+    // A directory of its own for each test, as they run in parallel
+    fn table_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chess-syzygy-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A table of the Kings and a White PIECE where every position with White to move has
+    // the value WHITE, and with Black to move BLACK. A DTZ table only has White's moves.
+    fn single_value_table(piece: PieceType, dtz: bool, white: u8, black: u8) -> Vec<u8> {
+        let mut bytes = if dtz { DTZ_MAGIC } else { WDL_MAGIC }.to_vec();
+        // Not symmetric, no Pawns, the leading group first, then the White piece and King
+        // and the Black King for each player
+        let piece = table_piece(Piece(piece, White)) * 0x11;
+        bytes.extend_from_slice(&[0x01, 0x00, piece, 0x66, 0xEE, 0x00]);
+        bytes.extend_from_slice(&[SINGLE_VALUE, white]);
+        if !dtz {
+            bytes.extend_from_slice(&[SINGLE_VALUE, black]);
+        }
+        bytes.resize(80, 0);
+        bytes
+    }
+
+    // The values of one player's table, compressed as a table generator does: runs of
+    // values are paired up into symbols, and the symbols Huffman coded into blocks
+    struct Compressed {
+        flags: u8,
+        block_size: usize, // In bytes, a power of two
+        span: usize, // Values between entries of the sparse index, a power of two
+        min_sym_len: u8,
+        lowest_sym: Vec<u16>, // By code length, from the shortest
+        pairs: Vec<(usize, usize)>, // Each symbol's two symbols, or its value and 0xFFF
+        sparse_index: Vec<(u32, u16)>,
+        block_lengths: Vec<u16>, // The number of values in each block, less one
+        data: Vec<u8>,
+    }
+
+    // Compress VALUES into blocks of BLOCK_SIZE bytes with an entry of the sparse index
+    // every SPAN values
+    fn compress(values: &[u16], flags: u8, block_size: usize, span: usize) -> Compressed {
+        // Each value is a symbol, then the commonest pair of symbols becomes another
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        let mut runs: Vec<usize> = Vec::new(); // The number of values of each symbol
+        let mut stream: Vec<usize> = values
+            .iter()
+            .map(|&value| {
+                let leaf = (value as usize, 0xFFF);
+                pairs.iter().position(|&pair| pair == leaf).unwrap_or_else(|| {
+                    pairs.push(leaf);
+                    runs.push(1);
+                    pairs.len() - 1
+                })
+            })
+            .collect();
+        while pairs.len() < 100 {
+            let mut counts: HashMap<(usize, usize), usize> = HashMap::new();
+            for pair in stream.windows(2) {
+                if runs[pair[0]] + runs[pair[1]] <= 256 {
+                    *counts.entry((pair[0], pair[1])).or_insert(0) += 1;
+                }
+            }
+            let best = counts.into_iter().max_by_key(|&(pair, count)| (count, Reverse(pair)));
+            let (pair, count) = match best {
+                Some(best) if best.1 >= 4 => best,
+                _ => break,
+            };
+            let sym = pairs.len();
+            pairs.push(pair);
+            runs.push(runs[pair.0] + runs[pair.1]);
+            let mut paired = Vec::with_capacity(stream.len() - count);
+            let mut i = 0;
+            while i < stream.len() {
+                if i + 1 < stream.len() && (stream[i], stream[i + 1]) == pair {
+                    paired.push(sym);
+                    i += 2;
+                } else {
+                    paired.push(stream[i]);
+                    i += 1;
+                }
+            }
+            stream = paired;
+        }
+
+        // Huffman code lengths of the symbols in the stream, at least one bit each
+        let mut frequency = vec![0usize; pairs.len()];
+        for &sym in stream.iter() {
+            frequency[sym] += 1;
+        }
+        let mut lengths = vec![0u8; pairs.len()];
+        let mut heap: BinaryHeap<Reverse<(usize, Vec<usize>)>> = (0..pairs.len())
+            .filter(|&sym| frequency[sym] > 0)
+            .map(|sym| Reverse((frequency[sym], vec![sym])))
+            .collect();
+        if heap.len() == 1 {
+            let Reverse((_, syms)) = heap.pop().unwrap();
+            lengths[syms[0]] = 1;
+        }
+        while heap.len() > 1 {
+            let Reverse((left, mut left_syms)) = heap.pop().unwrap();
+            let Reverse((right, right_syms)) = heap.pop().unwrap();
+            left_syms.extend(right_syms);
+            for &sym in left_syms.iter() {
+                lengths[sym] += 1;
+            }
+            heap.push(Reverse((left + right, left_syms)));
+        }
+        let coded: Vec<usize> = (0..pairs.len()).filter(|&sym| lengths[sym] > 0).collect();
+        let min_len = coded.iter().map(|&sym| lengths[sym]).min().unwrap();
+        let max_len = coded.iter().map(|&sym| lengths[sym]).max().unwrap();
+        assert!(max_len <= 32);
+
+        // Symbols are numbered with the longest codes first, and longer codes are lower
+        let mut order: Vec<usize> = (0..pairs.len()).collect();
+        order.sort_by_key(|&sym| (Reverse(lengths[sym]), sym));
+        let mut number = vec![0; pairs.len()];
+        for (i, &sym) in order.iter().enumerate() {
+            number[sym] = i;
+        }
+        let levels = (max_len - min_len + 1) as usize;
+        let mut count = vec![0usize; levels];
+        for &sym in coded.iter() {
+            count[(lengths[sym] - min_len) as usize] += 1;
+        }
+        let mut lowest_sym = vec![0u16; levels];
+        let mut base = vec![0u64; levels];
+        for i in (0..levels - 1).rev() {
+            lowest_sym[i] = lowest_sym[i + 1] + count[i + 1] as u16;
+            assert_eq!((base[i + 1] + count[i + 1] as u64) % 2, 0);
+            base[i] = (base[i + 1] + count[i + 1] as u64) / 2;
+        }
+        let code = |sym: usize| -> (u64, u8) {
+            let level = (lengths[sym] - min_len) as usize;
+            (base[level] + (number[sym] - lowest_sym[level] as usize) as u64, lengths[sym])
+        };
+
+        // Fill each block with as many whole symbols as fit
+        let mut blocks: Vec<(Vec<bool>, usize)> = Vec::new();
+        let (mut bits, mut block_values) = (Vec::new(), 0);
+        for &sym in stream.iter() {
+            let (value, len) = code(sym);
+            if bits.len() + len as usize > block_size * 8 || block_values + runs[sym] > 65536 {
+                blocks.push((bits, block_values));
+                bits = Vec::new();
+                block_values = 0;
+            }
+            bits.extend((0..len).rev().map(|bit| (value >> bit) & 1 == 1));
+            block_values += runs[sym];
+        }
+        blocks.push((bits, block_values));
+
+        let mut data = Vec::new();
+        let mut starts = Vec::new(); // The number of the first value of each block
+        let mut start = 0;
+        for (bits, block_values) in blocks.iter() {
+            let mut block = vec![0u8; block_size];
+            for (i, _) in bits.iter().enumerate().filter(|(_, &bit)| bit) {
+                block[i / 8] |= 0x80 >> (i % 8);
+            }
+            data.extend(block);
+            starts.push(start);
+            start += block_values;
+        }
+        // Each entry gives the block and offset of the value in the middle of its span
+        let sparse_index = (0..values.len().div_ceil(span))
+            .map(|k| {
+                let middle = k * span + span / 2;
+                let block = starts.partition_point(|&start| start <= middle) - 1;
+                (block as u32, (middle - starts[block]) as u16)
+            })
+            .collect();
+        let old_pairs = pairs.clone();
+        for (sym, &pair) in old_pairs.iter().enumerate() {
+            pairs[number[sym]] = match pair {
+                (value, 0xFFF) => (value, 0xFFF),
+                (left, right) => (number[left], number[right]),
+            };
+        }
+        Compressed {
+            flags,
+            block_size,
+            span,
+            min_sym_len: min_len,
+            lowest_sym,
+            pairs,
+            sparse_index,
+            block_lengths: blocks.iter().map(|&(_, values)| (values - 1) as u16).collect(),
+            data,
+        }
+    }
+
+    // A table of the Kings and a White PIECE in the layout of single_value_table, holding
+    // SIDES for each player (only White, for a DTZ table). A DTZ table may map its values
+    // to distances with MAP, a list for each result as numbered by map_dtz.
+    fn write_table(
+        piece: PieceType,
+        dtz: bool,
+        sides: &[Compressed],
+        map: Option<&[Vec<u16>; 4]>,
+    ) -> Vec<u8> {
+        let mut bytes = single_value_table(piece, dtz, 0, 0)[..10].to_vec();
+        for side in sides.iter() {
+            let log2 = |n: usize| n.trailing_zeros() as u8;
+            bytes.extend_from_slice(&[side.flags, log2(side.block_size), log2(side.span), 0]);
+            bytes.extend_from_slice(&(side.block_lengths.len() as u32).to_le_bytes());
+            let levels = side.lowest_sym.len() as u8;
+            bytes.extend_from_slice(&[side.min_sym_len + levels - 1, side.min_sym_len]);
+            for lowest in side.lowest_sym.iter() {
+                bytes.extend_from_slice(&lowest.to_le_bytes());
+            }
+            bytes.extend_from_slice(&(side.pairs.len() as u16).to_le_bytes());
+            for &(left, right) in side.pairs.iter() {
+                bytes.extend_from_slice(&[left as u8, (left >> 8 | right << 4) as u8, (right >> 4) as u8]);
+            }
+            bytes.resize(bytes.len() + (side.pairs.len() & 1), 0);
+        }
+        if let Some(map) = map {
+            let wide = sides[0].flags & WIDE != 0;
+            for list in map.iter() {
+                if wide {
+                    bytes.extend_from_slice(&(list.len() as u16).to_le_bytes());
+                    list.iter().for_each(|value| bytes.extend_from_slice(&value.to_le_bytes()));
+                } else {
+                    bytes.push(list.len() as u8);
+                    bytes.extend(list.iter().map(|&value| value as u8));
+                }
+            }
+            bytes.resize(bytes.len() + (bytes.len() & 1), 0);
+        }
+        for side in sides.iter() {
+            for &(block, offset) in side.sparse_index.iter() {
+                bytes.extend_from_slice(&block.to_le_bytes());
+                bytes.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+        for side in sides.iter() {
+            side.block_lengths.iter().for_each(|len| bytes.extend_from_slice(&len.to_le_bytes()));
+        }
+        for side in sides.iter() {
+            bytes.resize((bytes.len() + 0x3F) & !0x3F, 0);
+            bytes.extend_from_slice(&side.data);
+        }
+        // Files end with a 16 byte checksum, which isn't read
+        bytes.resize(((bytes.len() + 0x3F) & !0x3F) + 16, 0);
+        bytes
+    }
+
+    // Every legal position of the Kings and a White PIECE on one of the squares numbered
+    // PIECES, with either player to move
+    fn positions(piece: PieceType, pieces: impl Iterator<Item = usize> + Clone) -> Vec<Board> {
+        let mut boards = Vec::new();
+        for (a, b, c) in iproduct!(pieces, 0..64, 0..64) {
+            let (queen, white, black) = (index_square(a), index_square(b), index_square(c));
+            let apart = (white.0 - black.0).abs() > 1 || (white.1 - black.1).abs() > 1;
+            if a == b || a == c || b == c || !apart {
+                continue;
+            }
+            for &player in [White, Black].iter() {
+                let mut board = Board::empty(player, 1);
+                board
+                    .set(queen, Some(Piece(piece, White)))
+                    .set(white, Some(Piece(King, White)))
+                    .set(black, Some(Piece(King, Black)));
+                let waiting = if player == White { black } else { white };
+                if board.num_checking(waiting, Some(player.other())) == 0 {
+                    boards.push(board);
+                }
+            }
+        }
+        boards
+    }
+
+    // The value VALUE gives each position of BOARDS, numbered as TABLE numbers them, for each
+    // player. Numbers that no position has take the value before them.
+    fn table_values(table: &Table, boards: &[Board], value: impl Fn(&Board) -> u16) -> Vec<Vec<u16>> {
+        let sides = if table.dtz { 1 } else { 2 };
+        let d = &table.pairs[0][0];
+        let size = d.group_idx[d.group_len.iter().position(|&len| len == 0).unwrap()] as usize;
+        let mut values = vec![vec![None; size]; sides];
+        for board in boards.iter() {
+            if let Ok((side, _, idx)) = table.index(board, &table.name) {
+                let slot = &mut values[side][idx as usize];
+                assert!(slot.is_none() || *slot == Some(value(board)), "{}", board.to_fen());
+                *slot = Some(value(board));
+            }
+        }
+        values
+            .into_iter()
+            .map(|side| {
+                let mut last = side.iter().flatten().copied().next().unwrap();
+                side.into_iter()
+                    .map(|value| {
+                        last = value.unwrap_or(last);
+                        last
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn test_encoding() {
+        let e = &ENCODING;
+        // The Kings' placings are numbered 0 to 461
+        let mut kk: Vec<u64> = (0..10)
+            .flat_map(|i| (0..64).map(move |s| (i, s)))
+            .filter(|&(i, s)| e.kk[i][s] != 0 || (i, s) == (0, 0))
+            .map(|(i, s)| e.kk[i][s])
+            .collect();
+        kk.sort_unstable();
+        kk.dedup();
+        assert_eq!(kk.len(), 462);
+        assert_eq!(e.binomial[2][62], 62 * 61 / 2);
+        assert_eq!(e.binomial[3][5], 10);
+        // a2 leads over everything, then h2, a3, h3 ...
+        assert_eq!((e.pawns[8], e.pawns[15], e.pawns[16], e.pawns[55]), (47, 46, 45, 36));
+        assert_eq!((e.pawns[9], e.pawns[50]), (35, 13));
+        assert_eq!(e.lead_pawns_size[1], [6, 6, 6, 6]);
+        assert_eq!(e.a1_d1_d4[1], 0);
+        assert_eq!(e.a1_d1_d4[27], 9);
+    }
+    #[test]
+    fn test_material() {
+        let board = board("8/8/4k3/3r4/8/8/1P1PK3/3R4 w - - 0 1");
+        assert_eq!(material(&board), ("KRPP".to_string(), "KR".to_string()));
+        assert!(parse_material("KRPPvKR").is_some());
+        assert_eq!(parse_material("KRPPvKR").unwrap()[0][1], 2);
+        assert!(parse_material("KQv").is_none());
+        assert!(parse_material("QKvK").is_none());
+        assert!(parse_material("KQQQQQQvK").is_none());
+    }
+    #[test]
+    fn test_probe_wdl() {
+        let dir = table_dir("wdl");
+        fs::write(dir.join("KQvK.rtbw"), single_value_table(Queen, false, 4, 0)).unwrap();
+        fs::write(dir.join("KRvK.rtbw"), single_value_table(Rook, false, 2, 2)).unwrap();
+        fs::write(dir.join("KNvK.rtbw"), vec![0; 80]).unwrap();
+        let mut tablebase = Tablebase::open(dir.to_str().unwrap()).unwrap();
+        assert_eq!(tablebase.max_pieces(), 3);
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/2k5/8/KQ6 w - - 0 1")), Some(Wdl::Win));
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/2k5/8/8/8/K6Q b - - 0 1")), Some(Wdl::Loss));
+        // The same table serves Black with the Queen, swapping the colors
+        assert_eq!(tablebase.probe_wdl(&board("k6q/8/8/8/2K5/8/8/8 w - - 0 1")), Some(Wdl::Loss));
+        assert_eq!(tablebase.probe_wdl(&board("k6q/8/8/8/2K5/8/8/8 b - - 0 1")), Some(Wdl::Win));
+        // Taking the Queen draws, whatever the table says
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/8/1kQ5/K7 b - - 0 1")), Some(Wdl::Draw));
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/2k5/8/K7 w - - 0 1")), Some(Wdl::Draw));
+        // Missing, corrupt and too big tables, and castling rights
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/2k5/8/KB6 w - - 0 1")), None);
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/2k5/8/KN6 w - - 0 1")), None);
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/2k5/8/KQQ5 w - - 0 1")), None);
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/2k5/8/4K2R w - - 0 1")), Some(Wdl::Draw));
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/2k5/8/4K2R w K - 0 1")), None);
+        assert!(Tablebase::open(dir.join("missing").to_str().unwrap()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn test_probe_dtz() {
+        let dir = table_dir("dtz");
+        fs::write(dir.join("KQvK.rtbw"), single_value_table(Queen, false, 4, 0)).unwrap();
+        fs::write(dir.join("KQvK.rtbz"), single_value_table(Queen, true, 5, 0)).unwrap();
+        let mut tablebase = Tablebase::open(dir.to_str().unwrap()).unwrap();
+        // 5 moves are 10 plies, counting to the zeroing move
+        let white = board("8/8/8/8/8/2k5/8/KQ6 w - - 0 1");
+        assert_eq!(tablebase.probe_dtz(&white), Some(11));
+        // The table has no Black moves, so they are worked out from White's
+        assert_eq!(tablebase.probe_dtz(&board("8/8/8/2k5/8/8/8/K6Q b - - 0 1")), Some(-12));
+        // Every move keeps the win except those that leave the Queen to be taken
+        let moves = tablebase.root_moves(&white).unwrap();
+        assert_eq!(moves.len(), white.legal_moves().len());
+        let mut hanging = 0;
+        for (mv, dtz) in moves {
+            let hangs = white.play(mv).legal_moves().iter().any(|mv| mv.capture);
+            hanging += hangs as usize;
+            assert_eq!(dtz, if hangs { 0 } else { 13 }, "{}", mv);
+        }
+        assert_eq!(hanging, 4);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn test_groups() {
+        // The Kings and the Queen are numbered together, as one of 31332 placings
+        let table = Table::new("KQvK", single_value_table(Queen, false, 4, 0), false).unwrap();
+        let d = &table.pairs[0][0];
+        assert_eq!(d.group_len[..2], [3, 0]);
+        assert_eq!(d.group_idx[1], 31332);
+    }
+    #[test]
+    fn test_written_tables() {
+        // Tables of every position of KQvK and KRvK, with values from retrograde analysis,
+        // compressed and read back. KQvK maps its distances in moves with 8-bit values,
+        // KRvK in plies with 16-bit values.
+        let mut endgames = EndgameTables::new();
+        endgames.generate("KQvK").unwrap();
+        endgames.generate("KRvK").unwrap();
+        let dir = table_dir("written");
+        let mut all_boards = Vec::new();
+        for &(piece, flags) in [(Queen, MAPPED), (Rook, MAPPED | WIDE | WIN_PLIES)].iter() {
+            let name = format!("K{}vK", piece.letter());
+            // Every position can be mirrored to put the piece on a1-d1-d4
+            let boards = positions(piece, (0..64).filter(|&s| s >> 3 <= s & 7 && s & 7 < 4));
+            let dtm = |board: &Board| endgames.probe(board).unwrap();
+            let skeleton = Table::new(&name, single_value_table(piece, false, 0, 0), false).unwrap();
+            let wdl = table_values(&skeleton, &boards, |board| match dtm(board) {
+                Dtm::Mate(_) => 4,
+                Dtm::Mated(_) => 0,
+                Dtm::Draw => 2,
+            });
+            let sides: Vec<Compressed> = wdl.iter().map(|values| compress(values, 0, 16, 64)).collect();
+            assert!(sides.iter().all(|side| side.block_lengths.len() > 1));
+            let bytes = write_table(piece, false, &sides, None);
+            fs::write(dir.join(format!("{}.rtbw", name)), bytes).unwrap();
+
+            // Only White's moves are stored. Nothing can be taken or moved that zeroes the
+            // count but the Queen or Rook, so the distance to zero is the distance to mate.
+            let stored = |board: &Board| match dtm(board) {
+                Dtm::Mate(plies) if flags & WIN_PLIES != 0 => plies as u16 - 1,
+                Dtm::Mate(plies) => (plies as u16 - 1) / 2,
+                _ => 0,
+            };
+            let mut wins: Vec<u16> = boards.iter().map(stored).collect();
+            wins.sort_unstable();
+            wins.dedup();
+            let skeleton = Table::new(&name, single_value_table(piece, true, 0, 0), true).unwrap();
+            let dtz = table_values(&skeleton, &boards, |board| {
+                wins.binary_search(&stored(board)).unwrap() as u16
+            });
+            let side = compress(&dtz[0], flags, 32, 16);
+            let map = [wins, vec![], vec![], vec![]];
+            let bytes = write_table(piece, true, &[side], Some(&map));
+            fs::write(dir.join(format!("{}.rtbz", name)), bytes).unwrap();
+            all_boards.extend(positions(piece, 0..64).into_iter().step_by(97));
+        }
+
+        let mut tablebase = Tablebase::open(dir.to_str().unwrap()).unwrap();
+        for board in all_boards.iter() {
+            let (wdl, dtz) = match endgames.probe(board).unwrap() {
+                Dtm::Mate(plies) => (Wdl::Win, plies as i32),
+                Dtm::Mated(plies) => (Wdl::Loss, -(plies.max(1) as i32)),
+                Dtm::Draw => (Wdl::Draw, 0),
+            };
+            assert_eq!(tablebase.probe_wdl(board), Some(wdl), "{}", board.to_fen());
+            assert_eq!(tablebase.probe_dtz(board), Some(dtz), "{}", board.to_fen());
+        }
+        // Mate in one, mated in one move and a Queen that can be taken
+        assert_eq!(tablebase.probe_dtz(&board("k7/8/1K6/8/8/8/7Q/8 w - - 0 1")), Some(1));
+        assert_eq!(tablebase.probe_dtz(&board("k7/8/1K6/8/8/8/8/7R b - - 0 1")), Some(-2));
+        assert_eq!(tablebase.probe_wdl(&board("8/8/8/8/8/8/1k6/Q6K b - - 0 1")), Some(Wdl::Draw));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+use core::mem;
+
+/** How a stored score relates to the position's true score */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower, // The search failed high, so the true score is at least this
+    Upper, // No move reached alpha, so the true score is at most this
+}
+
+/** What an earlier search learned about a position */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TableEntry {
+    pub key: u64, // The position's Board::hash
+    pub depth: u32, // Moves searched below the position
+    pub bound: Bound,
+    pub score: i32, // Mate scores count from the position, not from the root
+    pub best_move: Option<Move>,
+    generation: u8, // The search that stored the entry
+}
+
+impl TableEntry {
+    /**
+    Returns the stored score with mate scores counted from the root of a search that
+    reached the position PLY moves in
+    */
+    pub fn score(&self, ply: usize) -> i32 {
+        from_table(self.score, ply)
+    }
+}
+
+/**
+A fixed-size table of positions already searched, so that a position reached by
+another order of moves need not be searched again. Each position has one slot,
+chosen by its key. A slot keeps the deeper of two searches, but entries left over
+from earlier searches are always replaced.
+*/
+#[derive(Clone)]
+pub struct TranspositionTable {
+    entries: Vec<Option<TableEntry>>,
+    generation: u8,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(TranspositionTable::DEFAULT_MEGABYTES)
+    }
+}
+
+impl TranspositionTable {
+    pub const DEFAULT_MEGABYTES: usize = 16;
+
+    /** A table taking up about MEGABYTES of memory, holding at least one entry */
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let len = (megabytes << 20) / mem::size_of::<Option<TableEntry>>();
+        TranspositionTable {
+            entries: vec![None; len.max(1)],
+            generation: 0,
+        }
+    }
+
+    /** The number of entries the table holds */
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /** Forget every position */
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+        self.generation = 0;
+    }
+
+    /** Mark the entries stored so far as old, to be replaced by those of a new search */
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /** Returns what is known about the position with KEY, if it is still in the table */
+    pub fn probe(&self, key: u64) -> Option<&TableEntry> {
+        self.entries[self.index(key)]
+            .as_ref()
+            .filter(|entry| entry.key == key)
+    }
+
+    /**
+    Record the result of searching the position with KEY DEPTH moves deep, PLY moves
+    from the root. Does nothing if a deeper search of this search is in the slot.
+    */
+    pub fn store(
+        &mut self,
+        key: u64,
+        depth: u32,
+        ply: usize,
+        bound: Bound,
+        score: i32,
+        best_move: Option<Move>,
+    ) {
+        let (index, generation) = (self.index(key), self.generation);
+        let slot = &mut self.entries[index];
+        let old = *slot;
+        if old.is_some_and(|old| old.generation == generation && old.depth > depth) {
+            return;
+        }
+        // A search that found no move still leaves the old best move worth trying first
+        let best_move = best_move.or_else(|| {
+            old.filter(|old| old.key == key)
+                .and_then(|old| old.best_move)
+        });
+        *slot = Some(TableEntry {
+            key,
+            depth,
+            bound,
+            score: to_table(score, ply),
+            best_move,
+            generation,
+        });
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+}
+
+// Mate scores count the plies from the root, but a position may be reached at any ply,
+// so the table counts them from the position itself
+fn to_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MATE_RANGE {
+        score + ply as i32
+    } else if score <= -MATE + MATE_RANGE {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn from_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MATE_RANGE {
+        score - ply as i32
+    } else if score <= -MATE + MATE_RANGE {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_probe() {
+        let mut table = TranspositionTable::new(1);
+        assert!(table.capacity() > 1000);
+        let board = Board::new();
+        let e4 = board.parse_uci("e2e4").unwrap();
+        assert_eq!(table.probe(board.hash()), None);
+        table.store(board.hash(), 3, 0, Bound::Lower, 25, Some(e4));
+        let entry = *table.probe(board.hash()).unwrap();
+        assert_eq!((entry.depth, entry.bound, entry.score(0)), (3, Bound::Lower, 25));
+        assert_eq!(entry.best_move, Some(e4));
+        // A shallower search of the same position doesn't replace a deeper one
+        table.store(board.hash(), 2, 0, Bound::Exact, 10, None);
+        assert_eq!(table.probe(board.hash()).unwrap().depth, 3);
+        // Unless it comes from a later search, which keeps the move
+        table.new_search();
+        table.store(board.hash(), 2, 0, Bound::Upper, 10, None);
+        let entry = table.probe(board.hash()).unwrap();
+        assert_eq!((entry.depth, entry.best_move), (2, Some(e4)));
+        // Another position sharing the slot is not mistaken for this one
+        let other = board.hash() - table.capacity() as u64;
+        assert_eq!(table.probe(other), None);
+        table.clear();
+        assert_eq!(table.probe(board.hash()), None);
+    }
+    #[test]
+    fn test_mate_scores() {
+        let mut table = TranspositionTable::new(1);
+        // Mated 5 plies from the root in a position 3 plies from the root
+        table.store(1, 4, 3, Bound::Exact, -MATE + 5, None);
+        let entry = table.probe(1).unwrap();
+        assert_eq!(entry.score, -MATE + 2);
+        // Reached again 7 plies from the root, the mate is 9 plies away
+        assert_eq!(entry.score(7), -MATE + 9);
+        table.store(2, 4, 6, Bound::Lower, MATE - 7, None);
+        assert_eq!(table.probe(2).unwrap().score(2), MATE - 3);
+        table.store(3, 4, 6, Bound::Exact, 150, None);
+        assert_eq!(table.probe(3).unwrap().score(2), 150);
+    }
+}
@@ -0,0 +1,619 @@
+// Jack Alpert 2020
+
+use crate::board::*;
+
+/**
+The rules of a game played with chess pieces: the position it starts from, which moves
+are legal, what each move does and how the game ends. Every method but name defaults
+to standard chess, so a variant only gives the rules it changes.
+A Game plays by the rules of its variant.
+*/
+pub trait Variant: Sync {
+    /** The name of the variant, as written in a PGN Variant tag */
+    fn name(&self) -> &'static str;
+
+    /** The position games of this variant start from */
+    fn start(&self) -> Board {
+        Board::new()
+    }
+
+    /** Read a position of this variant written in FEN */
+    fn parse_fen(&self, fen: &str) -> Result<Board, ParseError> {
+        Board::from_fen(fen)
+    }
+
+    /** Returns every legal move available to the current player on BOARD */
+    fn legal_moves(&self, board: &Board) -> Vec<Move> {
+        board.legal_moves()
+    }
+
+    /**
+    Ensure that the current player may move the piece at FROM to TO on BOARD,
+    explaining why not otherwise. Promotion is not checked.
+    */
+    fn validate_move(&self, board: &Board, from: Square, to: Square) -> Result<(), MoveError> {
+        board.validate_from(from, None)?;
+        board.validate_move(from, to, None)
+    }
+
+    /**
+    Find the legal move on BOARD written as TEXT, either in long algebraic notation
+    (e2e4) or SAN (Nf3)
+    */
+    fn parse_move(&self, board: &Board, text: &str) -> Result<Move, ParseError> {
+        let long_algebraic = text.len() >= 4
+            && text.is_char_boundary(2)
+            && text.is_char_boundary(4)
+            && parse_square(&text[..2]).is_ok()
+            && parse_square(&text[2..4]).is_ok();
+        if long_algebraic {
+            board.parse_uci(text)
+        } else {
+            board.parse_san(text)
+        }
+    }
+
+    /** Describe MV, a legal move on BOARD, in SAN */
+    fn san(&self, board: &Board, mv: Move) -> String {
+        board.san(mv)
+    }
+
+    /** Returns the position after MV is played on BOARD, with any effects of the variant */
+    fn play(&self, board: &Board, mv: Move) -> Board {
+        board.play(mv)
+    }
+
+    /** Returns how GAME has ended, or None if it continues */
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        game.standard_outcome()
+    }
+}
+
+// Every variant that can be played, starting with standard chess
+pub const VARIANTS: [&dyn Variant; 6] =
+    [&Standard, &KingOfTheHill, &ThreeCheck, &Antichess, &Horde, &Crazyhouse];
+
+/**
+Find the variant called NAME, ignoring case, spaces and hyphens, e.g. "three-check" or
+"KingOfTheHill". Returns None if there is no such variant.
+*/
+pub fn find_variant(name: &str) -> Option<&'static dyn Variant> {
+    let simplify = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    VARIANTS
+        .iter()
+        .copied()
+        .find(|variant| simplify(variant.name()) == simplify(name))
+}
+
+// Ensure moving FROM -> TO is one of MOVES, for variants that find their own moves
+fn validate_among(board: &Board, moves: &[Move], from: Square, to: Square) -> Result<(), MoveError> {
+    board.validate_from(from, None)?;
+    if moves.iter().any(|mv| mv.from == from && mv.to == to) {
+        Ok(())
+    } else {
+        Err(MoveError::IllegalPattern {
+            piece: board.get(from).unwrap(),
+            from,
+            to,
+        })
+    }
+}
+
+// Returns an error naming the first Pawn on one of ROWS, if any
+fn pawns_on_rows(pawns: Bitboard, rows: &[isize]) -> Result<(), ParseError> {
+    match Squares(pawns).find(|loc| rows.contains(&loc.0)) {
+        Some(loc) => Err(ParseError::InvalidFen(format!(
+            "FEN piece placement has a Pawn on {}.",
+            square_name(loc)
+        ))),
+        None => Ok(()),
+    }
+}
+
+/** Standard chess, by the FIDE Laws of Chess */
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "Standard"
+    }
+}
+
+// The four squares in the center of the board
+const HILL: [Square; 4] = [(3, 3), (3, 4), (4, 3), (4, 4)];
+
+/**
+King of the Hill: as well as by checkmate, a player wins by bringing their King to
+one of the four squares in the center of the board. Since a lone King can still
+reach the center, no position is a draw by insufficient material.
+*/
+pub struct KingOfTheHill;
+
+impl Variant for KingOfTheHill {
+    fn name(&self) -> &'static str {
+        "King of the Hill"
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        let board = game.board();
+        let mover = board.player().other();
+        if board.find_king(Some(mover)).is_some_and(|king| HILL.contains(&king)) {
+            return Some(Outcome::VariantWin(mover));
+        }
+        game.mate_outcome()
+            .or_else(|| game.automatic_draw().map(Outcome::Drawn))
+    }
+}
+
+// Number of checks that wins a game of Three-Check
+pub const CHECKS_TO_WIN: u8 = 3;
+
+/**
+Three-Check: as well as by checkmate, a player wins by giving check for the third time.
+Any piece can give check, so only bare Kings are insufficient material.
+*/
+pub struct ThreeCheck;
+
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "Three-check"
+    }
+
+    // The checks each player has given may be written after the move counters
+    fn parse_fen(&self, fen: &str) -> Result<Board, ParseError> {
+        let board = Board::parse_fen(fen, true).map_err(ParseError::InvalidFen)?;
+        board
+            .check_variant_fields(false, true)
+            .map_err(ParseError::InvalidFen)?;
+        Ok(board)
+    }
+
+    // Count the check MV gives, if any
+    fn play(&self, board: &Board, mv: Move) -> Board {
+        let mut after = board.play(mv);
+        if after.in_check() {
+            after.checks[board.player() as usize] += 1;
+        }
+        after
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        let board = game.board();
+        let mover = board.player().other();
+        if board.checks_given(mover) >= CHECKS_TO_WIN {
+            return Some(Outcome::VariantWin(mover));
+        }
+        if let Some(outcome) = game.mate_outcome() {
+            Some(outcome)
+        } else if board.occupied() == board.pieces[King as usize] {
+            Some(Outcome::Drawn(InsufficientMaterial))
+        } else {
+            game.automatic_draw().map(Outcome::Drawn)
+        }
+    }
+}
+
+/**
+Antichess: a player wins by losing all their pieces, or by having no legal moves.
+Capturing is compulsory, the King is an ordinary piece that can be captured and
+that a Pawn may be promoted to, there is no check and nobody castles.
+*/
+pub struct Antichess;
+
+impl Variant for Antichess {
+    fn name(&self) -> &'static str {
+        "Antichess"
+    }
+
+    fn start(&self) -> Board {
+        let mut board = Board::new();
+        board.castling = [[None; 2]; 2];
+        board
+    }
+
+    // Any number of Kings is allowed, and castling rights are ignored
+    fn parse_fen(&self, fen: &str) -> Result<Board, ParseError> {
+        let mut board = Board::parse_fen(fen, false).map_err(ParseError::InvalidFen)?;
+        board
+            .check_variant_fields(false, false)
+            .map_err(ParseError::InvalidFen)?;
+        pawns_on_rows(board.pieces[Pawn as usize], &[0, 7])?;
+        board.castling = [[None; 2]; 2];
+        Ok(board)
+    }
+
+    fn legal_moves(&self, board: &Board) -> Vec<Move> {
+        let player = board.player();
+        let kings = board.piece_bitboard(Piece(King, player.other()));
+        let mut moves = Vec::new();
+        for from in Squares(board.color_bitboard(player)) {
+            let piece = board.get(from).unwrap();
+            let targets = board.target_squares(from, piece) | (board.attacks_from(from) & kings);
+            for to in Squares(targets) {
+                if board.castle_side(from, to).is_some() {
+                    continue;
+                } else if board.is_promotion(from, to) {
+                    for &piece_type in &[Queen, Rook, Bishop, Knight, King] {
+                        moves.push(Move::new(board, from, to, Some(piece_type)));
+                    }
+                } else {
+                    moves.push(Move::new(board, from, to, None));
+                }
+            }
+        }
+        // Capturing is compulsory
+        if moves.iter().any(|mv| mv.capture) {
+            moves.retain(|mv| mv.capture);
+        }
+        moves
+    }
+
+    fn validate_move(&self, board: &Board, from: Square, to: Square) -> Result<(), MoveError> {
+        let moves = self.legal_moves(board);
+        validate_among(board, &moves, from, to).map_err(|error| {
+            let capture = board.get(to).is_some() || board.is_en_passant(from, to);
+            match error {
+                MoveError::IllegalPattern { .. } if moves.iter().any(|mv| mv.capture) && !capture => {
+                    MoveError::MustCapture
+                }
+                error => error,
+            }
+        })
+    }
+
+    fn parse_move(&self, board: &Board, text: &str) -> Result<Move, ParseError> {
+        board.parse_move_among(text, &self.legal_moves(board))
+    }
+
+    // There is no check, so no move is marked as giving it
+    fn san(&self, board: &Board, mv: Move) -> String {
+        board.san_among(mv, &self.legal_moves(board))
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        let board = game.board();
+        if self.legal_moves(&board).is_empty() {
+            Some(Outcome::VariantWin(board.player()))
+        } else {
+            game.automatic_draw().map(Outcome::Drawn)
+        }
+    }
+}
+
+/**
+Horde: White has no King, but 36 Pawns, and wins by checkmating Black. Black wins by
+capturing every White piece. White's Pawns on the first row may advance one or two
+squares, like those on the second.
+*/
+pub struct Horde;
+
+impl Variant for Horde {
+    fn name(&self) -> &'static str {
+        "Horde"
+    }
+
+    fn start(&self) -> Board {
+        self.parse_fen("rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1")
+            .unwrap()
+    }
+
+    // White has no King, and may have Pawns on the first row
+    fn parse_fen(&self, fen: &str) -> Result<Board, ParseError> {
+        let board = Board::parse_fen(fen, false).map_err(ParseError::InvalidFen)?;
+        board
+            .check_variant_fields(false, false)
+            .map_err(ParseError::InvalidFen)?;
+        let kings = |color| Squares(board.piece_bitboard(Piece(King, color))).count();
+        if (kings(White), kings(Black)) != (0, 1) {
+            return Err(ParseError::InvalidFen(String::from(
+                "Horde FEN must have one Black King and no White King.",
+            )));
+        }
+        pawns_on_rows(board.piece_bitboard(Piece(Pawn, White)), &[7])?;
+        pawns_on_rows(board.piece_bitboard(Piece(Pawn, Black)), &[0, 7])?;
+        Ok(board)
+    }
+
+    fn legal_moves(&self, board: &Board) -> Vec<Move> {
+        let mut moves = board.legal_moves();
+        if board.player() == White {
+            for from in Squares(board.piece_bitboard(Piece(Pawn, White))).filter(|loc| loc.0 == 0) {
+                let (one, two) = ((1, from.1), (2, from.1));
+                if board.get(one).is_none() && board.get(two).is_none() {
+                    moves.push(Move::new(board, from, two, None));
+                }
+            }
+        }
+        moves
+    }
+
+    fn validate_move(&self, board: &Board, from: Square, to: Square) -> Result<(), MoveError> {
+        match board.player() {
+            White => validate_among(board, &self.legal_moves(board), from, to),
+            Black => Standard.validate_move(board, from, to),
+        }
+    }
+
+    fn parse_move(&self, board: &Board, text: &str) -> Result<Move, ParseError> {
+        board.parse_move_among(text, &self.legal_moves(board))
+    }
+
+    // A Pawn advancing two squares from the first row can't be taken en passant
+    fn play(&self, board: &Board, mv: Move) -> Board {
+        let mut after = board.play(mv);
+        if mv.from.0 == 0 && board.get(mv.from) == Some(Piece(Pawn, White)) {
+            after.en_passant = None;
+        }
+        after
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        let board = game.board();
+        if board.color_bitboard(White) == 0 {
+            Some(Outcome::VariantWin(Black))
+        } else if !self.legal_moves(&board).is_empty() {
+            game.automatic_draw().map(Outcome::Drawn)
+        } else if board.in_check() {
+            Some(Outcome::Checkmate(board.player().other()))
+        } else {
+            Some(Outcome::Drawn(Stalemate))
+        }
+    }
+}
+
+/**
+Crazyhouse: a captured piece changes sides and goes into the capturing player's pocket,
+from which it may later be dropped onto any empty square instead of moving. Pawns cannot
+be dropped on the first or last row. A promoted piece goes back to being a Pawn when captured.
+As no piece ever leaves the game, no position is a draw by insufficient material.
+*/
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "Crazyhouse"
+    }
+
+    // Pieces may be in the pockets, and marked as promoted with '~'
+    fn parse_fen(&self, fen: &str) -> Result<Board, ParseError> {
+        let board = Board::parse_fen(fen, true).map_err(ParseError::InvalidFen)?;
+        board
+            .check_variant_fields(true, false)
+            .map_err(ParseError::InvalidFen)?;
+        Ok(board)
+    }
+
+    fn legal_moves(&self, board: &Board) -> Vec<Move> {
+        let mut moves = board.legal_moves();
+        moves.extend(board.legal_drops(None));
+        moves
+    }
+
+    fn parse_move(&self, board: &Board, text: &str) -> Result<Move, ParseError> {
+        if text.contains('@') {
+            board.parse_drop(text)
+        } else {
+            Standard.parse_move(board, text)
+        }
+    }
+
+    // Pocket the piece MV captures, and mark the piece a Pawn is promoted to
+    fn play(&self, board: &Board, mv: Move) -> Board {
+        let mut after = board.play(mv);
+        let player = board.player();
+        if mv.capture {
+            let captured = if mv.en_passant {
+                (mv.from.0, mv.to.1)
+            } else {
+                mv.to
+            };
+            let piece_type = match board.get(captured) {
+                _ if board.is_promoted(captured) => Pawn,
+                Some(piece) => piece.piece_type(),
+                None => unreachable!("A capture must take a piece."), // Coder error
+            };
+            after.pockets[player as usize][piece_type as usize] += 1;
+        }
+        if mv.promotion.is_some() {
+            after.promoted |= square_bit(mv.to);
+        }
+        after
+    }
+
+    // A check is only mate if no piece can be dropped in the way
+    fn san(&self, board: &Board, mv: Move) -> String {
+        let mut san = board.san_among(mv, &board.legal_moves());
+        let after = self.play(board, mv);
+        if after.in_check() {
+            san.push(if self.legal_moves(&after).is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        san
+    }
+
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        let board = game.board();
+        if !self.legal_moves(&board).is_empty() {
+            game.automatic_draw().map(Outcome::Drawn)
+        } else if board.in_check() {
+            Some(Outcome::Checkmate(board.player().other()))
+        } else {
+            Some(Outcome::Drawn(Stalemate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Play the moves in SANS, written in SAN or long algebraic notation
+    fn play(game: &mut Game, sans: &[&str]) {
+        for san in sans {
+            let mv = game.variant().parse_move(&game.board(), san).unwrap();
+            game.play(mv);
+        }
+    }
+    #[test]
+    fn test_find_variant() {
+        assert_eq!(find_variant("three-check").unwrap().name(), "Three-check");
+        assert_eq!(find_variant("KingOfTheHill").unwrap().name(), "King of the Hill");
+        assert_eq!(find_variant("STANDARD").unwrap().name(), "Standard");
+        assert!(find_variant("Atomic").is_none());
+    }
+    #[test]
+    fn test_king_of_the_hill() {
+        // Bare Kings are not a draw, as either may still reach the hill
+        let board = Board::from_fen("7k/8/8/8/8/2K5/8/8 w - - 0 1").unwrap();
+        let mut game = Game::with_variant(&KingOfTheHill, board);
+        assert_eq!(game.tag("Variant"), Some("King of the Hill"));
+        assert_eq!(game.outcome(), None);
+        play(&mut game, &["Kd4"]);
+        assert_eq!(game.outcome(), Some(Outcome::VariantWin(White)));
+        assert_eq!(Game::from_board(board).outcome(), Some(Outcome::Drawn(InsufficientMaterial)));
+    }
+    #[test]
+    fn test_three_check() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let mut game = Game::with_variant(&ThreeCheck, board);
+        play(&mut game, &["Ra8+", "Kd7", "Ra7+", "Ke6"]);
+        assert_eq!(game.board().checks_given(White), 2);
+        assert_eq!(game.board().checks_given(Black), 0);
+        assert_eq!(game.outcome(), None);
+        // The checks given are kept in FEN, or may be written as the checks left
+        let fen = game.board().to_fen();
+        assert_eq!(fen, "8/R7/4k3/8/8/8/8/4K3 w - - 4 3 +2+0");
+        assert_eq!(ThreeCheck.parse_fen(&fen).unwrap().to_fen(), fen);
+        let left = ThreeCheck.parse_fen("8/R7/4k3/8/8/8/8/4K3 w - - 1+3 4 3").unwrap();
+        assert_eq!(left.to_fen(), fen);
+        assert!(ThreeCheck.parse_fen("8/R7/4k3/8/8/8/8/4K3 w - - 4 3 +4+0").is_err());
+        assert!(Standard.parse_fen(&fen).is_err());
+        play(&mut game, &["Ra6+"]);
+        assert_eq!(game.outcome(), Some(Outcome::VariantWin(White)));
+        // Positions with different numbers of checks given are not the same
+        assert!(!game.boards()[1].is_same_position(&board.play(board.parse_san("Ra8").unwrap())));
+    }
+    #[test]
+    fn test_antichess() {
+        let mut game = Game::with_variant(&Antichess, Antichess.start());
+        assert_eq!(game.variant().legal_moves(&game.board()).len(), 20);
+        play(&mut game, &["e3", "b5"]);
+        // Capturing is compulsory
+        let board = game.board();
+        let moves = Antichess.legal_moves(&board);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(Antichess.san(&board, moves[0]), "Bxb5");
+        assert_eq!(Antichess.validate_move(&board, (1, 0), (2, 0)), Err(MoveError::MustCapture));
+        // A King may be captured, and a Pawn promoted to one
+        let board = Antichess.parse_fen("8/8/8/8/8/8/1k6/K7 w - - 0 1").unwrap();
+        let mut game = Game::with_variant(&Antichess, board);
+        play(&mut game, &["Kxb2"]);
+        assert_eq!(game.outcome(), Some(Outcome::VariantWin(Black)));
+        let board = Antichess.parse_fen("8/8/8/8/8/8/1p6/B7 b - - 0 1").unwrap();
+        let moves = Antichess.legal_moves(&board);
+        assert_eq!(moves.len(), 5);
+        let mut game = Game::with_variant(&Antichess, board);
+        play(&mut game, &["bxa1=K"]);
+        assert_eq!(game.outcome(), Some(Outcome::VariantWin(White)));
+    }
+    #[test]
+    fn test_horde() {
+        let board = Horde.start();
+        assert!(board.find_king(Some(White)).is_none());
+        assert_eq!(Squares(board.color_bitboard(White)).count(), 36);
+        assert!(board.to_fen().starts_with("rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/"));
+        // Pawns on the first row may advance two squares
+        let board = Horde.parse_fen("7k/8/8/8/8/1p6/8/P7 w - - 0 1").unwrap();
+        let mut game = Game::with_variant(&Horde, board);
+        assert_eq!(Horde.legal_moves(&board).len(), 2);
+        play(&mut game, &["a3"]);
+        assert_eq!(game.board().en_passant(), None);
+        assert!(Horde.parse_move(&game.board(), "bxa2").is_err());
+        // Black wins by capturing every White piece
+        let board = Horde.parse_fen("7k/8/8/8/8/1p6/P7/8 b - - 0 1").unwrap();
+        let mut game = Game::with_variant(&Horde, board);
+        play(&mut game, &["bxa2"]);
+        assert_eq!(game.outcome(), Some(Outcome::VariantWin(Black)));
+        assert!(Horde.parse_fen("7k/8/8/8/8/8/8/P3K3 w - - 0 1").is_err());
+    }
+    #[test]
+    fn test_pgn() {
+        let mut game = Game::with_variant(&Antichess, Antichess.start());
+        play(&mut game, &["e3", "b5", "Bxb5"]);
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[Variant \"Antichess\"]"));
+        assert!(!pgn.contains("FEN"));
+        let parsed = Game::from_pgn(&pgn).unwrap();
+        assert_eq!(parsed.variant().name(), "Antichess");
+        assert_eq!(parsed.moves(), game.moves());
+    }
+    #[test]
+    fn test_crazyhouse() {
+        let mut game = Game::with_variant(&Crazyhouse, Crazyhouse.start());
+        play(&mut game, &["e4", "d5", "exd5", "Qxd5", "Nc3", "Qa5"]);
+        let board = game.board();
+        assert_eq!(board.pocket(White, Pawn), 1);
+        assert_eq!(board.pocket(Black, Pawn), 1);
+        assert_eq!(
+            board.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/q7/8/2N5/PPPP1PPP/R1BQKBNR[Pp] w KQkq - 2 4"
+        );
+        // A drop may block check
+        play(&mut game, &["Bb5+"]);
+        let block = Crazyhouse.parse_move(&game.board(), "P@c6").unwrap();
+        assert!(Crazyhouse.legal_moves(&game.board()).contains(&block));
+        assert_eq!(
+            Crazyhouse.validate_move(&game.board(), (6, 0), (5, 0)),
+            Err(MoveError::StillInCheck {
+                checkers: vec![(4, 1)]
+            })
+        );
+        play(&mut game, &["P@c6"]);
+        assert_eq!(game.board().pocket(Black, Pawn), 0);
+        assert_eq!(game.moves().last().unwrap().to_string(), "P@c6");
+        assert!(game.to_pgn().contains("P@c6"));
+        let parsed = Game::from_pgn(&game.to_pgn()).unwrap();
+        assert_eq!(parsed.board().to_fen(), game.board().to_fen());
+        // A promoted piece goes back to being a Pawn when captured
+        let board = Board::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut game = Game::with_variant(&Crazyhouse, board);
+        play(
+            &mut game,
+            &[
+                "axb8=Q", "Kd7", "Qb5+", "Kc7", "Qc5+", "Kb7", "Qb6+", "Kxb6",
+            ],
+        );
+        assert_eq!(game.board().pocket(White, Knight), 1);
+        assert_eq!(game.board().pocket(Black, Pawn), 1);
+        assert_eq!(game.board().pocket(Black, Queen), 0);
+        assert_eq!(game.outcome(), None);
+        // The mark that a piece was promoted is kept in FEN
+        let fen = "4k3/8/8/8/8/8/8/Q~3K3[Nn] w - - 0 1";
+        let board = Crazyhouse.parse_fen(fen).unwrap();
+        assert!(board.is_promoted((0, 0)));
+        assert_eq!(board.to_fen(), fen);
+        // Only Crazyhouse has pockets, each holding at most 255 of a piece
+        for variant in VARIANTS.iter().filter(|v| v.name() != "Crazyhouse") {
+            assert!(variant.parse_fen(fen).is_err(), "{}", variant.name());
+        }
+        let fen = format!("4k3/8/8/8/8/8/8/4K3[{}] w - - 0 1", "P".repeat(256));
+        assert!(Crazyhouse.parse_fen(&fen).is_err());
+        // A back row check is not mate while a piece can be dropped in the way
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1[n] w - - 0 1";
+        let board = Crazyhouse.parse_fen(fen).unwrap();
+        let check = board.parse_san("Ra8").unwrap();
+        assert_eq!(Crazyhouse.san(&board, check), "Ra8+");
+        let mut game = Game::with_variant(&Crazyhouse, board);
+        game.play(check);
+        assert_eq!(game.outcome(), None);
+        assert!(game.board().has_no_moves(None));
+    }
+}
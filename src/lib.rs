@@ -0,0 +1,37 @@
+// Jack Alpert 2020
+
+/*!
+The rules of chess: boards and pieces, legal move generation and perft, check and draw detection,
+and reading and writing positions and games as FEN, SAN and PGN. Games may be played by
+the rules of a `Variant`, such as King of the Hill, Three-check, Antichess, Horde or Crazyhouse.
+An alpha-beta search engine plays standard chess against you.
+
+Without the default `std` feature the library is `no_std` and only needs `alloc`.
+The colored `Display` for `Board`, `Board::random_move`, `Board::random_chess960` and
+the Syzygy `Tablebase` need `std`.
+*/
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod board;
+pub use crate::board::{
+    // Boards, pieces and moves
+    in_bounds, parse_square, square_name, Bishop, Black, Board, CastleSide, Color, Direction, King,
+    KingSide, Knight, Move, Pawn, Piece, PieceType, Queen, QueenSide, Rook, Sign, Square, White,
+    KING_HOME_COL, PIECE_TYPES, POCKET_TYPES,
+    // Errors
+    MoveError, ParseError,
+    // Games, their outcomes and variants
+    find_variant, Antichess, Crazyhouse, Draw, FiftyMoves, FivefoldRepetition, Game, Horde,
+    InsufficientMaterial, KingOfTheHill, Outcome, SeventyFiveMoves, Stalemate, Standard,
+    ThreeCheck, ThreefoldRepetition, Variant, CHECKS_TO_WIN, FIFTY_MOVES, SEVENTY_FIVE_MOVES,
+    SEVEN_TAG_ROSTER, VARIANTS,
+    // The search engine
+    mate_in, Bound, Engine, EvalConfig, SearchLimits, SearchResult, TableEntry,
+    TranspositionTable, Weight, MATE, MATE_RANGE, MAX_PLY,
+    // Opening books and endgame tables
+    Book, BookChoice, Dtm, EndgameTable, EndgameTables, ZobristKeys,
+};
+#[cfg(feature = "std")]
+pub use crate::board::{tablebase_rank, Tablebase, Wdl};
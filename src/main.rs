@@ -1,47 +1,143 @@
 // Jack Alpert 2020
 
+use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{self, BufRead};
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-mod board;
-use crate::board::*;
+use chess::*;
+use rand::seq::SliceRandom;
+
+mod uci;
 
 enum UserInput {
     Loc(Square),
+    MoveText(String), // A whole move, such as e2e4 or Nf3
     GoBack,
     Quit,
     Undo,
     Yes,
     Random,
+    ClaimDraw,
     Save(String),
     Load(String),
 }
 use UserInput::*;
 
+const USAGE: &str = "Usage: chess [--play white|black] [--depth DEPTH] [--time SECONDS] [--hash MEGABYTES]
+             [--book FILE] [--book-choice best|weighted] [--syzygy DIR] [--endgames FILE]
+             [--chess960 INDEX|random] [--variant NAME]
+       chess --uci
+       chess perft DEPTH [FEN]
+       chess book PGN_FILE BOOK_FILE [PLIES]
+       chess endgame FILE MATERIAL...";
+
+// How many moves of each game go into a book, unless told otherwise
+const DEFAULT_BOOK_PLIES: usize = 20;
+
+// The engine playing one side of the game
+struct Computer {
+    color: Color,
+    engine: Engine,
+    limits: SearchLimits,
+}
+
+// The position each game starts from
+#[derive(Clone, Copy)]
+enum Start {
+    Standard,
+    Chess960(u32), // The numbered Chess960 position
+    RandomChess960, // A different Chess960 position each game
+}
+
+impl Start {
+    // The starting position, which for variants other than standard chess is their own
+    fn board(self, variant: &dyn Variant) -> Board {
+        match self {
+            Start::Standard => variant.start(),
+            Start::Chess960(index) => Board::new_chess960(index).unwrap(), // Checked when parsed
+            Start::RandomChess960 => Board::random_chess960(),
+        }
+    }
+}
+
+// What the command line asked for
+struct Options {
+    start: Start,
+    variant: &'static dyn Variant,
+    computer: Option<Computer>,
+    book: Option<(Book, BookChoice)>, // Used by the engine and for random moves
+    tablebase: Option<Tablebase>, // For showing the result of endgames
+    endgames: Option<EndgameTables>, // Likewise, from tables made by `chess endgame`
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("perft") {
+        if let Err(msg) = perft(&args[1..]) {
+            eprintln!("{}\n{}", msg, USAGE);
+            process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("book") {
+        if let Err(msg) = build_book(&args[1..]) {
+            eprintln!("{}\n{}", msg, USAGE);
+            process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("endgame") {
+        if let Err(msg) = build_endgames(&args[1..]) {
+            eprintln!("{}\n{}", msg, USAGE);
+            process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("--uci") {
+        uci::run();
+        return;
+    }
+    let Options {
+        start,
+        variant,
+        mut computer,
+        book,
+        mut tablebase,
+        endgames,
+    } = match parse_options(&args) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{}\n{}", msg, USAGE);
+            process::exit(1);
+        }
+    };
     'main: loop {
-        let mut board = Board::new();
-        let mut history = Vec::new();
-        let winner: Option<Color> = 'game: loop {
+        let mut game = Game::with_variant(variant, start.board(variant));
+        game.set_tag("Date", &today());
+        if let Some(computer) = computer.as_mut() {
+            computer.engine.clear();
+            game.set_tag(&computer.color.to_string(), "Computer");
+        }
+        let outcome: Outcome = 'game: loop {
+            let board = game.board();
             // Save the state of the game
             if let Err(msg) = save_board(&board, "checkpoint.board") {
                 println!("{}", msg);
             }
-            
-            // Display the current state of the game
-            let num_checking =
-                board.num_checking(board.find_king(None).expect("No King Found!"), None);
-            //
+
             // Exit game loop if game is over
-            if board.has_no_moves(None) {
-                break 'game if num_checking > 0 {
-                    Some(board.player().other())
-                } else {
-                    None
-                }
+            if let Some(outcome) = game.outcome() {
+                break 'game outcome;
             }
+            //
+            // Display the current state of the game
+            let num_checking = board
+                .find_king(None)
+                .map_or(0, |king| board.num_checking(king, None));
             println!("Turn: {}, {} to move.", board.turn(), board.player());
             if num_checking > 0 {
                 println!(
@@ -51,44 +147,79 @@ fn main() {
                 );
             };
             println!("{}", board);
-            println!("Select a piece to move by specifying the row then the column, separated by whitespace. Then press enter:");
+            if let Some(analysis) = tablebase.as_mut().and_then(|tablebase| analyze(tablebase, &board)) {
+                println!("{}", analysis);
+            }
+            if let Some(analysis) = endgames.as_ref().and_then(|endgames| analyze_endgame(endgames, &board)) {
+                println!("{}", analysis);
+            }
+            // Let the engine move when it is its turn
+            if let Some(computer) = computer.as_mut().filter(|c| c.color == board.player()) {
+                println!("{} is thinking...", board.player());
+                let result = computer.engine.search(&game, computer.limits);
+                let mv = result.best_move.expect("The engine found no move."); // The game is not over
+                println!("{} plays {}\n\n", board.player(), game.variant().san(&board, mv));
+                game.play(mv);
+                continue 'game;
+            }
+            if let Some(draw) = game.claimable_draw() {
+                println!("{} may claim a draw by {}. Enter 'd' to claim it.", board.player(), draw);
+            }
+            println!("Select a piece to move by specifying the row then the column, separated by whitespace, or by naming its square (e.g. e2).");
+            if !board.legal_drops(None).is_empty() {
+                println!("Or drop a piece from your pocket, such as N@f3.");
+            }
+            println!("Or enter a whole move, such as e2e4 or Nf3. Then press enter:");
             //
             // Get the piece the current player wants to move
             let from: Square = 'validate_from: loop {
                 let err_msg: String = match parse_input() {
                     Some(Loc(from)) => match board.validate_from(from, None) {
                         Ok(()) => break 'validate_from from,
-                        Err(msg) => msg,
+                        // A square on its own may also be a Pawn advancing, e.g. e4
+                        Err(msg) if in_bounds(from) => match game.variant().parse_move(&board, &square_name(from)) {
+                            Ok(mv) => {
+                                game.play(mv);
+                                continue 'game;
+                            }
+                            Err(_) => msg.to_string(),
+                        },
+                        Err(msg) => msg.to_string(),
+                    },
+                    Some(MoveText(text)) => match game.variant().parse_move(&board, &text) {
+                        Ok(mv) => {
+                            game.play(mv);
+                            continue 'game;
+                        }
+                        Err(msg) => msg.to_string(),
                     },
                     Some(GoBack) => continue 'validate_from,
                     Some(Quit) => break 'main,
                     Some(Undo) => {
-                        board = history.pop().unwrap_or(board);
+                        undo(&mut game, &computer);
                         continue 'game;
                     }
                     Some(Random) => {
-                        history.push(board);
-                        let (from, to) = Board::random_move(&board);
-                        println!(
-                            "Moving {} to {} {}",
-                            board.get(from).unwrap(),
-                            to.0 + 1,
-                            to.1 + 1
-                        );
-                        board = board.execute_move(from, to);
+                        let mv = random_move(&game, &book);
+                        println!("{} plays {}", board.player(), game.variant().san(&board, mv));
+                        game.play(mv);
                         continue 'game;
                     }
-                    Some(Save(dest)) => match save_board(&board, &dest) {
+                    Some(Save(dest)) => match save_game(&game, &dest) {
                         Ok(()) => continue 'game,
                         Err(msg) => msg.to_string(),
                     },
-                    Some(Load(src)) => match load_board(&src) {
-                        Ok(b) => {
-                            board = b;
+                    Some(Load(src)) => match load_game(&src, game.variant()) {
+                        Ok(g) => {
+                            game = g;
                             continue 'game;
                         }
                         Err(msg) => msg.to_string(),
                     },
+                    Some(ClaimDraw) => match game.claimable_draw() {
+                        Some(draw) => break 'game Outcome::Drawn(draw),
+                        None => String::from("No draw can be claimed yet."),
+                    },
                     Some(Yes) | None => String::from("Input not received in proper format."),
                 };
                 println!("{} Try again please:", err_msg);
@@ -98,54 +229,91 @@ fn main() {
             let to: Square = 'validate_move: loop {
                 println!("Enter the square to which you would like to move this piece:");
                 let err_msg = match parse_input() {
-                    Some(Loc(to)) => match board.validate_move(from, to, None) {
+                    Some(Loc(to)) => match game.variant().validate_move(&board, from, to) {
                         Ok(()) => break 'validate_move to,
-                        Err(msg) => msg,
+                        Err(msg) => msg.to_string(),
+                    },
+                    Some(MoveText(text)) => match game.variant().parse_move(&board, &text) {
+                        Ok(mv) => {
+                            game.play(mv);
+                            continue 'game;
+                        }
+                        Err(msg) => msg.to_string(),
                     },
                     Some(GoBack) => continue 'game,
                     Some(Quit) => break 'main,
                     Some(Undo) => {
-                        board = history.pop().unwrap_or(board);
+                        undo(&mut game, &computer);
                         continue 'game;
                     }
                     Some(Random) => {
-                        history.push(board);
-                        let (from, to) = Board::random_move(&board);
-                        println!(
-                            "Moving {} to {} {}",
-                            board.get(from).unwrap(),
-                            to.0 + 1,
-                            to.1 + 1
-                        );
-                        board = board.execute_move(from, to);
+                        let mv = random_move(&game, &book);
+                        println!("{} plays {}", board.player(), game.variant().san(&board, mv));
+                        game.play(mv);
                         continue 'game;
                     }
-                    Some(Save(dest)) => match save_board(&board, &dest) {
+                    Some(Save(dest)) => match save_game(&game, &dest) {
                         Ok(()) => continue 'game,
                         Err(msg) => msg.to_string(),
                     },
-                    Some(Load(src)) => match load_board(&src) {
-                        Ok(b) => {
-                            board = b;
+                    Some(Load(src)) => match load_game(&src, game.variant()) {
+                        Ok(g) => {
+                            game = g;
                             continue 'game;
                         }
                         Err(msg) => msg.to_string(),
                     },
+                    Some(ClaimDraw) => match game.claimable_draw() {
+                        Some(draw) => break 'game Outcome::Drawn(draw),
+                        None => String::from("No draw can be claimed yet."),
+                    },
                     Some(Yes) | None => String::from("Input not received in proper format."),
                 };
                 println!("{} Try again please:", err_msg);
             };
             //
+            // Choose a piece to promote to if a Pawn reaches the opposite row
+            let promotion: Option<PieceType> = if board.is_promotion(from, to) {
+                println!("Promote your Pawn to a Queen (Q), Rook (R), Bishop (B) or Knight (N):");
+                loop {
+                    let err_msg = match parse_promotion() {
+                        Ok(piece_type) => {
+                            let mv = Move::new(&board, from, to, Some(piece_type));
+                            if game.variant().legal_moves(&board).contains(&mv) {
+                                break Some(piece_type);
+                            }
+                            MoveError::InvalidPromotion(piece_type).to_string()
+                        }
+                        Err(msg) => msg.to_string(),
+                    };
+                    println!("{} Try again please:", err_msg);
+                }
+            } else {
+                None
+            };
+            //
             // Execute the move
-            history.push(board);
-            board = board.execute_move(from, to);
+            game.play(Move::new(&board, from, to, promotion));
             // Make some space before the next move
             println!("\n\n");
         };
-        if let Some(winner) = winner {
-            println!("{} wins!\n{}", winner, board);
-        } else {
-            println!("Stalemate. Nobody wins.\n{}", board);
+        match outcome {
+            Outcome::Checkmate(winner) => println!("{} wins!\n{}", winner, game.board()),
+            Outcome::VariantWin(winner) => println!(
+                "{} wins by the rules of {}!\n{}",
+                winner,
+                game.variant().name(),
+                game.board()
+            ),
+            Outcome::Drawn(draw) => {
+                println!("Draw by {}. Nobody wins.\n{}", draw, game.board())
+            }
+        }
+        // Keep a record of the finished game
+        game.set_winner(outcome.winner());
+        match save_game(&game, "last_game.pgn") {
+            Ok(()) => println!("This game has been saved to last_game.pgn"),
+            Err(msg) => println!("{}", msg),
         }
         loop {
             println!("Play Again? Enter Yes (Y) or Quit (Q)");
@@ -159,10 +327,15 @@ fn main() {
     println!("Thanks for playing. Bye bye now!");
 }
 
-// Extract the row and column from a string containing two integers separated by whitespace
+// Extract the row and column from a string containing two integers separated by whitespace,
+// or from the name of a square such as e2
+// Anything else is taken to be a whole move in long algebraic notation or SAN
 // type 'r' to take a random turn
 // type 'b' to go back one step
 // type 'q' to quit the game
+// type 'd' to claim a draw by the fifty-move rule or threefold repetition
+// type 's FILE' or 'l FILE' to save or load the board. Files ending in .fen use FEN,
+//   and files ending in .pgn hold the whole game
 fn parse_input() -> Option<UserInput> {
     let stdin = io::stdin();
     let mut buffer = String::new();
@@ -182,28 +355,54 @@ fn parse_input() -> Option<UserInput> {
         Some(Yes)
     } else if buffer == "u" || buffer == "U" {
         Some(Undo)
+    } else if buffer == "d" || buffer == "D" {
+        Some(ClaimDraw)
     } else if buffer.starts_with("s") {
         Some(Save(String::from(buffer.trim_start_matches('s').trim())))
     } else if buffer.starts_with("l") {
         Some(Load(String::from(buffer.trim_start_matches('l').trim())))
     } else {
         let mut iter = buffer.split_whitespace();
-        let row: Option<isize> = iter.next().map(|row_str| row_str.parse().ok()).flatten();
-        let col: Option<isize> = iter.next().map(|col_str| col_str.parse().ok()).flatten();
+        let row: Option<isize> = iter.next().and_then(|row_str| row_str.parse().ok());
+        let col: Option<isize> = iter.next().and_then(|col_str| col_str.parse().ok());
         if let (Some(row), Some(col), None) = (row, col, iter.next()) {
             // Subtract 1 to zero-index
             Some(Loc((row - 1, col - 1)))
+        } else if let Ok(loc) = parse_square(buffer) {
+            Some(Loc(loc))
+        } else if !buffer.is_empty() {
+            Some(MoveText(String::from(buffer)))
         } else {
             None
         }
     }
 }
 
-// Write the current board to a file
+// Read the type of piece a Pawn is promoted to, given by its name or letter
+fn parse_promotion() -> Result<PieceType, ParseError> {
+    let stdin = io::stdin();
+    let mut buffer = String::new();
+    stdin
+        .lock()
+        .read_line(&mut buffer) // Read a line from stdin
+        .expect("Stdin not working properly.");
+    buffer.parse()
+}
+
+// Write the current board to a file, in FEN if the file name ends with .fen
 fn save_board(board: &Board, file_name: &str) -> io::Result<()> {
     let mut buffer = File::create(file_name)?;
+    if file_name.ends_with(".fen") {
+        return writeln!(buffer, "{}", board.to_fen());
+    }
 
-    write!(buffer, "{}\n{}\n", board.player(), board.turn())?;
+    write!(
+        buffer,
+        "{}\n{} {}\n",
+        board.player(),
+        board.turn(),
+        board.halfmove_clock()
+    )?;
     for row in 0..8 {
         for col in 0..8 {
             write!(
@@ -211,16 +410,279 @@ fn save_board(board: &Board, file_name: &str) -> io::Result<()> {
                 "{}",
                 match board.get((row, col)) {
                     Some(p) => format!(" {} ", p),
-                    None => format!(" _ "),
+                    None => " _ ".to_string(),
                 }
             )?;
         }
         // Add a new line for each row
-        write!(buffer, "\n")?;
+        writeln!(buffer)?;
     }
     Ok(())
 }
 
-fn load_board(file_name: &str) -> Result<Board, String> {
-    fs::read_to_string(file_name).map_err(|err| err.to_string())?.parse()
+// Write the whole game to a file as PGN if the file name ends with .pgn,
+// otherwise write only the current board
+fn save_game(game: &Game, file_name: &str) -> io::Result<()> {
+    if file_name.ends_with(".pgn") {
+        fs::write(file_name, game.to_pgn())
+    } else {
+        save_board(&game.board(), file_name)
+    }
+}
+
+// Read a game from a PGN file, or start a new game from a saved board
+fn load_game(file_name: &str, variant: &'static dyn Variant) -> Result<Game, Box<dyn Error>> {
+    if file_name.ends_with(".pgn") {
+        Ok(Game::from_pgn(&fs::read_to_string(file_name)?)?)
+    } else {
+        load_board(file_name, variant).map(|board| Game::with_variant(variant, board))
+    }
+}
+
+// Read a board of VARIANT from a file, in FEN if the file name ends with .fen
+fn load_board(file_name: &str, variant: &dyn Variant) -> Result<Board, Box<dyn Error>> {
+    let contents = fs::read_to_string(file_name)?;
+    if file_name.ends_with(".fen") {
+        Ok(variant.parse_fen(contents.trim())?)
+    } else {
+        Ok(contents.parse()?)
+    }
+}
+
+// Read which color the engine plays, if any, how long it thinks for, its table size,
+// the opening book, the endgame tablebases and the endgame tables, the starting position
+// and the variant
+fn parse_options(args: &[String]) -> Result<Options, Box<dyn Error>> {
+    let mut color = None;
+    let mut limits = SearchLimits::time(Duration::from_secs(3));
+    let mut engine = Engine::new();
+    let mut book = None;
+    let mut choice = BookChoice::Weighted;
+    let mut tablebase = None;
+    let mut endgames = None;
+    let mut start = Start::Standard;
+    let mut variant: &'static dyn Variant = &Standard;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value.", arg));
+        match arg.as_str() {
+            // The engine takes the other side to the player
+            "--play" => match value()?.to_lowercase().as_str() {
+                "white" | "w" => color = Some(Black),
+                "black" | "b" => color = Some(White),
+                other => return Err(format!("{} is not a color.", other).into()),
+            },
+            "--depth" => limits = SearchLimits::depth(value()?.parse()?),
+            "--time" => {
+                limits = SearchLimits::time(Duration::try_from_secs_f64(value()?.parse()?)?)
+            }
+            "--hash" => engine.set_table_size(value()?.parse()?),
+            "--book" => book = Some(load_book(value()?)?),
+            "--book-choice" => match value()?.to_lowercase().as_str() {
+                "best" => choice = BookChoice::Best,
+                "weighted" => choice = BookChoice::Weighted,
+                other => return Err(format!("{} is not a way to choose book moves.", other).into()),
+            },
+            "--syzygy" => tablebase = Some(Tablebase::open(value()?)?),
+            "--chess960" => match value()?.to_lowercase().as_str() {
+                "random" => start = Start::RandomChess960,
+                index => match index.parse() {
+                    Ok(index) if index < 960 => start = Start::Chess960(index),
+                    _ => return Err(format!("{} is not a number from 0 to 959.", index).into()),
+                },
+            },
+            "--endgames" => endgames = Some(EndgameTables::from_bytes(&fs::read(value()?)?)?),
+            "--variant" => {
+                let name = value()?;
+                variant = find_variant(name).ok_or_else(|| {
+                    let names: Vec<&str> = VARIANTS.iter().map(|variant| variant.name()).collect();
+                    format!("{} is not a variant. Choose from {}.", name, names.join(", "))
+                })?;
+            }
+            other => return Err(format!("Unknown option {}", other).into()),
+        }
+    }
+    // The engine, books and tables only know the rules of standard chess
+    if variant.name() != Standard.name() {
+        let standard_only = [
+            ("--play", color.is_some()),
+            ("--chess960", !matches!(start, Start::Standard)),
+            ("--book", book.is_some()),
+            ("--syzygy", tablebase.is_some()),
+            ("--endgames", endgames.is_some()),
+        ];
+        if let Some((option, _)) = standard_only.iter().find(|(_, given)| *given) {
+            return Err(format!("{} can only be used with standard chess.", option).into());
+        }
+    }
+    engine.set_book(book.clone(), choice);
+    engine.set_endgame_tables(endgames.clone());
+    // The engine and the analysis each keep the tables they load
+    engine.set_tablebase(tablebase.clone());
+    Ok(Options {
+        start,
+        variant,
+        computer: color.map(|color| Computer {
+            color,
+            engine,
+            limits,
+        }),
+        book: book.map(|book| (book, choice)),
+        tablebase,
+        endgames,
+    })
+}
+
+// What the tablebase says about BOARD and the move that keeps the best result,
+// or None if it doesn't cover the position
+fn analyze(tablebase: &mut Tablebase, board: &Board) -> Option<String> {
+    let (player, other) = (board.player(), board.player().other());
+    let wdl = tablebase.probe_wdl(board)?;
+    let dtz = tablebase.probe_dtz(board)?.abs();
+    let result = match wdl {
+        Wdl::Win => format!("{} wins, reaching a capture or Pawn move in {} plies", player, dtz),
+        Wdl::CursedWin => format!("{} wins, but not within the fifty move rule", player),
+        Wdl::Draw => String::from("Drawn"),
+        Wdl::BlessedLoss => format!("{} wins, but not within the fifty move rule", other),
+        Wdl::Loss => format!("{} wins, reaching a capture or Pawn move in {} plies", other, dtz),
+    };
+    // Ranked as the engine ranks them, so wins the fifty move rule would spoil come after the rest
+    let clock = board.halfmove_clock() as i32;
+    let best = tablebase
+        .root_moves(board)?
+        .into_iter()
+        .max_by_key(|&(_, dtz)| tablebase_rank(dtz, clock))
+        .map(|(mv, _)| format!(" Best move: {}", board.san(mv)));
+    Some(format!("Tablebase: {}.{}", result, best.unwrap_or_default()))
+}
+
+// How soon BOARD is mated according to the endgame tables and the move that mates
+// soonest or holds out longest, or None if they don't cover the position
+fn analyze_endgame(endgames: &EndgameTables, board: &Board) -> Option<String> {
+    let (player, other) = (board.player(), board.player().other());
+    let (mv, dtm) = endgames.best_move(board)?;
+    let result = match dtm {
+        Dtm::Mate(plies) => format!("{} mates in {} moves", player, plies.div_ceil(2)),
+        Dtm::Mated(plies) => format!("{} mates in {} moves", other, plies / 2),
+        Dtm::Draw => String::from("Drawn"),
+    };
+    Some(format!("Endgame table: {}. Best move: {}", result, board.san(mv)))
+}
+
+// Read a Polyglot opening book
+fn load_book(file_name: &str) -> Result<Book, Box<dyn Error>> {
+    Ok(Book::from_bytes(&fs::read(file_name)?, ZobristKeys::default())?)
+}
+
+// A move from the book if it has one for the current position, otherwise any legal move
+fn random_move(game: &Game, book: &Option<(Book, BookChoice)>) -> Move {
+    let board = game.board();
+    book.as_ref()
+        .and_then(|(book, choice)| book.choose(&board, *choice, rand::random()))
+        .unwrap_or_else(|| {
+            let moves = game.variant().legal_moves(&board);
+            *moves.choose(&mut rand::thread_rng()).expect("No valid moves.") // The game is not over
+        })
+}
+
+// Take back the last move, and the engine's reply so that it is the player's turn again
+fn undo(game: &mut Game, computer: &Option<Computer>) {
+    game.undo();
+    if let Some(computer) = computer {
+        if computer.color == game.board().player() {
+            game.undo();
+        }
+    }
+}
+
+// Count the positions DEPTH moves deep from the start or from the given FEN,
+// listing the count after each first move
+fn perft(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let depth: u32 = match args.first() {
+        Some(depth) => depth.parse()?,
+        None => return Err("No depth given.".into()),
+    };
+    let board = match args.get(1..) {
+        Some(fen) if !fen.is_empty() => Board::from_fen(&fen.join(" "))?,
+        _ => Board::new(),
+    };
+    let start = Instant::now();
+    let nodes = if depth == 0 {
+        1
+    } else {
+        let divided = board.divide(depth);
+        for (mv, count) in &divided {
+            println!("{}: {}", mv, count);
+        }
+        println!();
+        divided.iter().map(|(_, count)| count).sum()
+    };
+    let elapsed = start.elapsed().as_secs_f64();
+    println!("Nodes searched: {}", nodes);
+    println!(
+        "Time: {:.3} s, {:.0} nodes per second",
+        elapsed,
+        nodes as f64 / elapsed
+    );
+    Ok(())
+}
+
+// Build an opening book from the first moves of every game in a PGN file
+fn build_book(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (pgn_file, book_file) = match args {
+        [pgn_file, book_file, ..] => (pgn_file, book_file),
+        _ => return Err("Expected a PGN file and a book file.".into()),
+    };
+    let plies = match args.get(2) {
+        Some(plies) => plies.parse()?,
+        None => DEFAULT_BOOK_PLIES,
+    };
+    let games = Game::parse_pgn_games(&fs::read_to_string(pgn_file)?)?;
+    let book = Book::from_games(&games, ZobristKeys::default(), plies);
+    fs::write(book_file, book.to_bytes())?;
+    println!("Wrote {} moves from {} games to {}", book.len(), games.len(), book_file);
+    Ok(())
+}
+
+// Work out the endgame tables for each MATERIAL, such as KRvK, and those they need,
+// adding them to the tables already in FILE
+fn build_endgames(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (file_name, materials) = match args {
+        [file_name, materials @ ..] if !materials.is_empty() => (file_name, materials),
+        _ => return Err("Expected a file and the pieces of at least one ending.".into()),
+    };
+    let mut endgames = match fs::read(file_name) {
+        Ok(bytes) => EndgameTables::from_bytes(&bytes)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => EndgameTables::new(),
+        Err(err) => return Err(err.into()),
+    };
+    for material in materials {
+        endgames.generate(material)?;
+    }
+    fs::write(file_name, endgames.to_bytes())?;
+    println!("Wrote the tables for {} to {}", endgames.names().join(", "), file_name);
+    Ok(())
+}
+
+// Today's date in the YYYY.MM.DD form used by PGN
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs()) as i64;
+    // Convert days since 1970-01-01 to a civil date
+    let days = secs / 86400 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
 }
@@ -0,0 +1,365 @@
+// Jack Alpert 2020
+
+// The Universal Chess Interface, used by chess GUIs and tournament managers to talk
+// to the engine over stdin and stdout. Part of the binary, not the library.
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chess::*;
+
+// Moves left until the next time control, if the GUI doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// Time kept back from each move for the GUI and the operating system, in milliseconds
+const DEFAULT_MOVE_OVERHEAD: u64 = 10;
+
+/**
+The state of the engine between commands. A search runs on its own thread, which
+owns the Engine until it finishes, so commands such as stop can be handled meanwhile.
+*/
+pub struct Uci<W: Write + Send + 'static> {
+    game: Game,
+    engine: Option<Engine>, // None while searching
+    search: Option<JoinHandle<Engine>>,
+    stop: Arc<AtomicBool>,
+    out: Arc<Mutex<W>>,
+    move_overhead: Duration,
+    chess960: bool, // Castling is sent as the King taking its own Rook
+}
+
+/** Speak UCI over stdin and stdout until the GUI quits */
+pub fn run() {
+    let mut uci = Uci::new(io::stdout());
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(line) if uci.handle(&line) => continue,
+            _ => break,
+        }
+    }
+    uci.wait();
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    pub fn new(out: W) -> Uci<W> {
+        let engine = Engine::new();
+        Uci {
+            game: Game::new(),
+            stop: engine.stop_flag(),
+            engine: Some(engine),
+            search: None,
+            out: Arc::new(Mutex::new(out)),
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+            chess960: false,
+        }
+    }
+
+    /** Carry out one command from the GUI. Returns false once the GUI quits. */
+    pub fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["uci", ..] => {
+                self.send(&format!("id name {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+                self.send("id author Jack Alpert");
+                self.send(&format!(
+                    "option name Hash type spin default {} min 1 max 4096",
+                    TranspositionTable::DEFAULT_MEGABYTES
+                ));
+                self.send("option name Book File type string default <empty>");
+                self.send("option name SyzygyPath type string default <empty>");
+                self.send("option name Endgame File type string default <empty>");
+                self.send(&format!(
+                    "option name Move Overhead type spin default {} min 0 max 5000",
+                    DEFAULT_MOVE_OVERHEAD
+                ));
+                self.send("option name UCI_Chess960 type check default false");
+                self.send("uciok");
+            }
+            ["isready", ..] => self.send("readyok"),
+            ["ucinewgame", ..] => {
+                self.wait();
+                self.game = Game::new();
+                if let Some(engine) = self.engine.as_mut() {
+                    engine.clear();
+                }
+            }
+            ["position", args @ ..] => match parse_position(args, self.chess960) {
+                Ok(game) => self.game = game,
+                Err(msg) => self.send(&format!("info string {}", msg)),
+            },
+            ["go", args @ ..] => self.go(args),
+            ["stop", ..] => self.wait(),
+            ["setoption", args @ ..] => self.set_option(args),
+            ["quit", ..] => return false,
+            // Unknown commands are ignored, as the protocol asks
+            _ => (),
+        }
+        true
+    }
+
+    // Start searching the current position on another thread
+    fn go(&mut self, args: &[&str]) {
+        self.wait();
+        let (limits, infinite) = parse_go(args, self.game.board().player(), self.move_overhead);
+        let mut engine = self.engine.take().expect("The engine is still searching."); // Coder error
+        let game = self.game.clone();
+        let out = self.out.clone();
+        let stop = self.stop.clone();
+        stop.store(false, Ordering::Relaxed);
+        self.search = Some(thread::spawn(move || {
+            let start = Instant::now();
+            let result = engine.search_with(&game, limits, |result| {
+                send(&out, &info(result, start.elapsed()));
+            });
+            // An infinite search only reports its move once told to stop
+            while infinite && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            let best_move = result
+                .best_move
+                .map_or(String::from("0000"), |mv| mv.to_string());
+            send(&out, &format!("bestmove {}", best_move));
+            engine
+        }));
+    }
+
+    /** Stop any search in progress and wait for it to report its move */
+    pub fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            self.engine = Some(search.join().expect("The search panicked."));
+        }
+    }
+
+    // setoption name NAME [value VALUE]. Names may contain spaces.
+    fn set_option(&mut self, args: &[&str]) {
+        let value_at = args.iter().position(|&word| word == "value");
+        let name = args[..value_at.unwrap_or(args.len())]
+            .iter()
+            .skip_while(|&&word| word == "name")
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let value = value_at.map(|i| args[i + 1..].join(" "));
+        match (name.to_lowercase().as_str(), value) {
+            ("hash", Some(value)) => match value.parse() {
+                Ok(megabytes) => {
+                    self.wait();
+                    if let Some(engine) = self.engine.as_mut() {
+                        engine.set_table_size(megabytes);
+                    }
+                }
+                Err(_) => self.send(&format!("info string {} is not a number", value)),
+            },
+            ("book file", value) => {
+                let book = match value.as_deref() {
+                    None | Some("") | Some("<empty>") => None,
+                    Some(file_name) => match std::fs::read(file_name) {
+                        Ok(bytes) => match Book::from_bytes(&bytes, ZobristKeys::default()) {
+                            Ok(book) => Some(book),
+                            Err(err) => return self.send(&format!("info string {}", err)),
+                        },
+                        Err(err) => return self.send(&format!("info string {}", err)),
+                    },
+                };
+                self.wait();
+                if let Some(engine) = self.engine.as_mut() {
+                    engine.set_book(book, BookChoice::Weighted);
+                }
+            }
+            ("syzygypath", value) => {
+                let tablebase = match value.as_deref() {
+                    None | Some("") | Some("<empty>") => None,
+                    Some(dirs) => match Tablebase::open(dirs) {
+                        Ok(tablebase) => Some(tablebase),
+                        Err(err) => return self.send(&format!("info string {}", err)),
+                    },
+                };
+                if let Some(tablebase) = tablebase.as_ref() {
+                    self.send(&format!(
+                        "info string Found tables of up to {} pieces",
+                        tablebase.max_pieces()
+                    ));
+                }
+                self.wait();
+                if let Some(engine) = self.engine.as_mut() {
+                    engine.set_tablebase(tablebase);
+                }
+            }
+            ("endgame file", value) => {
+                let endgames = match value.as_deref() {
+                    None | Some("") | Some("<empty>") => None,
+                    Some(file_name) => match std::fs::read(file_name) {
+                        Ok(bytes) => match EndgameTables::from_bytes(&bytes) {
+                            Ok(endgames) => Some(endgames),
+                            Err(err) => return self.send(&format!("info string {}", err)),
+                        },
+                        Err(err) => return self.send(&format!("info string {}", err)),
+                    },
+                };
+                self.wait();
+                if let Some(engine) = self.engine.as_mut() {
+                    engine.set_endgame_tables(endgames);
+                }
+            }
+            ("uci_chess960", Some(value)) => match value.parse() {
+                Ok(chess960) => self.chess960 = chess960,
+                Err(_) => self.send(&format!("info string {} is not true or false", value)),
+            },
+            ("move overhead", Some(value)) => match value.parse() {
+                Ok(millis) => self.move_overhead = Duration::from_millis(millis),
+                Err(_) => self.send(&format!("info string {} is not a number", value)),
+            },
+            _ => self.send(&format!("info string Unknown option {}", name)),
+        }
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+}
+
+fn send<W: Write>(out: &Mutex<W>, line: &str) {
+    let mut out = out.lock().unwrap();
+    // There is nobody to tell if the GUI has gone away
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+// The info line describing one iteration of the search
+fn info(result: &SearchResult, elapsed: Duration) -> String {
+    let score = match mate_in(result.score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", result.score),
+    };
+    let millis = elapsed.as_millis().max(1);
+    let pv: Vec<String> = result.pv.iter().map(|mv| mv.to_string()).collect();
+    format!(
+        "info depth {} score {} nodes {} nps {} time {} pv {}",
+        result.depth,
+        score,
+        result.nodes,
+        result.nodes as u128 * 1000 / millis,
+        millis,
+        pv.join(" ")
+    )
+}
+
+// position (startpos | fen FEN) [moves MOVE...]
+// In CHESS960 castling is the King taking its own Rook, even from the standard start.
+fn parse_position(args: &[&str], chess960: bool) -> Result<Game, String> {
+    let moves_at = args.iter().position(|&word| word == "moves").unwrap_or(args.len());
+    let mut board = match &args[..moves_at] {
+        ["startpos"] => Board::new(),
+        ["fen", fen @ ..] => Board::from_fen(&fen.join(" ")).map_err(|err| err.to_string())?,
+        _ => return Err(String::from("Expected startpos or fen")),
+    };
+    if chess960 {
+        board.set_chess960(true);
+    }
+    let mut game = Game::from_board(board);
+    for text in args.iter().skip(moves_at + 1) {
+        let mv = game.board().parse_uci(text).map_err(|err| err.to_string())?;
+        game.play(mv);
+    }
+    Ok(game)
+}
+
+// The limits given to go, and whether the search is infinite. PLAYER is the one to move.
+fn parse_go(args: &[&str], player: Color, move_overhead: Duration) -> (SearchLimits, bool) {
+    let mut limits = SearchLimits::default();
+    let mut infinite = false;
+    let (mut time_left, mut increment, mut moves_to_go) = (None, 0, DEFAULT_MOVES_TO_GO);
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let mut number = || args.next().and_then(|value| value.parse::<u64>().ok());
+        match (arg, player) {
+            ("depth", _) => limits.depth = number().map(|depth| depth as u32),
+            ("nodes", _) => limits.nodes = number(),
+            ("movetime", _) => limits.time = number().map(Duration::from_millis),
+            ("wtime", White) | ("btime", Black) => time_left = number(),
+            ("winc", White) | ("binc", Black) => increment = number().unwrap_or(0),
+            ("movestogo", _) => moves_to_go = number().map_or(moves_to_go, |n| n.max(1) as u32),
+            ("infinite", _) => infinite = true,
+            _ => (),
+        }
+    }
+    // Spend an even share of the clock on each move, and most of the increment
+    if let (Some(time_left), None) = (time_left, limits.time) {
+        let share = time_left / moves_to_go as u64 + increment * 3 / 4;
+        let most = time_left.saturating_sub(move_overhead.as_millis() as u64);
+        limits.time = Some(Duration::from_millis(share.min(most).max(1)));
+    }
+    (limits, infinite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run COMMANDS, waiting for any search to finish, and return every line sent back
+    fn run_commands(commands: &[&str]) -> Vec<String> {
+        let mut uci = Uci::new(Vec::new());
+        for command in commands {
+            uci.handle(command);
+        }
+        if let Some(search) = uci.search.take() {
+            search.join().unwrap();
+        }
+        let out = uci.out.lock().unwrap();
+        String::from_utf8(out.clone())
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+    #[test]
+    fn test_handshake() {
+        let lines = run_commands(&["uci", "setoption name Hash value 1", "isready"]);
+        assert!(lines[0].starts_with("id name"));
+        assert_eq!(lines[lines.len() - 2], "uciok");
+        assert_eq!(lines[lines.len() - 1], "readyok");
+    }
+    #[test]
+    fn test_position() {
+        let game = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"], false).unwrap();
+        assert_eq!(game.moves().len(), 3);
+        assert_eq!(game.board().player(), Black);
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        let mut args = vec!["fen"];
+        args.extend(fen.split(' '));
+        args.extend(&["moves", "e1g1"]);
+        let game = parse_position(&args, false).unwrap();
+        assert_eq!(game.board().to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        assert!(parse_position(&["startpos", "moves", "e2e5"], false).is_err());
+        // In Chess960 the same castling is the King taking the Rook
+        assert!(parse_position(&args, true).is_err());
+        args.pop();
+        args.push("e1h1");
+        let game = parse_position(&args, true).unwrap();
+        assert_eq!(game.board().to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+    }
+    #[test]
+    fn test_go_limits() {
+        let overhead = Duration::from_millis(10);
+        let (limits, infinite) = parse_go(&["depth", "5"], White, overhead);
+        assert_eq!(limits, SearchLimits::depth(5));
+        assert!(!infinite);
+        let (limits, _) = parse_go(&["wtime", "60000", "btime", "1000", "winc", "1000"], White, overhead);
+        assert_eq!(limits.time, Some(Duration::from_millis(2750)));
+        let (limits, _) = parse_go(&["wtime", "60000", "btime", "1000", "movestogo", "1"], Black, overhead);
+        assert_eq!(limits.time, Some(Duration::from_millis(990)));
+        let (limits, infinite) = parse_go(&["infinite"], White, overhead);
+        assert!(infinite && limits == SearchLimits::default());
+    }
+    #[test]
+    fn test_search() {
+        let lines = run_commands(&["position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "go depth 2"]);
+        assert!(lines[0].starts_with("info depth 1"));
+        assert!(lines.iter().any(|line| line.contains("score mate 1")));
+        assert_eq!(lines.last().unwrap(), "bestmove a1a8");
+    }
+}